@@ -58,6 +58,7 @@ if [[ $REPLY =~ ^[Yy]$ ]]; then
     <key>ProgramArguments</key>
     <array>
         <string>$INSTALL_DIR/$APP_NAME.app/Contents/MacOS/locus</string>
+        <string>--headless</string>
     </array>
     
     <key>RunAtLoad</key>
@@ -0,0 +1,431 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::browser;
+use crate::model::ActiveWindow;
+use crate::rules::{Productivity, RulesState};
+use crate::storage::Storage;
+use crate::titles::{TitleFields, TitleParsers};
+
+/// Mirrors the frontend `SessionHistory` metadata; `session_started_on` is in
+/// unix milliseconds.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Session {
+    pub id: String,
+    pub pomodoro_length_in_seconds: i64,
+    pub break_length_in_seconds: i64,
+    pub session_started_on: i64,
+    /// Focus phases the timer was set to run; unknown for sessions the
+    /// frontend saved or imported.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub number_of_sessions: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum SegmentState {
+    #[default]
+    Active,
+    /// Nobody was at the keyboard. AFK segments have an empty class and title.
+    Afk,
+}
+
+impl SegmentState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SegmentState::Active => "active",
+            SegmentState::Afk => "afk",
+        }
+    }
+}
+
+impl FromStr for SegmentState {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "active" => Ok(SegmentState::Active),
+            "afk" => Ok(SegmentState::Afk),
+            other => Err(format!("Unknown segment state `{}`", other)),
+        }
+    }
+}
+
+/// A stretch of time spent in one window, or away, in unix milliseconds.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ActivitySegment {
+    pub session_id: String,
+    pub class: String,
+    pub title: String,
+    pub start: i64,
+    pub end: i64,
+    #[serde(default)]
+    pub state: SegmentState,
+    /// Filled in by the rules engine; `None` until the segment is classified.
+    #[serde(default)]
+    pub category: Option<String>,
+    #[serde(default)]
+    pub productivity: Option<Productivity>,
+    /// What the title says about the project, file or conversation, and the
+    /// browser tab's address.
+    #[serde(flatten)]
+    pub fields: TitleFields,
+    /// The site `fields.url` groups under.
+    #[serde(default)]
+    pub domain: Option<String>,
+    /// The title before normalization, as first seen in this segment.
+    #[serde(default)]
+    pub raw_title: Option<String>,
+}
+
+pub fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+/// An active, unclassified segment in session `"session"`, for tests to
+/// adjust with the `with_*` methods.
+#[cfg(test)]
+pub fn segment(class: &str, title: &str, start: i64, end: i64) -> ActivitySegment {
+    ActivitySegment {
+        session_id: "session".to_string(),
+        class: class.to_string(),
+        title: title.to_string(),
+        start,
+        end,
+        state: SegmentState::Active,
+        category: None,
+        productivity: None,
+        fields: TitleFields::default(),
+        domain: None,
+        raw_title: None,
+    }
+}
+
+/// Time away, as tests write it.
+#[cfg(test)]
+pub fn afk(start: i64, end: i64) -> ActivitySegment {
+    segment("", "", start, end).with_state(SegmentState::Afk)
+}
+
+#[cfg(test)]
+impl ActivitySegment {
+    pub fn with_session(self, session_id: &str) -> Self {
+        ActivitySegment {
+            session_id: session_id.to_string(),
+            ..self
+        }
+    }
+
+    pub fn with_state(self, state: SegmentState) -> Self {
+        ActivitySegment { state, ..self }
+    }
+
+    pub fn with_category(self, category: &str) -> Self {
+        ActivitySegment {
+            category: Some(category.to_string()),
+            ..self
+        }
+    }
+
+    pub fn with_productivity(self, productivity: Productivity) -> Self {
+        ActivitySegment {
+            productivity: Some(productivity),
+            ..self
+        }
+    }
+}
+
+/// Turns a stream of window changes and idle transitions into closed segments.
+#[derive(Debug)]
+pub struct SegmentBuilder {
+    session_id: String,
+    open: Option<(ActiveWindow, SegmentState, i64)>,
+}
+
+impl SegmentBuilder {
+    pub fn new(session_id: String) -> Self {
+        SegmentBuilder {
+            session_id,
+            open: None,
+        }
+    }
+
+    /// Starts tracking `window` at `at` and returns the segment it replaces.
+    /// The "none" window is a gap, not a segment.
+    pub fn observe(&mut self, window: ActiveWindow, at: i64) -> Option<ActivitySegment> {
+        let closed = self.finish(at);
+
+        if window != ActiveWindow::none() {
+            self.open = Some((window, SegmentState::Active, at));
+        }
+
+        closed
+    }
+
+    /// Opens an AFK segment from `since`, closing the open window there.
+    ///
+    /// Idle time is only noticed after the threshold, so `since` is usually in
+    /// the past; it is clamped so segments never overlap.
+    pub fn idle_start(&mut self, since: i64) -> Option<ActivitySegment> {
+        let since = match &self.open {
+            Some((_, _, start)) => since.max(*start),
+            None => since,
+        };
+        let closed = self.finish(since);
+        self.open = Some((afk_window(), SegmentState::Afk, since));
+
+        closed
+    }
+
+    /// Closes the open segment, if any, at `at`.
+    pub fn finish(&mut self, at: i64) -> Option<ActivitySegment> {
+        self.open
+            .take()
+            .filter(|(_, _, start)| at > *start)
+            .map(|(window, state, start)| self.segment(window, state, start, at))
+    }
+
+    /// The open segment as if it closed at `at`, leaving it open.
+    pub fn peek(&self, at: i64) -> Option<ActivitySegment> {
+        self.open
+            .as_ref()
+            .filter(|(_, _, start)| at > *start)
+            .map(|(window, state, start)| self.segment(window.clone(), *state, *start, at))
+    }
+
+    fn segment(
+        &self,
+        window: ActiveWindow,
+        state: SegmentState,
+        start: i64,
+        end: i64,
+    ) -> ActivitySegment {
+        ActivitySegment {
+            session_id: self.session_id.clone(),
+            class: window.class,
+            title: window.title,
+            start,
+            end,
+            state,
+            category: None,
+            productivity: None,
+            domain: window.url.as_deref().and_then(browser::domain),
+            raw_title: window.raw_title,
+            fields: TitleFields {
+                url: window.url,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+fn afk_window() -> ActiveWindow {
+    ActiveWindow {
+        class: String::new(),
+        title: String::new(),
+        url: None,
+        raw_title: None,
+    }
+}
+
+/// Feeds window changes from the stream loop into storage, parsing each
+/// segment's title and classifying it with the current rules as it is
+/// written.
+///
+/// The open segment is saved at each `checkpoint` and its end moved forward
+/// at the next, so a crash or a kill loses at most one checkpoint of it.
+pub struct ActivityRecorder {
+    builder: SegmentBuilder,
+    storage: Arc<Storage>,
+    rules: RulesState,
+    titles: TitleParsers,
+    /// Row of the open segment, once a checkpoint saved it.
+    open_row: Option<i64>,
+}
+
+impl ActivityRecorder {
+    pub fn new(session_id: String, storage: Arc<Storage>, rules: RulesState) -> Self {
+        ActivityRecorder {
+            builder: SegmentBuilder::new(session_id),
+            storage,
+            rules,
+            titles: TitleParsers::default(),
+            open_row: None,
+        }
+    }
+
+    pub fn observe(&mut self, window: &ActiveWindow) {
+        let closed = self.builder.observe(window.clone(), now_millis());
+        self.write(closed);
+    }
+
+    /// The user went away `idle_for` ago.
+    pub fn idle_start(&mut self, idle_for: Duration) {
+        let since = now_millis() - idle_for.as_millis() as i64;
+        let closed = self.builder.idle_start(since);
+        self.write(closed);
+    }
+
+    /// The user is back. The stream observes the current window right after,
+    /// which opens the next segment.
+    pub fn idle_end(&mut self) {
+        let closed = self.builder.finish(now_millis());
+        self.write(closed);
+    }
+
+    pub fn finish(&mut self) {
+        let closed = self.builder.finish(now_millis());
+        self.write(closed);
+    }
+
+    /// Saves the open segment as it stands now.
+    pub fn checkpoint(&mut self) {
+        self.checkpoint_at(now_millis());
+    }
+
+    fn checkpoint_at(&mut self, at: i64) {
+        let Some(segment) = self.builder.peek(at) else {
+            return;
+        };
+
+        let saved = match self.open_row {
+            Some(id) => self.storage.set_segment_end(id, segment.end),
+            None => self
+                .storage
+                .append_segment(&self.classify(segment))
+                .map(|id| self.open_row = Some(id)),
+        };
+        if let Err(e) = saved {
+            tracing::error!(error = %e, "Unable to checkpoint the open activity segment");
+        }
+    }
+
+    /// Stores the segment the builder just closed, or drops the checkpointed
+    /// one if idle time swallowed it whole.
+    fn write(&mut self, segment: Option<ActivitySegment>) {
+        let written = match (segment, self.open_row.take()) {
+            (Some(segment), Some(id)) => self.storage.set_segment_end(id, segment.end),
+            (Some(segment), None) => self
+                .storage
+                .append_segment(&self.classify(segment))
+                .map(|_| ()),
+            (None, Some(id)) => self.storage.delete_segment(id),
+            (None, None) => Ok(()),
+        };
+
+        if let Err(e) = written {
+            tracing::error!(error = %e, "Unable to write an activity segment");
+        }
+    }
+
+    fn classify(&self, mut segment: ActivitySegment) -> ActivitySegment {
+        // AFK time has no window to parse or classify
+        if segment.state == SegmentState::Active {
+            let parsed = self.titles.parse(&segment.class, &segment.title);
+            segment.fields = segment.fields.or(parsed);
+        }
+        if let (SegmentState::Active, Ok(rules)) = (segment.state, self.rules.read()) {
+            let classification = rules.classify(&segment.class, &segment.title);
+            segment.category = Some(classification.category);
+            segment.productivity = Some(classification.productivity);
+        }
+
+        segment
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(class: &str, title: &str) -> ActiveWindow {
+        ActiveWindow {
+            class: class.to_string(),
+            title: title.to_string(),
+            url: None,
+            raw_title: None,
+        }
+    }
+
+    #[test]
+    fn builder_closes_segments_on_change_and_skips_gaps() {
+        let mut builder = SegmentBuilder::new("session".to_string());
+
+        assert_eq!(builder.observe(window("Code", "main.rs"), 0), None);
+        assert_eq!(
+            builder.observe(ActiveWindow::none(), 1_000),
+            Some(segment("Code", "main.rs", 0, 1_000))
+        );
+        assert_eq!(builder.observe(window("Slack", "general"), 4_000), None);
+        assert_eq!(
+            builder.finish(6_000),
+            Some(segment("Slack", "general", 4_000, 6_000))
+        );
+        assert_eq!(builder.finish(7_000), None);
+    }
+
+    #[test]
+    fn idle_time_is_split_into_an_afk_segment() {
+        let mut builder = SegmentBuilder::new("session".to_string());
+
+        builder.observe(window("Slack", "general"), 0);
+        assert_eq!(
+            builder.idle_start(2_000),
+            Some(segment("Slack", "general", 0, 2_000))
+        );
+        assert_eq!(builder.finish(9_000), Some(afk(2_000, 9_000)));
+
+        // Idle noticed late, after a window that opened on its own
+        builder.observe(window("Mail", "Inbox"), 10_000);
+        assert_eq!(builder.idle_start(8_000), None);
+        assert_eq!(
+            builder.observe(window("Mail", "Inbox"), 12_000),
+            Some(afk(10_000, 12_000))
+        );
+    }
+
+    #[test]
+    fn recorder_checkpoints_the_open_segment_in_place() {
+        let storage = Arc::new(Storage::open_in_memory().unwrap());
+        let mut recorder = ActivityRecorder::new(
+            "session".to_string(),
+            storage.clone(),
+            Arc::new(std::sync::RwLock::new(Default::default())),
+        );
+        let stored = || {
+            storage
+                .query_segments(&Default::default())
+                .unwrap()
+                .iter()
+                .map(|segment| (segment.title.clone(), segment.start, segment.end))
+                .collect::<Vec<_>>()
+        };
+
+        recorder.builder.observe(window("Code", "main.rs"), 1_000);
+        recorder.checkpoint_at(5_000);
+        recorder.checkpoint_at(9_000);
+        assert_eq!(stored(), [("main.rs".to_string(), 1_000, 9_000)]);
+        assert!(storage.query_segments(&Default::default()).unwrap()[0]
+            .category
+            .is_some());
+
+        let closed = recorder.builder.observe(window("Mail", "Inbox"), 12_000);
+        recorder.write(closed);
+        assert_eq!(stored(), [("main.rs".to_string(), 1_000, 12_000)]);
+
+        // Idle since before the checkpoint swallows the open segment whole
+        recorder.checkpoint_at(13_000);
+        let closed = recorder.builder.idle_start(11_000);
+        recorder.write(closed);
+        assert_eq!(stored(), [("main.rs".to_string(), 1_000, 12_000)]);
+    }
+}
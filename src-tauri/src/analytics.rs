@@ -0,0 +1,430 @@
+pub mod focus;
+pub mod switches;
+
+use chrono::{Datelike, Days, NaiveDate, TimeZone, Timelike};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::activity::{ActivitySegment, SegmentState};
+use crate::model::StorageError;
+use crate::storage::{SegmentFilter, Storage};
+
+/// Category reported for active time no rule has classified yet.
+pub const UNCATEGORIZED: &str = "Uncategorized";
+
+const HOUR_MILLIS: i64 = 60 * 60 * 1000;
+
+/// Active time under one app, title or category.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Bucket {
+    pub name: String,
+    pub millis: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TitleTime {
+    pub class: String,
+    pub title: String,
+    pub millis: i64,
+}
+
+/// Where the time in a range went. Lists are longest first; AFK time only
+/// counts towards `afk_millis`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Totals {
+    pub active_millis: i64,
+    pub afk_millis: i64,
+    pub by_app: Vec<Bucket>,
+    pub by_title: Vec<TitleTime>,
+    pub by_category: Vec<Bucket>,
+    /// Active time per local hour of day, midnight first.
+    pub by_hour: Vec<i64>,
+}
+
+impl Totals {
+    /// The totals of both ranges together.
+    pub fn merge(&self, other: &Totals) -> Totals {
+        let mut tally = Tally::default();
+        tally.add_totals(self);
+        tally.add_totals(other);
+        tally.finish()
+    }
+}
+
+#[derive(Default)]
+struct Tally {
+    active: i64,
+    afk: i64,
+    apps: HashMap<String, i64>,
+    titles: HashMap<(String, String), i64>,
+    categories: HashMap<String, i64>,
+    hours: [i64; 24],
+}
+
+impl Tally {
+    fn add_totals(&mut self, totals: &Totals) {
+        self.active += totals.active_millis;
+        self.afk += totals.afk_millis;
+        for bucket in &totals.by_app {
+            *self.apps.entry(bucket.name.clone()).or_default() += bucket.millis;
+        }
+        for title in &totals.by_title {
+            *self
+                .titles
+                .entry((title.class.clone(), title.title.clone()))
+                .or_default() += title.millis;
+        }
+        for bucket in &totals.by_category {
+            *self.categories.entry(bucket.name.clone()).or_default() += bucket.millis;
+        }
+        for (hour, millis) in totals.by_hour.iter().enumerate().take(24) {
+            self.hours[hour] += millis;
+        }
+    }
+
+    fn finish(self) -> Totals {
+        Totals {
+            active_millis: self.active,
+            afk_millis: self.afk,
+            by_app: buckets(self.apps),
+            by_title: {
+                let mut titles: Vec<_> = self
+                    .titles
+                    .into_iter()
+                    .map(|((class, title), millis)| TitleTime {
+                        class,
+                        title,
+                        millis,
+                    })
+                    .collect();
+                titles.sort_by(|a, b| {
+                    b.millis
+                        .cmp(&a.millis)
+                        .then_with(|| (&a.class, &a.title).cmp(&(&b.class, &b.title)))
+                });
+                titles
+            },
+            by_category: buckets(self.categories),
+            by_hour: self.hours.to_vec(),
+        }
+    }
+}
+
+fn buckets(totals: HashMap<String, i64>) -> Vec<Bucket> {
+    let mut buckets: Vec<_> = totals
+        .into_iter()
+        .map(|(name, millis)| Bucket { name, millis })
+        .collect();
+    buckets.sort_by(|a, b| b.millis.cmp(&a.millis).then_with(|| a.name.cmp(&b.name)));
+    buckets
+}
+
+/// Totals of `segments` clipped to `[from, to)`, with hours read in `tz`.
+pub fn summarize<Tz: TimeZone>(
+    segments: &[ActivitySegment],
+    from: i64,
+    to: i64,
+    tz: &Tz,
+) -> Totals {
+    let mut tally = Tally::default();
+
+    for segment in segments {
+        let (start, end) = (segment.start.max(from), segment.end.min(to));
+        if end <= start {
+            continue;
+        }
+        let millis = end - start;
+
+        if segment.state == SegmentState::Afk {
+            tally.afk += millis;
+            continue;
+        }
+
+        tally.active += millis;
+        *tally.apps.entry(segment.class.clone()).or_default() += millis;
+        *tally
+            .titles
+            .entry((segment.class.clone(), segment.title.clone()))
+            .or_default() += millis;
+        let category = segment.category.as_deref().unwrap_or(UNCATEGORIZED);
+        *tally.categories.entry(category.to_string()).or_default() += millis;
+
+        // Split at each local hour boundary
+        let mut at = start;
+        while at < end {
+            let Some(local) = tz.timestamp_millis_opt(at).single() else {
+                break;
+            };
+            let into_hour = i64::from(local.minute()) * 60_000
+                + i64::from(local.second()) * 1000
+                + i64::from(local.timestamp_subsec_millis());
+            let next = (at - into_hour + HOUR_MILLIS).min(end);
+            tally.hours[local.hour() as usize] += next - at;
+            at = next;
+        }
+    }
+
+    tally.finish()
+}
+
+/// `day` as half-open unix milliseconds, from one local midnight to the next.
+pub fn day_bounds<Tz: TimeZone>(day: NaiveDate, tz: &Tz) -> (i64, i64) {
+    let midnight = |day: NaiveDate| {
+        let naive = day.and_time(Default::default());
+        tz.from_local_datetime(&naive)
+            .earliest()
+            // Zones whose clocks skip midnight start the day when they resume
+            .unwrap_or_else(|| tz.from_utc_datetime(&naive))
+            .timestamp_millis()
+    };
+
+    (midnight(day), midnight(day + Days::new(1)))
+}
+
+/// How rollups are grouped.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum Period {
+    #[default]
+    Day,
+    /// ISO weeks, starting on Monday.
+    Week,
+    Month,
+}
+
+impl Period {
+    /// The first day of the period `day` falls in.
+    fn start_of(&self, day: NaiveDate) -> NaiveDate {
+        match self {
+            Period::Day => day,
+            Period::Week => day - Days::new(u64::from(day.weekday().num_days_from_monday())),
+            Period::Month => day.with_day(1).unwrap_or(day),
+        }
+    }
+}
+
+/// Totals for the period starting on `start`, clipped to the days asked for.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Rollup {
+    pub start: NaiveDate,
+    pub from: i64,
+    pub to: i64,
+    pub totals: Totals,
+}
+
+/// Totals per `period` for the local days `first` through `last`.
+///
+/// Days that ended before `now` are cached in storage, so history views only
+/// compute days they haven't seen or whose segments changed since.
+pub fn rollups<Tz: TimeZone>(
+    storage: &Storage,
+    first: NaiveDate,
+    last: NaiveDate,
+    period: Period,
+    tz: &Tz,
+    now: i64,
+) -> Result<Vec<Rollup>, StorageError> {
+    let mut days = Vec::new();
+    let mut missing = Vec::new();
+    let mut day = first;
+    while day <= last {
+        let (from, to) = day_bounds(day, tz);
+        let key = day.to_string();
+        match storage.daily_rollup::<Totals>(&key, from, to)? {
+            Some(totals) => days.push((day, from, to, totals)),
+            None => missing.push((day, from, to)),
+        }
+        day = day + Days::new(1);
+    }
+
+    // Read each run of adjacent missing days on its own, so one missing day
+    // at each end of a long cached range doesn't load everything between
+    let mut runs: Vec<Vec<(NaiveDate, i64, i64)>> = Vec::new();
+    for day in missing {
+        match runs.last_mut() {
+            Some(run) if run.last().is_some_and(|&(_, _, to)| to == day.1) => run.push(day),
+            _ => runs.push(vec![day]),
+        }
+    }
+
+    for run in &runs {
+        let (Some(&(_, from, _)), Some(&(_, _, to))) = (run.first(), run.last()) else {
+            continue;
+        };
+        let segments = storage.query_segments(&SegmentFilter {
+            from: Some(from),
+            to: Some(to),
+            ..Default::default()
+        })?;
+
+        for &(day, from, to) in run {
+            let totals = summarize(&segments, from, to, tz);
+            if to <= now {
+                storage.set_daily_rollup(&day.to_string(), from, to, &totals)?;
+            }
+            days.push((day, from, to, totals));
+        }
+    }
+    if !runs.is_empty() {
+        days.sort_by_key(|(day, ..)| *day);
+    }
+
+    let mut rollups: Vec<Rollup> = Vec::new();
+    for (day, from, to, totals) in days {
+        let start = period.start_of(day);
+        match rollups.last_mut() {
+            Some(rollup) if rollup.start == start => {
+                rollup.to = to;
+                rollup.totals = rollup.totals.merge(&totals);
+            }
+            _ => rollups.push(Rollup {
+                start,
+                from,
+                to,
+                totals,
+            }),
+        }
+    }
+
+    Ok(rollups)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::activity::{afk, segment};
+    use chrono::FixedOffset;
+
+    /// Unix milliseconds of `hour:minute` on October `day`, 2024, in `tz`.
+    fn at(tz: &FixedOffset, day: u32, hour: u32, minute: u32) -> i64 {
+        tz.with_ymd_and_hms(2024, 10, day, hour, minute, 0)
+            .unwrap()
+            .timestamp_millis()
+    }
+
+    #[test]
+    fn summarize_clips_to_the_range_and_splits_local_hours() {
+        let tz = FixedOffset::east_opt(5 * 3600 + 1800).unwrap();
+        let minutes = |n: i64| n * 60_000;
+        let segments = vec![
+            segment("Code", "main.rs", at(&tz, 1, 8, 40), at(&tz, 1, 9, 20))
+                .with_category("Development"),
+            segment("Safari", "Docs", at(&tz, 1, 9, 20), at(&tz, 1, 9, 30)),
+            afk(at(&tz, 1, 9, 30), at(&tz, 1, 10, 0)),
+            segment("Code", "lib.rs", at(&tz, 1, 23, 50), at(&tz, 2, 0, 30))
+                .with_category("Development"),
+        ];
+        let (from, to) = day_bounds(NaiveDate::from_ymd_opt(2024, 10, 1).unwrap(), &tz);
+
+        let totals = summarize(&segments, from, to, &tz);
+
+        assert_eq!(totals.active_millis, minutes(60));
+        assert_eq!(totals.afk_millis, minutes(30));
+        assert_eq!(
+            totals.by_app,
+            vec![
+                Bucket {
+                    name: "Code".to_string(),
+                    millis: minutes(50)
+                },
+                Bucket {
+                    name: "Safari".to_string(),
+                    millis: minutes(10)
+                },
+            ]
+        );
+        assert_eq!(totals.by_title[0].title, "main.rs");
+        assert_eq!(
+            totals.by_category,
+            vec![
+                Bucket {
+                    name: "Development".to_string(),
+                    millis: minutes(50)
+                },
+                Bucket {
+                    name: UNCATEGORIZED.to_string(),
+                    millis: minutes(10)
+                },
+            ]
+        );
+        assert_eq!(totals.by_hour[8], minutes(20));
+        assert_eq!(totals.by_hour[9], minutes(30));
+        assert_eq!(totals.by_hour[23], minutes(10));
+        assert_eq!(totals.by_hour.iter().sum::<i64>(), totals.active_millis);
+    }
+
+    #[test]
+    fn rollups_cache_finished_days_and_group_them_by_week() {
+        let tz = FixedOffset::east_opt(0).unwrap();
+        let storage = Storage::open_in_memory().unwrap();
+        // Monday the 7th and Tuesday the 8th are in one week, Sunday the 6th
+        // in the one before
+        for day in [6, 7, 8] {
+            storage
+                .append_segment(&segment(
+                    "Code",
+                    "main.rs",
+                    at(&tz, day, 9, 0),
+                    at(&tz, day, 10, 0),
+                ))
+                .unwrap();
+        }
+        let first = NaiveDate::from_ymd_opt(2024, 10, 6).unwrap();
+        let last = NaiveDate::from_ymd_opt(2024, 10, 8).unwrap();
+        let now = at(&tz, 8, 12, 0);
+
+        let weeks = rollups(&storage, first, last, Period::Week, &tz, now).unwrap();
+        assert_eq!(
+            weeks
+                .iter()
+                .map(|week| (week.start.day(), week.totals.active_millis))
+                .collect::<Vec<_>>(),
+            vec![(30, HOUR_MILLIS), (7, 2 * HOUR_MILLIS)]
+        );
+
+        let (from, to) = day_bounds(NaiveDate::from_ymd_opt(2024, 10, 7).unwrap(), &tz);
+        assert!(storage
+            .daily_rollup::<Totals>("2024-10-07", from, to)
+            .unwrap()
+            .is_some());
+        // Today is still going, so it isn't cached
+        let (from, to) = day_bounds(last, &tz);
+        assert!(storage
+            .daily_rollup::<Totals>("2024-10-08", from, to)
+            .unwrap()
+            .is_none());
+
+        let days = rollups(&storage, first, last, Period::Day, &tz, now).unwrap();
+        assert_eq!(days.len(), 3);
+        assert!(days
+            .iter()
+            .all(|day| day.totals.active_millis == HOUR_MILLIS));
+        // Missing days on either side of the cached ones are read separately
+        let wider = rollups(
+            &storage,
+            first - Days::new(1),
+            last + Days::new(1),
+            Period::Day,
+            &tz,
+            now,
+        )
+        .unwrap();
+        assert_eq!(
+            wider
+                .iter()
+                .map(|day| (day.start.day(), day.totals.active_millis))
+                .collect::<Vec<_>>(),
+            vec![
+                (5, 0),
+                (6, HOUR_MILLIS),
+                (7, HOUR_MILLIS),
+                (8, HOUR_MILLIS),
+                (9, 0)
+            ]
+        );
+    }
+}
@@ -0,0 +1,482 @@
+use chrono::{NaiveDate, TimeZone};
+use serde::{Deserialize, Serialize};
+
+use crate::activity::{ActivitySegment, SegmentState, Session};
+use crate::analytics::{switches, Period};
+use crate::pomodoro::PomodoroSettings;
+use crate::rules::Productivity;
+
+/// Setting the focus score weights are saved under.
+pub const FOCUS_WEIGHTS_SETTING: &str = "focusWeights";
+
+/// Switching this often, or more, earns no switching points.
+pub const MAX_SWITCHES_PER_HOUR: f64 = 60.0;
+
+/// How much each factor counts towards the score, relative to the others.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct FocusWeights {
+    pub productive: f64,
+    pub switches: f64,
+    pub distracting: f64,
+    pub idle: f64,
+}
+
+impl Default for FocusWeights {
+    fn default() -> Self {
+        FocusWeights {
+            productive: 0.4,
+            switches: 0.2,
+            distracting: 0.25,
+            idle: 0.15,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum FocusFactor {
+    /// Share of active time the rules call productive.
+    Productive,
+    /// Window switches per active hour.
+    Switches,
+    /// Share of active time the rules call distracting.
+    Distracting,
+    /// Share of focus time away or untracked.
+    Idle,
+}
+
+/// One factor's part in a score.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct FocusComponent {
+    pub factor: FocusFactor,
+    /// What was measured: a share from 0 to 1, or switches per hour.
+    pub value: f64,
+    /// How well the session did on this factor, from 0 to 1.
+    pub quality: f64,
+    pub weight: f64,
+    /// Points out of 100 this factor contributed.
+    pub points: f64,
+}
+
+/// How focused a session was, from 0 to 100, and why.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct FocusScore {
+    pub session_id: String,
+    pub session_started_on: i64,
+    /// Time in focus phases that was tracked; breaks don't count.
+    pub focus_millis: i64,
+    pub score: f64,
+    pub breakdown: Vec<FocusComponent>,
+}
+
+/// How many focus phases `session` ran.
+///
+/// Sessions imported from the chart history don't record it, so it is worked
+/// out from their totals with the first of `candidates` that fits: exactly,
+/// else on the break length alone, as the focus length may have changed
+/// since. One phase if none fits.
+pub fn phase_count(session: &Session, candidates: &[PomodoroSettings]) -> u32 {
+    if let Some(count) = session.number_of_sessions {
+        return count.max(1);
+    }
+
+    let breaks = |settings: &PomodoroSettings| {
+        let length = settings.break_length_in_seconds as i64;
+        (length > 0 && session.break_length_in_seconds % length == 0)
+            .then(|| session.break_length_in_seconds / length)
+            .filter(|count| *count > 0)
+    };
+    let exact = candidates.iter().find_map(|settings| {
+        let count = breaks(settings)?;
+        let cycle = (settings.session_length_in_seconds + settings.break_length_in_seconds) as i64;
+        (session.pomodoro_length_in_seconds == count * cycle).then_some(count)
+    });
+
+    exact
+        .or_else(|| candidates.iter().find_map(breaks))
+        .and_then(|count| u32::try_from(count).ok())
+        .unwrap_or(1)
+}
+
+/// The `phases` focus phases of `session` as half-open unix milliseconds.
+///
+/// Phases are laid out as the timer plans them, each followed by its break;
+/// pauses aren't recorded, so they shift later phases unnoticed.
+pub fn focus_phases(session: &Session, phases: u32) -> Vec<(i64, i64)> {
+    let phases = i64::from(phases.max(1));
+    let cycle = session.pomodoro_length_in_seconds * 1000 / phases;
+    let focus =
+        (session.pomodoro_length_in_seconds - session.break_length_in_seconds) * 1000 / phases;
+
+    (0..phases)
+        .map(|phase| {
+            let start = session.session_started_on + phase * cycle;
+            (start, start + focus.max(0))
+        })
+        .collect()
+}
+
+/// Scores `session` from its `segments`, oldest first, with its phase count
+/// worked out from `candidates` if it has none. Focus time after the last
+/// segment doesn't count, so aborted and running sessions aren't penalized
+/// for time they never had.
+pub fn focus_score(
+    session: &Session,
+    segments: &[ActivitySegment],
+    weights: &FocusWeights,
+    candidates: &[PomodoroSettings],
+) -> FocusScore {
+    let tracked_until = segments.iter().map(|s| s.end).max().unwrap_or(i64::MIN);
+    let phases: Vec<(i64, i64)> = focus_phases(session, phase_count(session, candidates))
+        .into_iter()
+        .map(|(start, end)| (start, end.min(tracked_until)))
+        .filter(|(start, end)| end > start)
+        .collect();
+
+    let focus_millis: i64 = phases.iter().map(|(start, end)| end - start).sum();
+    let (mut active, mut productive, mut distracting) = (0, 0, 0);
+    let mut switch_count = 0;
+
+    for &(from, to) in &phases {
+        for segment in segments {
+            let millis = segment.end.min(to) - segment.start.max(from);
+            if millis <= 0 || segment.state == SegmentState::Afk {
+                continue;
+            }
+            active += millis;
+            match segment.productivity {
+                Some(Productivity::Productive) => productive += millis,
+                Some(Productivity::Distracting) => distracting += millis,
+                _ => {}
+            }
+        }
+        switch_count += switches::switch_metrics(segments, from, to).switches;
+    }
+
+    let share = |part: i64, whole: i64| {
+        if whole > 0 {
+            part as f64 / whole as f64
+        } else {
+            0.0
+        }
+    };
+    let switches_per_hour = if active > 0 {
+        f64::from(switch_count) / (active as f64 / 3_600_000.0)
+    } else {
+        0.0
+    };
+    let productive_share = share(productive, active);
+    let distracting_share = share(distracting, active);
+    let idle_share = share(focus_millis - active, focus_millis);
+
+    let factors = [
+        (
+            FocusFactor::Productive,
+            productive_share,
+            productive_share,
+            weights.productive,
+        ),
+        (
+            FocusFactor::Switches,
+            switches_per_hour,
+            1.0 - (switches_per_hour / MAX_SWITCHES_PER_HOUR).min(1.0),
+            weights.switches,
+        ),
+        (
+            FocusFactor::Distracting,
+            distracting_share,
+            1.0 - distracting_share,
+            weights.distracting,
+        ),
+        (
+            FocusFactor::Idle,
+            idle_share,
+            1.0 - idle_share,
+            weights.idle,
+        ),
+    ];
+    // Negative weights would let a factor push the score past its bounds
+    let total_weight: f64 = factors.iter().map(|(.., weight)| weight.max(0.0)).sum();
+
+    let breakdown: Vec<FocusComponent> = factors
+        .into_iter()
+        .map(|(factor, value, quality, weight)| FocusComponent {
+            factor,
+            value,
+            quality,
+            weight,
+            points: if total_weight > 0.0 && focus_millis > 0 {
+                100.0 * quality * weight.max(0.0) / total_weight
+            } else {
+                0.0
+            },
+        })
+        .collect();
+
+    FocusScore {
+        session_id: session.id.clone(),
+        session_started_on: session.session_started_on,
+        focus_millis,
+        score: breakdown.iter().map(|component| component.points).sum(),
+        breakdown,
+    }
+}
+
+/// Scores of the sessions started in one week.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct FocusWeek {
+    /// The Monday the week starts on.
+    pub start: NaiveDate,
+    /// Mean score of the sessions with any focus time tracked.
+    pub average_score: Option<f64>,
+    pub sessions: Vec<FocusScore>,
+}
+
+/// Groups `scores`, oldest first, by the local week their session started in.
+pub fn weekly_trend<Tz: TimeZone>(scores: Vec<FocusScore>, tz: &Tz) -> Vec<FocusWeek> {
+    let mut weeks: Vec<FocusWeek> = Vec::new();
+
+    for score in scores {
+        let Some(started) = tz.timestamp_millis_opt(score.session_started_on).single() else {
+            continue;
+        };
+        let start = Period::Week.start_of(started.date_naive());
+        match weeks.last_mut() {
+            Some(week) if week.start == start => week.sessions.push(score),
+            _ => weeks.push(FocusWeek {
+                start,
+                average_score: None,
+                sessions: vec![score],
+            }),
+        }
+    }
+
+    for week in &mut weeks {
+        let scored: Vec<f64> = week
+            .sessions
+            .iter()
+            .filter(|session| session.focus_millis > 0)
+            .map(|session| session.score)
+            .collect();
+        if !scored.is_empty() {
+            week.average_score = Some(scored.iter().sum::<f64>() / scored.len() as f64);
+        }
+    }
+
+    weeks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::activity::{self, afk};
+    use crate::storage::{SegmentFilter, Storage};
+    use chrono::FixedOffset;
+
+    const MINUTE: i64 = 60_000;
+
+    /// `class` from minute `start` to `end`; away if `class` is empty.
+    fn segment(
+        class: &str,
+        productivity: Option<Productivity>,
+        start: i64,
+        end: i64,
+    ) -> ActivitySegment {
+        if class.is_empty() {
+            return afk(start * MINUTE, end * MINUTE);
+        }
+        ActivitySegment {
+            productivity,
+            ..activity::segment(class, class, start * MINUTE, end * MINUTE)
+        }
+    }
+
+    /// Two 25 minute phases with 5 minute breaks, from zero.
+    fn session() -> Session {
+        Session {
+            id: "session".to_string(),
+            pomodoro_length_in_seconds: 2 * 30 * 60,
+            break_length_in_seconds: 2 * 5 * 60,
+            session_started_on: 0,
+            number_of_sessions: Some(2),
+        }
+    }
+
+    #[test]
+    fn scores_only_focus_phases_and_explain_the_points() {
+        let productive = Some(Productivity::Productive);
+        let segments = vec![
+            segment("Code", productive, 0, 20),
+            segment("Slack", Some(Productivity::Distracting), 20, 25),
+            // The break, which doesn't count against the session
+            segment("Twitter", Some(Productivity::Distracting), 25, 30),
+            segment("Code", productive, 30, 40),
+            segment("", None, 40, 50),
+            segment("Code", productive, 50, 55),
+        ];
+
+        let score = focus_score(&session(), &segments, &FocusWeights::default(), &[]);
+
+        assert_eq!(
+            focus_phases(&session(), 2),
+            [(0, 25 * MINUTE), (30 * MINUTE, 55 * MINUTE)]
+        );
+        assert_eq!(score.focus_millis, 50 * MINUTE);
+        let value = |factor| {
+            score
+                .breakdown
+                .iter()
+                .find(|component| component.factor == factor)
+                .unwrap()
+                .value
+        };
+        assert!((value(FocusFactor::Productive) - 35.0 / 40.0).abs() < 1e-9);
+        assert!((value(FocusFactor::Distracting) - 5.0 / 40.0).abs() < 1e-9);
+        assert!((value(FocusFactor::Idle) - 10.0 / 50.0).abs() < 1e-9);
+        // Code to Slack; the AFK gap breaks the rest
+        assert!((value(FocusFactor::Switches) - 1.0 / (40.0 / 60.0)).abs() < 1e-9);
+
+        let points: f64 = score.breakdown.iter().map(|c| c.points).sum();
+        assert!((score.score - points).abs() < 1e-9);
+        assert!(score.score > 0.0 && score.score < 100.0);
+
+        let only_idle = FocusWeights {
+            productive: 0.0,
+            switches: 0.0,
+            distracting: 0.0,
+            idle: 1.0,
+        };
+        let score = focus_score(&session(), &segments, &only_idle, &[]);
+        assert!((score.score - 80.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn imported_sessions_take_their_phase_count_from_their_totals() {
+        let saved = PomodoroSettings {
+            session_length_in_seconds: 50 * 60,
+            number_of_sessions: 4,
+            break_length_in_seconds: 10 * 60,
+        };
+        let candidates = [saved, PomodoroSettings::default()];
+        let imported = |settings: PomodoroSettings, phases: u32| Session {
+            number_of_sessions: None,
+            ..PomodoroSettings {
+                number_of_sessions: phases,
+                ..settings
+            }
+            .session("imported".to_string(), 0)
+        };
+
+        assert_eq!(phase_count(&session(), &candidates), 2);
+        assert_eq!(phase_count(&imported(saved, 3), &candidates), 3);
+        assert_eq!(
+            phase_count(&imported(PomodoroSettings::default(), 3), &candidates),
+            3
+        );
+        // Only the break length fits: the focus length changed since
+        let changed = PomodoroSettings {
+            session_length_in_seconds: 40 * 60,
+            ..saved
+        };
+        assert_eq!(phase_count(&imported(changed, 3), &candidates), 3);
+        let odd = PomodoroSettings {
+            session_length_in_seconds: 20 * 60,
+            number_of_sessions: 1,
+            break_length_in_seconds: 7 * 60,
+        };
+        assert_eq!(phase_count(&imported(odd, 3), &candidates), 1);
+
+        // The break between phases only counts as idle while the count is unknown
+        let productive = Some(Productivity::Productive);
+        let segments = [
+            segment("Code", productive, 0, 25),
+            segment("Code", productive, 30, 55),
+        ];
+        let idle = |candidates: &[PomodoroSettings]| {
+            let score = focus_score(
+                &imported(PomodoroSettings::default(), 2),
+                &segments,
+                &FocusWeights::default(),
+                candidates,
+            );
+            score.breakdown[3].value
+        };
+        assert!((idle(&[]) - 5.0 / 50.0).abs() < 1e-9);
+        assert_eq!(idle(&[PomodoroSettings::default()]), 0.0);
+    }
+
+    #[test]
+    fn sessions_the_timer_records_can_be_scored() {
+        let storage = Storage::open_in_memory().unwrap();
+        let started = 1_700_000_000_000;
+        let session = PomodoroSettings::default().session("timer".to_string(), started);
+        storage.insert_session(&session).unwrap();
+        let at = |minute: i64| started + minute * MINUTE;
+        for segment in [
+            activity::segment("Code", "main.rs", at(0), at(10)).with_session("timer"),
+            activity::segment("Code", "lib.rs", at(10), at(20)).with_session("timer"),
+            // Another session's, which the filter leaves out
+            activity::segment("Slack", "general", at(20), at(25)).with_session("other"),
+        ] {
+            storage.append_segment(&segment).unwrap();
+        }
+
+        let stored = storage.session("timer").unwrap().unwrap();
+        let segments = storage
+            .query_segments(&SegmentFilter {
+                session_id: Some("timer".to_string()),
+                ..Default::default()
+            })
+            .unwrap();
+        let score = focus_score(&stored, &segments, &FocusWeights::default(), &[]);
+
+        assert_eq!(segments.len(), 2);
+        assert_eq!(score.session_id, "timer");
+        assert_eq!(score.session_started_on, started);
+        assert_eq!(score.focus_millis, 20 * MINUTE);
+        assert!(score.score > 0.0);
+    }
+
+    #[test]
+    fn trend_averages_scored_sessions_per_week() {
+        let tz = FixedOffset::east_opt(0).unwrap();
+        let at = |day: u32| {
+            tz.with_ymd_and_hms(2024, 10, day, 9, 0, 0)
+                .unwrap()
+                .timestamp_millis()
+        };
+        let score = |day: u32, score: f64, focus_millis: i64| FocusScore {
+            session_id: day.to_string(),
+            session_started_on: at(day),
+            focus_millis,
+            score,
+            breakdown: Vec::new(),
+        };
+
+        let weeks = weekly_trend(
+            vec![
+                score(6, 50.0, MINUTE),
+                score(7, 60.0, MINUTE),
+                score(9, 80.0, MINUTE),
+                // Nothing tracked, so it doesn't drag the average down
+                score(10, 0.0, 0),
+            ],
+            &tz,
+        );
+
+        assert_eq!(
+            weeks
+                .iter()
+                .map(|week| (week.start, week.average_score, week.sessions.len()))
+                .collect::<Vec<_>>(),
+            vec![
+                (NaiveDate::from_ymd_opt(2024, 9, 30).unwrap(), Some(50.0), 1),
+                (NaiveDate::from_ymd_opt(2024, 10, 7).unwrap(), Some(70.0), 3),
+            ]
+        );
+    }
+}
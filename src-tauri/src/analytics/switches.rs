@@ -0,0 +1,273 @@
+use chrono::{Days, NaiveDate, TimeZone};
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::activity::{ActivitySegment, SegmentState};
+use crate::analytics::day_bounds;
+use crate::rules::Productivity;
+
+/// Segments closer than this still count as back to back; polling leaves
+/// small gaps.
+pub const MAX_GAP_MILLIS: i64 = 5_000;
+
+/// An A → B → A detour has to happen this often for the pair to be reported.
+pub const MIN_PING_PONGS: u32 = 2;
+
+const HOUR_MILLIS: f64 = 60.0 * 60.0 * 1000.0;
+
+/// Two apps the user kept alternating between.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PingPong {
+    pub apps: [String; 2],
+    pub count: u32,
+}
+
+/// How fragmented the active time in a range was.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SwitchMetrics {
+    /// Changes of the active window, app or title, without stepping away.
+    pub switches: u32,
+    pub active_millis: i64,
+    pub switches_per_hour: f64,
+    /// Typical time spent in one window before moving on.
+    pub median_stretch_millis: i64,
+    /// Longest run of active time without stepping away or opening a window
+    /// the rules call distracting.
+    pub longest_deep_work_millis: i64,
+    /// Most alternated pairs first.
+    pub ping_pongs: Vec<PingPong>,
+}
+
+/// Uninterrupted time in one window.
+struct Stretch<'a> {
+    class: &'a str,
+    title: &'a str,
+    start: i64,
+    end: i64,
+    distracting: bool,
+}
+
+fn back_to_back(end: i64, next_start: i64) -> bool {
+    next_start - end <= MAX_GAP_MILLIS
+}
+
+/// Switching metrics for `segments`, oldest first, clipped to `[from, to)`.
+pub fn switch_metrics(segments: &[ActivitySegment], from: i64, to: i64) -> SwitchMetrics {
+    let mut stretches: Vec<Stretch> = Vec::new();
+
+    for segment in segments {
+        let (start, end) = (segment.start.max(from), segment.end.min(to));
+        if end <= start || segment.state == SegmentState::Afk {
+            continue;
+        }
+        let distracting = segment.productivity == Some(Productivity::Distracting);
+
+        match stretches.last_mut() {
+            Some(last)
+                if back_to_back(last.end, start)
+                    && last.class == segment.class
+                    && last.title == segment.title =>
+            {
+                last.end = end;
+                last.distracting |= distracting;
+            }
+            _ => stretches.push(Stretch {
+                class: &segment.class,
+                title: &segment.title,
+                start,
+                end,
+                distracting,
+            }),
+        }
+    }
+
+    let switches = stretches
+        .windows(2)
+        .filter(|pair| back_to_back(pair[0].end, pair[1].start))
+        .count() as u32;
+    let active_millis: i64 = stretches.iter().map(|s| s.end - s.start).sum();
+
+    SwitchMetrics {
+        switches,
+        active_millis,
+        switches_per_hour: if active_millis > 0 {
+            f64::from(switches) / (active_millis as f64 / HOUR_MILLIS)
+        } else {
+            0.0
+        },
+        median_stretch_millis: median(stretches.iter().map(|s| s.end - s.start).collect()),
+        longest_deep_work_millis: longest_deep_work(&stretches),
+        ping_pongs: ping_pongs(&stretches),
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DaySwitches {
+    pub day: NaiveDate,
+    pub metrics: SwitchMetrics,
+}
+
+/// Switching metrics for each local day `first` through `last`.
+pub fn per_day<Tz: TimeZone>(
+    segments: &[ActivitySegment],
+    first: NaiveDate,
+    last: NaiveDate,
+    tz: &Tz,
+) -> Vec<DaySwitches> {
+    let mut days = Vec::new();
+    let mut day = first;
+    while day <= last {
+        let (from, to) = day_bounds(day, tz);
+        days.push(DaySwitches {
+            day,
+            metrics: switch_metrics(segments, from, to),
+        });
+        day = day + Days::new(1);
+    }
+    days
+}
+
+fn median(mut values: Vec<i64>) -> i64 {
+    values.sort_unstable();
+    match values.len() {
+        0 => 0,
+        n if n % 2 == 1 => values[n / 2],
+        n => (values[n / 2 - 1] + values[n / 2]) / 2,
+    }
+}
+
+fn longest_deep_work(stretches: &[Stretch]) -> i64 {
+    let mut longest = 0;
+    let mut block: Option<(i64, i64)> = None;
+
+    for stretch in stretches {
+        if stretch.distracting {
+            block = None;
+            continue;
+        }
+        block = match block {
+            Some((start, end)) if back_to_back(end, stretch.start) => Some((start, stretch.end)),
+            _ => Some((stretch.start, stretch.end)),
+        };
+        if let Some((start, end)) = block {
+            longest = longest.max(end - start);
+        }
+    }
+
+    longest
+}
+
+/// Counts A → B → A app changes, per unordered pair.
+fn ping_pongs(stretches: &[Stretch]) -> Vec<PingPong> {
+    let mut counts = HashMap::<(&str, &str), u32>::new();
+    // Apps in the order they were used, one entry per visit
+    let mut visits: Vec<&Stretch> = Vec::new();
+
+    for stretch in stretches {
+        match visits.last_mut() {
+            Some(last) if !back_to_back(last.end, stretch.start) => visits.clear(),
+            // Another window of the same app continues the visit
+            Some(last) if last.class == stretch.class => {
+                *last = stretch;
+                continue;
+            }
+            _ => {}
+        }
+        visits.push(stretch);
+
+        if let [.., a, b, c] = visits.as_slice() {
+            if a.class == c.class {
+                let pair = if a.class < b.class {
+                    (a.class, b.class)
+                } else {
+                    (b.class, a.class)
+                };
+                *counts.entry(pair).or_default() += 1;
+            }
+        }
+    }
+
+    let mut pairs: Vec<PingPong> = counts
+        .into_iter()
+        .filter(|(_, count)| *count >= MIN_PING_PONGS)
+        .map(|((a, b), count)| PingPong {
+            apps: [a.to_string(), b.to_string()],
+            count,
+        })
+        .collect();
+    pairs.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.apps.cmp(&b.apps)));
+    pairs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::activity::{afk, segment};
+
+    const MINUTE: i64 = 60_000;
+
+    /// Back-to-back segments of `(class, title, minutes)`, from zero.
+    fn timeline(windows: &[(&str, &str, i64)]) -> Vec<ActivitySegment> {
+        let mut start = 0;
+        windows
+            .iter()
+            .map(|(class, title, minutes)| {
+                let end = start + minutes * MINUTE;
+                let segment = match *class {
+                    "" => afk(start, end),
+                    "Slack" => segment(class, title, start, end)
+                        .with_productivity(Productivity::Distracting),
+                    _ => segment(class, title, start, end),
+                };
+                start = end;
+                segment
+            })
+            .collect()
+    }
+
+    #[test]
+    fn metrics_count_switches_stretches_and_ping_pongs() {
+        let segments = timeline(&[
+            ("Code", "main.rs", 20),
+            ("Terminal", "cargo", 2),
+            ("Code", "main.rs", 10),
+            ("Code", "lib.rs", 8),
+            ("Terminal", "cargo", 2),
+            ("Code", "lib.rs", 6),
+            ("Slack", "general", 4),
+            ("", "", 30),
+            ("Code", "lib.rs", 12),
+        ]);
+
+        let metrics = switch_metrics(&segments, 0, i64::MAX);
+
+        // The stretch after the break doesn't count as a switch
+        assert_eq!(metrics.switches, 6);
+        assert_eq!(metrics.active_millis, 64 * MINUTE);
+        assert!((metrics.switches_per_hour - 6.0 / (64.0 / 60.0)).abs() < 1e-9);
+        assert_eq!(metrics.median_stretch_millis, 7 * MINUTE);
+        // Everything before Slack
+        assert_eq!(metrics.longest_deep_work_millis, 48 * MINUTE);
+        assert_eq!(
+            metrics.ping_pongs,
+            vec![PingPong {
+                apps: ["Code".to_string(), "Terminal".to_string()],
+                count: 3,
+            }]
+        );
+    }
+
+    #[test]
+    fn metrics_only_see_the_range() {
+        let segments = timeline(&[("Code", "main.rs", 30), ("Safari", "Docs", 30)]);
+
+        let metrics = switch_metrics(&segments, 40 * MINUTE, 60 * MINUTE);
+
+        assert_eq!(metrics.switches, 0);
+        assert_eq!(metrics.active_millis, 20 * MINUTE);
+        assert_eq!(metrics.median_stretch_millis, 20 * MINUTE);
+    }
+}
@@ -0,0 +1,347 @@
+use chrono::{DateTime, Local, NaiveDate, TimeZone};
+use clap::{Args, Parser, Subcommand};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, Write},
+    path::PathBuf,
+    process::ExitCode,
+};
+
+use locus_lib::activity::{ActivitySegment, SegmentState};
+use locus_lib::export::{self, ExportFormat};
+use locus_lib::paths;
+use locus_lib::storage::{SegmentFilter, Storage};
+
+/// Summaries of the activity Locus has recorded, straight from its database.
+#[derive(Parser)]
+#[command(name = "locus-cli", version)]
+struct Cli {
+    /// Database to read instead of the app's
+    #[arg(long, global = true)]
+    db: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Time per app today
+    Today,
+    /// Time per app over a range of days
+    Report(DayRange),
+    /// The apps with the most time over a range of days
+    TopApps {
+        #[command(flatten)]
+        range: DayRange,
+        #[arg(long, default_value_t = 5)]
+        limit: usize,
+    },
+    /// Recorded segments, for spreadsheets and scripts
+    Export {
+        #[arg(long, value_enum, default_value_t = Format::Csv)]
+        format: Format,
+        #[command(flatten)]
+        range: DayRange,
+        /// File to write instead of stdout
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Pomodoro sessions
+    Sessions {
+        #[command(subcommand)]
+        command: SessionsCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum SessionsCommand {
+    /// Sessions started over a range of days
+    List(DayRange),
+}
+
+/// `ExportFormat` as a command line value, so the library doesn't depend on
+/// clap for it.
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum Format {
+    /// One row per segment, times in the local timezone
+    Csv,
+    /// Sessions and segments as separate lists, times in unix milliseconds
+    Json,
+}
+
+impl From<Format> for ExportFormat {
+    fn from(format: Format) -> Self {
+        match format {
+            Format::Csv => ExportFormat::Csv,
+            Format::Json => ExportFormat::Json,
+        }
+    }
+}
+
+/// Whole local days, `to` included. `from` defaults to today and `to` to the
+/// later of `from` and today.
+#[derive(Args, Default)]
+struct DayRange {
+    /// First day, as YYYY-MM-DD
+    #[arg(long)]
+    from: Option<NaiveDate>,
+    /// Last day, as YYYY-MM-DD
+    #[arg(long)]
+    to: Option<NaiveDate>,
+}
+
+impl DayRange {
+    /// The range as half-open unix milliseconds.
+    fn millis(&self) -> Result<(i64, i64), String> {
+        let today = Local::now().date_naive();
+        let from = self.from.unwrap_or(today);
+        let to = self.to.unwrap_or(from.max(today));
+
+        if to < from {
+            return Err(format!("--to {} is before --from {}", to, from));
+        }
+
+        Ok((
+            start_of_day(from)?,
+            start_of_day(to.succ_opt().ok_or("Date out of range")?)?,
+        ))
+    }
+}
+
+fn start_of_day(date: NaiveDate) -> Result<i64, String> {
+    Local
+        .from_local_datetime(&date.and_time(Default::default()))
+        .earliest()
+        .map(|start| start.timestamp_millis())
+        .ok_or_else(|| format!("{} has no midnight in the local timezone", date))
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    match run(cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("locus-cli: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(cli: Cli) -> Result<(), String> {
+    let path = cli
+        .db
+        .or_else(paths::database_path)
+        .ok_or("Unable to find the Locus data folder; pass --db")?;
+    if !path.exists() {
+        return Err(format!(
+            "No database at {}; has Locus run yet?",
+            path.display()
+        ));
+    }
+    let storage = Storage::open(&path).map_err(|e| e.to_string())?;
+    let mut out = io::stdout().lock();
+
+    match cli.command {
+        Command::Today => print_report(&storage, &DayRange::default(), None, &mut out),
+        Command::Report(range) => print_report(&storage, &range, None, &mut out),
+        Command::TopApps { range, limit } => print_report(&storage, &range, Some(limit), &mut out),
+        Command::Export {
+            format,
+            range,
+            output,
+        } => {
+            let (from, to) = range.millis()?;
+            let export = export::collect(&storage, from, to).map_err(|e| e.to_string())?;
+            let format = ExportFormat::from(format);
+            match output {
+                Some(path) => {
+                    let file = File::create(&path)
+                        .map_err(|e| format!("Unable to create {}: {}", path.display(), e))?;
+                    export::write(&export, format, &Local, io::BufWriter::new(file))
+                }
+                None => export::write(&export, format, &Local, &mut out),
+            }
+            .map_err(|e| format!("Unable to write the export: {}", e))
+        }
+        Command::Sessions {
+            command: SessionsCommand::List(range),
+        } => {
+            let (from, to) = range.millis()?;
+            let sessions = storage
+                .sessions_between(from, to)
+                .map_err(|e| e.to_string())?;
+
+            for session in sessions {
+                writeln!(
+                    out,
+                    "{}  {}  {:>8}",
+                    session.id,
+                    local_time(session.session_started_on),
+                    format_duration(session.pomodoro_length_in_seconds * 1000)
+                )
+                .map_err(|e| e.to_string())?;
+            }
+            Ok(())
+        }
+    }
+}
+
+fn segments_in(storage: &Storage, range: &DayRange) -> Result<Vec<ActivitySegment>, String> {
+    let (from, to) = range.millis()?;
+
+    storage
+        .query_segments(&SegmentFilter {
+            from: Some(from),
+            to: Some(to),
+            ..Default::default()
+        })
+        .map_err(|e| e.to_string())
+}
+
+fn print_report(
+    storage: &Storage,
+    range: &DayRange,
+    limit: Option<usize>,
+    out: &mut impl Write,
+) -> Result<(), String> {
+    let (from, to) = range.millis()?;
+    let segments = segments_in(storage, range)?;
+    let (mut apps, away) = time_per_app(&segments, from, to);
+    let total: i64 = apps.iter().map(|(_, millis)| millis).sum();
+    if let Some(limit) = limit {
+        apps.truncate(limit);
+    }
+
+    write_report(&apps, total, away, out).map_err(|e| e.to_string())
+}
+
+fn write_report(
+    apps: &[(String, i64)],
+    total: i64,
+    away: i64,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    for (app, millis) in apps {
+        writeln!(
+            out,
+            "{:<32} {:>8} {:>5.1}%",
+            app,
+            format_duration(*millis),
+            *millis as f64 * 100.0 / total as f64
+        )?;
+    }
+    writeln!(out, "{:<32} {:>8}", "Total", format_duration(total))?;
+    if away > 0 {
+        writeln!(out, "{:<32} {:>8}", "Away", format_duration(away))?;
+    }
+
+    Ok(())
+}
+
+/// Active time per app within `[from, to)`, longest first, and the AFK total.
+fn time_per_app(segments: &[ActivitySegment], from: i64, to: i64) -> (Vec<(String, i64)>, i64) {
+    let mut apps = HashMap::<&str, i64>::new();
+    let mut away = 0;
+
+    for segment in segments {
+        let millis = segment.end.min(to) - segment.start.max(from);
+        if millis <= 0 {
+            continue;
+        }
+
+        match segment.state {
+            SegmentState::Active => *apps.entry(segment.class.as_str()).or_default() += millis,
+            SegmentState::Afk => away += millis,
+        }
+    }
+
+    let mut apps: Vec<_> = apps
+        .into_iter()
+        .map(|(app, millis)| (app.to_string(), millis))
+        .collect();
+    apps.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+    (apps, away)
+}
+
+fn local_time(millis: i64) -> String {
+    DateTime::from_timestamp_millis(millis)
+        .map(|time| time.with_timezone(&Local).to_rfc3339())
+        .unwrap_or_default()
+}
+
+fn format_duration(millis: i64) -> String {
+    let minutes = millis / 60_000;
+
+    if minutes >= 60 {
+        format!("{}h {:02}m", minutes / 60, minutes % 60)
+    } else {
+        format!("{}m", minutes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use locus_lib::titles::TitleFields;
+
+    fn segment(class: &str, state: SegmentState, start: i64, end: i64) -> ActivitySegment {
+        ActivitySegment {
+            session_id: "session".to_string(),
+            class: class.to_string(),
+            title: "title".to_string(),
+            start,
+            end,
+            state,
+            category: None,
+            productivity: None,
+            fields: TitleFields::default(),
+            domain: None,
+            raw_title: None,
+        }
+    }
+
+    #[test]
+    fn time_per_app_clips_to_the_range_and_separates_afk() {
+        let segments = [
+            segment("Code", SegmentState::Active, 0, 4_000),
+            segment("Slack", SegmentState::Active, 4_000, 5_000),
+            segment("", SegmentState::Afk, 5_000, 7_000),
+            segment("Code", SegmentState::Active, 7_000, 12_000),
+        ];
+
+        assert_eq!(
+            time_per_app(&segments, 1_000, 10_000),
+            (
+                vec![("Code".to_string(), 6_000), ("Slack".to_string(), 1_000)],
+                2_000
+            )
+        );
+    }
+
+    #[test]
+    fn day_ranges_cover_whole_days() {
+        let range = DayRange {
+            from: NaiveDate::from_ymd_opt(2024, 10, 5),
+            to: NaiveDate::from_ymd_opt(2024, 10, 6),
+        };
+        let (from, to) = range.millis().unwrap();
+
+        assert!(to - from >= 47 * 3_600_000 && to - from <= 49 * 3_600_000);
+        assert!(DayRange {
+            from: range.to,
+            to: range.from,
+        }
+        .millis()
+        .is_err());
+    }
+
+    #[test]
+    fn durations_read_as_hours_and_minutes() {
+        assert_eq!(format_duration(59_999), "0m");
+        assert_eq!(format_duration(125 * 60_000), "2h 05m");
+    }
+}
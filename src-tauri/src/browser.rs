@@ -0,0 +1,202 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use url::Url;
+
+use crate::activity::{ActivitySegment, SegmentState};
+use crate::model::ActiveWindow;
+
+/// Browsers by the name NSWorkspace and System Events give their process.
+const BROWSERS: &[&str] = &[
+    "Safari",
+    "Safari Technology Preview",
+    "Google Chrome",
+    "Google Chrome Canary",
+    "Chromium",
+    "Firefox",
+    "Firefox Developer Edition",
+    "Firefox Nightly",
+    "Brave Browser",
+    "Arc",
+    "Microsoft Edge",
+];
+
+/// Whether `app_name` is exactly one of the browsers; apps that merely share
+/// a word with one, like Archive Utility, aren't.
+pub fn is_browser(app_name: &str) -> bool {
+    BROWSERS.contains(&app_name)
+}
+
+/// Parses what the browser AppleScript prints: `Class|title` on the first
+/// line and, for browsers that expose it, the tab's URL on the second.
+///
+/// Titles can contain `|` themselves, so only the first one separates the
+/// class.
+pub fn parse_tab_output(output: &str) -> Option<ActiveWindow> {
+    let mut lines = output.trim_end().lines();
+    let (class, title) = lines.next()?.split_once('|')?;
+    if class == "Unknown" || class.is_empty() {
+        return None;
+    }
+
+    let url = lines
+        .next()
+        .map(str::trim)
+        .filter(|url| !url.is_empty() && *url != "missing value")
+        .map(str::to_string);
+
+    Some(ActiveWindow {
+        class: class.to_string(),
+        title: title.to_string(),
+        url,
+        raw_title: None,
+    })
+}
+
+/// The site a URL belongs to: its lowercase host without a leading `www.`.
+/// `None` for anything that isn't a web page, like `file:` or `about:blank`.
+pub fn domain(url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return None;
+    }
+
+    let host = url.host_str()?.to_lowercase();
+    Some(host.strip_prefix("www.").unwrap_or(&host).to_string())
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct DomainTime {
+    pub domain: String,
+    pub millis: i64,
+}
+
+/// Active time per domain, longest first. Segments without a web page are
+/// left out.
+pub fn time_per_domain(segments: &[ActivitySegment]) -> Vec<DomainTime> {
+    let mut domains = HashMap::<&str, i64>::new();
+
+    for segment in segments {
+        if let (SegmentState::Active, Some(domain)) = (segment.state, &segment.domain) {
+            *domains.entry(domain.as_str()).or_default() += segment.end - segment.start;
+        }
+    }
+
+    let mut domains: Vec<_> = domains
+        .into_iter()
+        .map(|(domain, millis)| DomainTime {
+            domain: domain.to_string(),
+            millis,
+        })
+        .collect();
+    domains.sort_by(|a, b| {
+        b.millis
+            .cmp(&a.millis)
+            .then_with(|| a.domain.cmp(&b.domain))
+    });
+
+    domains
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::activity;
+
+    #[test]
+    fn tab_output_carries_the_url_when_the_browser_exposes_it() {
+        let safari = "Safari|Inbox (3) - Gmail\nhttps://mail.google.com/mail/u/0/#inbox\n";
+        assert_eq!(
+            parse_tab_output(safari),
+            Some(ActiveWindow {
+                class: "Safari".to_string(),
+                title: "Inbox (3) - Gmail".to_string(),
+                url: Some("https://mail.google.com/mail/u/0/#inbox".to_string()),
+                raw_title: None,
+            })
+        );
+
+        let firefox = parse_tab_output("Firefox|Rust | docs.rs\n").unwrap();
+        assert_eq!(firefox.title, "Rust | docs.rs");
+        assert_eq!(firefox.url, None);
+
+        let new_tab = parse_tab_output("Chrome|New Tab\nmissing value\n").unwrap();
+        assert_eq!(new_tab.url, None);
+
+        assert_eq!(parse_tab_output("Unknown|No Browser Tab\n"), None);
+        assert_eq!(parse_tab_output(""), None);
+    }
+
+    #[test]
+    fn browsers_are_matched_by_their_whole_name() {
+        for app in [
+            "Safari",
+            "Google Chrome",
+            "Brave Browser",
+            "Arc",
+            "Microsoft Edge",
+        ] {
+            assert!(is_browser(app), "{}", app);
+        }
+        for app in [
+            "Archive Utility",
+            "Edge Detector",
+            "Chrome Remote Desktop",
+            "",
+        ] {
+            assert!(!is_browser(app), "{}", app);
+        }
+    }
+
+    #[test]
+    fn domains_drop_www_and_ignore_non_web_urls() {
+        let cases = [
+            (
+                "https://www.GitHub.com/rust-lang/rust/pulls",
+                Some("github.com"),
+            ),
+            (
+                "https://mail.google.com/mail/u/0/#inbox",
+                Some("mail.google.com"),
+            ),
+            ("http://localhost:1420/", Some("localhost")),
+            ("http://127.0.0.1:8080/health", Some("127.0.0.1")),
+            ("file:///Users/me/notes.html", None),
+            ("about:blank", None),
+            ("chrome://settings", None),
+            ("not a url", None),
+        ];
+
+        for (url, expected) in cases {
+            assert_eq!(domain(url).as_deref(), expected, "{}", url);
+        }
+    }
+
+    #[test]
+    fn time_is_grouped_by_domain() {
+        let segment = |domain: Option<&str>, state, start, end| ActivitySegment {
+            domain: domain.map(str::to_string),
+            ..activity::segment("Safari", "", start, end).with_state(state)
+        };
+        let segments = [
+            segment(Some("github.com"), SegmentState::Active, 0, 1_000),
+            segment(Some("docs.rs"), SegmentState::Active, 1_000, 4_000),
+            segment(None, SegmentState::Active, 4_000, 5_000),
+            segment(None, SegmentState::Afk, 5_000, 9_000),
+            segment(Some("github.com"), SegmentState::Active, 9_000, 12_000),
+        ];
+
+        assert_eq!(
+            time_per_domain(&segments),
+            vec![
+                DomainTime {
+                    domain: "github.com".to_string(),
+                    millis: 4_000,
+                },
+                DomainTime {
+                    domain: "docs.rs".to_string(),
+                    millis: 3_000,
+                },
+            ]
+        );
+    }
+}
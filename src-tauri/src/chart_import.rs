@@ -0,0 +1,299 @@
+use chrono::DateTime;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+use crate::activity::{ActivitySegment, SegmentState, Session};
+use crate::model::StorageError;
+use crate::storage::Storage;
+use crate::titles::TitleFields;
+
+/// Break length the frontend assumes when a stored chart predates the field.
+const DEFAULT_BREAK_LENGTH_IN_SECONDS: f64 = 5.0 * 60.0;
+
+/// A session as the frontend writes it to `chart_data.json`, matching
+/// `chartHistorySchema` in `settingStore.tsx`.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct StoredChart {
+    chart_data: HashMap<String, Vec<StoredTitleRange>>,
+    id: String,
+    pomodoro_length_in_seconds: f64,
+    #[serde(default = "default_break_length")]
+    break_length_in_seconds: f64,
+    session_started_on: String,
+}
+
+/// `range` is in seconds since the session started.
+#[derive(Deserialize, Debug)]
+struct StoredTitleRange {
+    range: (f64, f64),
+    title: String,
+}
+
+fn default_break_length() -> f64 {
+    DEFAULT_BREAK_LENGTH_IN_SECONDS
+}
+
+#[derive(Serialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportReport {
+    pub imported: usize,
+    pub already_present: usize,
+    pub issues: Vec<ImportIssue>,
+}
+
+/// A chart entry that could not be imported. The rest of the file still is.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportIssue {
+    pub id: Option<String>,
+    pub message: String,
+}
+
+impl ImportIssue {
+    fn new(id: Option<&str>, message: impl Into<String>) -> Self {
+        ImportIssue {
+            id: id.map(str::to_string),
+            message: message.into(),
+        }
+    }
+}
+
+/// Loads the contents of a `chart_data.json` store into `storage`.
+///
+/// Sessions are keyed on their id, so importing the same file twice is a no-op.
+/// Malformed entries are reported in the result instead of aborting the import;
+/// only storage failures are returned as errors.
+pub fn import_chart_history(
+    storage: &Storage,
+    contents: &str,
+) -> Result<ImportReport, StorageError> {
+    let mut report = ImportReport::default();
+
+    let store = match serde_json::from_str::<Value>(contents) {
+        Ok(Value::Object(store)) => store,
+        Ok(_) => {
+            report
+                .issues
+                .push(ImportIssue::new(None, "Chart store is not a JSON object"));
+            return Ok(report);
+        }
+        Err(e) => {
+            report.issues.push(ImportIssue::new(
+                None,
+                format!("Chart store is not valid JSON: {}", e),
+            ));
+            return Ok(report);
+        }
+    };
+
+    let chart_ids: Vec<String> = match store.get("chart_ids").cloned().map(serde_json::from_value) {
+        Some(Ok(ids)) => ids,
+        Some(Err(e)) => {
+            report.issues.push(ImportIssue::new(
+                None,
+                format!("`chart_ids` is not a list of strings: {}", e),
+            ));
+            return Ok(report);
+        }
+        None => {
+            report.issues.push(ImportIssue::new(
+                None,
+                "Chart store has no `chart_ids` list, so there is nothing to import",
+            ));
+            return Ok(report);
+        }
+    };
+
+    for id in &chart_ids {
+        let Some(entry) = store.get(id) else {
+            report.issues.push(ImportIssue::new(
+                Some(id),
+                "Listed in `chart_ids` but missing from the store",
+            ));
+            continue;
+        };
+
+        let (session, segments) = match parse_chart(id, entry.clone()) {
+            Ok(parsed) => parsed,
+            Err(message) => {
+                report.issues.push(ImportIssue::new(Some(id), message));
+                continue;
+            }
+        };
+
+        if storage.import_session(&session, &segments)? {
+            report.imported += 1;
+        } else {
+            report.already_present += 1;
+        }
+    }
+
+    Ok(report)
+}
+
+fn parse_chart(id: &str, entry: Value) -> Result<(Session, Vec<ActivitySegment>), String> {
+    let chart: StoredChart =
+        serde_json::from_value(entry).map_err(|e| format!("Invalid chart entry: {}", e))?;
+
+    if chart.id != id {
+        return Err(format!(
+            "Stored under `{}` but its id is `{}`",
+            id, chart.id
+        ));
+    }
+
+    let started_on = DateTime::parse_from_rfc3339(&chart.session_started_on)
+        .map_err(|e| {
+            format!(
+                "Invalid sessionStartedOn `{}`: {}",
+                chart.session_started_on, e
+            )
+        })?
+        .timestamp_millis();
+
+    if chart.pomodoro_length_in_seconds < 0.0 || chart.break_length_in_seconds < 0.0 {
+        return Err("Session lengths must not be negative".to_string());
+    }
+
+    let mut segments = Vec::new();
+    for (class, ranges) in chart.chart_data {
+        for StoredTitleRange { range, title } in ranges {
+            let (start, end) = range;
+            if !start.is_finite() || !end.is_finite() || start < 0.0 || end < start {
+                return Err(format!(
+                    "Invalid range [{}, {}] for `{}` in {}",
+                    start, end, title, class
+                ));
+            }
+
+            segments.push(ActivitySegment {
+                session_id: chart.id.clone(),
+                class: class.clone(),
+                title,
+                start: started_on + (start * 1000.0) as i64,
+                end: started_on + (end * 1000.0) as i64,
+                state: SegmentState::Active,
+                category: None,
+                productivity: None,
+                fields: TitleFields::default(),
+                domain: None,
+                raw_title: None,
+            });
+        }
+    }
+    segments.sort_by_key(|segment| segment.start);
+
+    let session = Session {
+        id: chart.id,
+        pomodoro_length_in_seconds: chart.pomodoro_length_in_seconds as i64,
+        break_length_in_seconds: chart.break_length_in_seconds as i64,
+        session_started_on: started_on,
+        number_of_sessions: None,
+    };
+
+    Ok((session, segments))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::SegmentFilter;
+
+    const CHART_STORE: &str = r#"{
+        "chart_ids": ["a", "b", "missing", "broken"],
+        "a": {
+            "id": "a",
+            "chartData": {
+                "Code": [{ "range": [0, 60], "title": "main.rs" }],
+                "Safari": [{ "range": [60, 90], "title": "Docs" }]
+            },
+            "pomodoroLengthInSeconds": 1500,
+            "breakLengthInSeconds": 300,
+            "sessionStartedOn": "2024-10-05T09:00:00.000Z"
+        },
+        "b": {
+            "id": "b",
+            "chartData": {},
+            "pomodoroLengthInSeconds": 3000,
+            "sessionStartedOn": "2024-10-06T09:00:00.000Z"
+        },
+        "broken": {
+            "id": "broken",
+            "chartData": { "Code": [{ "range": [10], "title": "main.rs" }] },
+            "pomodoroLengthInSeconds": 1500,
+            "sessionStartedOn": "2024-10-07T09:00:00.000Z"
+        }
+    }"#;
+
+    #[test]
+    fn imports_valid_sessions_and_reports_the_rest() {
+        let storage = Storage::open_in_memory().unwrap();
+
+        let report = import_chart_history(&storage, CHART_STORE).unwrap();
+
+        assert_eq!(report.imported, 2);
+        assert_eq!(report.already_present, 0);
+        assert_eq!(
+            report
+                .issues
+                .iter()
+                .map(|issue| issue.id.as_deref())
+                .collect::<Vec<_>>(),
+            vec![Some("missing"), Some("broken")]
+        );
+
+        let started_on = 1_728_118_800_000;
+        let segments = storage
+            .query_segments(&SegmentFilter {
+                session_id: Some("a".to_string()),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(
+            segments
+                .iter()
+                .map(|segment| (segment.class.as_str(), segment.start, segment.end))
+                .collect::<Vec<_>>(),
+            vec![
+                ("Code", started_on, started_on + 60_000),
+                ("Safari", started_on + 60_000, started_on + 90_000),
+            ]
+        );
+
+        let sessions = storage.sessions_between(0, i64::MAX).unwrap();
+        assert_eq!(sessions[1].break_length_in_seconds, 300);
+    }
+
+    #[test]
+    fn importing_twice_is_a_no_op() {
+        let storage = Storage::open_in_memory().unwrap();
+
+        import_chart_history(&storage, CHART_STORE).unwrap();
+        let report = import_chart_history(&storage, CHART_STORE).unwrap();
+
+        assert_eq!(report.imported, 0);
+        assert_eq!(report.already_present, 2);
+        assert_eq!(
+            storage
+                .query_segments(&SegmentFilter::default())
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn unreadable_stores_are_reported_not_raised() {
+        let storage = Storage::open_in_memory().unwrap();
+
+        for contents in ["{ not json", "[]", r#"{ "a": {} }"#] {
+            let report = import_chart_history(&storage, contents).unwrap();
+
+            assert_eq!(report.imported, 0);
+            assert_eq!(report.issues.len(), 1, "{}", contents);
+            assert_eq!(report.issues[0].id, None);
+        }
+    }
+}
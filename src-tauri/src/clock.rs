@@ -0,0 +1,68 @@
+use std::time::Instant;
+
+use crate::activity::now_millis;
+
+/// Source of monotonic time, injectable so timing logic can be tested without
+/// waiting on real timers.
+pub trait Clock: Send + 'static {
+    fn now(&self) -> Instant;
+}
+
+/// Source of wall-clock time in unix milliseconds. `Instant` stands still
+/// while a Mac sleeps; this doesn't, so deadlines set on it pass during sleep
+/// as they would on the wall.
+pub trait WallClock: Send + 'static {
+    fn now_millis(&self) -> i64;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+impl WallClock for SystemClock {
+    fn now_millis(&self) -> i64 {
+        now_millis()
+    }
+}
+
+/// A clock that only moves when told to. Clones share the same time.
+#[cfg(test)]
+#[derive(Clone)]
+pub struct ManualClock {
+    now: std::sync::Arc<std::sync::Mutex<Instant>>,
+    /// The `Instant` wall-clock time counts from, at unix time zero.
+    epoch: Instant,
+}
+
+#[cfg(test)]
+impl ManualClock {
+    pub fn new() -> Self {
+        let epoch = Instant::now();
+        ManualClock {
+            now: std::sync::Arc::new(std::sync::Mutex::new(epoch)),
+            epoch,
+        }
+    }
+
+    pub fn advance(&self, by: std::time::Duration) {
+        *self.now.lock().unwrap() += by;
+    }
+}
+
+#[cfg(test)]
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        *self.now.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+impl WallClock for ManualClock {
+    fn now_millis(&self) -> i64 {
+        self.now().duration_since(self.epoch).as_millis() as i64
+    }
+}
@@ -0,0 +1,216 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::Instant,
+};
+use tokio::signal::unix::{signal, SignalKind};
+use uuid::Uuid;
+
+use crate::model::{AppError, Context};
+use crate::rpc::{client, server, Call, RpcError};
+use crate::rules::{RuleSet, RulesState};
+use crate::storage::Storage;
+use crate::stream::manager::StreamManager;
+use crate::stream::normalize::{NormalizerState, NormalizingSource, TitleNormalizer};
+use crate::stream::stream_utils;
+use crate::window_info::cached_probe::ProbeStats;
+use crate::window_info::macos_window_info::MacOSProber;
+use crate::{logging, paths};
+
+/// The headless tracker: window polling, idle detection and persistence, plus
+/// the socket API, without Tauri or a webview.
+struct Daemon {
+    storage: Arc<Storage>,
+    rules: RulesState,
+    normalizer: NormalizerState,
+    probe_stats: Arc<ProbeStats>,
+    stream: Arc<StreamManager>,
+    /// Session being tracked, if any. Held while tracking starts or stops.
+    session: tokio::sync::Mutex<Option<String>>,
+}
+
+impl Daemon {
+    /// Tracks under `session_id`, moving over from the running session if it
+    /// differs, or carries on with the running session if none is given.
+    /// Returns the session tracked.
+    async fn start(&self, session_id: Option<String>) -> Result<String, AppError> {
+        let mut session = self.session.lock().await;
+        if let Some(running) = session.take() {
+            if self.stream.is_running().await {
+                if session_id.as_ref().is_none_or(|id| *id == running) {
+                    *session = Some(running.clone());
+                    return Ok(running);
+                }
+                self.stream
+                    .stop()
+                    .await
+                    .context("Unable to stop the stream")?;
+            }
+        }
+
+        let session_id = session_id.unwrap_or_else(|| Uuid::new_v4().to_string());
+        let (recorder, idle) =
+            stream_utils::live_recorder(&self.storage, &self.rules, session_id.clone())?;
+        let schedule = stream_utils::live_schedule(&self.storage)?;
+        let source = NormalizingSource::new(
+            stream_utils::live_source(MacOSProber::headless(), self.probe_stats.clone()),
+            self.normalizer.clone(),
+        );
+
+        stream_utils::start(&self.stream, source, idle, schedule).await?;
+        stream_utils::subscribe(&self.stream, recorder).await?;
+        *session = Some(session_id.clone());
+        Ok(session_id)
+    }
+
+    /// Stops tracking and waits for the last segment to be written.
+    async fn stop(&self) -> Result<(), AppError> {
+        let mut session = self.session.lock().await;
+        *session = None;
+        self.stream
+            .stop()
+            .await
+            .context("Unable to stop the stream")
+    }
+
+    async fn execute(&self, call: Call) -> Result<Value, RpcError> {
+        match call {
+            Call::CurrentWindow => to_value(Ok(self.stream.current_window())),
+            Call::PomodoroState => Err(RpcError::new(
+                RpcError::SERVER_ERROR,
+                "The daemon does not run a pomodoro timer; open the app",
+            )),
+            Call::StartStream(params) => {
+                if params.record_to.is_some() {
+                    return Err(RpcError::new(
+                        RpcError::INVALID_PARAMS,
+                        "The daemon does not record traces",
+                    ));
+                }
+                to_value(self.start(params.session_id).await)
+            }
+            Call::StopStream => to_value(self.stop().await),
+            Call::StreamRunning => to_value(Ok(self.stream.is_running().await)),
+            Call::QueryActivity(filter) => to_value(
+                self.storage
+                    .query_segments(&filter)
+                    .context("Unable to read activity"),
+            ),
+            Call::QuerySessions(range) => to_value(
+                self.storage
+                    .sessions_between(range.from, range.to)
+                    .context("Unable to read sessions"),
+            ),
+            Call::ReloadRules => {
+                server::reload_rules(&self.storage, &self.rules)?;
+                Ok(Value::Null)
+            }
+            Call::ProbeCounters => to_value(Ok(self.probe_stats.counters(Instant::now()))),
+            Call::ReloadNormalization => {
+                server::reload_normalization(&self.storage, &self.normalizer)?;
+                Ok(Value::Null)
+            }
+            Call::Subscribe | Call::Unsubscribe(_) => Err(server::connection_only()),
+            Call::Handover => Err(RpcError::new(
+                RpcError::SERVER_ERROR,
+                "A daemon is already serving this socket",
+            )),
+        }
+    }
+}
+
+fn to_value(value: Result<impl Serialize, AppError>) -> Result<Value, RpcError> {
+    let value = value?;
+    serde_json::to_value(value).map_err(|e| RpcError::new(RpcError::SERVER_ERROR, e.to_string()))
+}
+
+/// Runs the headless daemon until SIGTERM or SIGINT, as launchd does with
+/// `locus --headless`. The app attaches to it when opened.
+pub fn run_headless() -> Result<(), Box<dyn Error>> {
+    let data_dir = paths::app_data_dir().ok_or("Unable to resolve the app data folder")?;
+    let _logs = logging::init(
+        &paths::app_log_dir().ok_or("Unable to resolve the log folder")?,
+        logging::DAEMON_LOG_PREFIX,
+    )?;
+    fs::create_dir_all(&data_dir)?;
+
+    // Probes run on the blocking pool, so two workers are plenty for the
+    // stream and the socket
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(2)
+        .enable_all()
+        .build()?;
+
+    runtime.block_on(serve(data_dir))
+}
+
+async fn serve(data_dir: PathBuf) -> Result<(), Box<dyn Error>> {
+    let socket_path = server::socket_path(&data_dir)?;
+    let session_id = if server::is_listening(&socket_path) {
+        take_over(&socket_path).await?
+    } else {
+        None
+    };
+    let listener = server::bind(&socket_path)?;
+
+    let storage = Arc::new(Storage::open(data_dir.join(paths::DATABASE_FILE_NAME))?);
+    let rules = RuleSet::load(&storage).unwrap_or_else(|e| {
+        tracing::warn!(error = %e, "Ignoring saved rules");
+        RuleSet::default()
+    });
+    let normalizer = TitleNormalizer::load(&storage).unwrap_or_else(|e| {
+        tracing::warn!(error = %e, "Ignoring saved title normalization");
+        TitleNormalizer::default()
+    });
+    let daemon = Arc::new(Daemon {
+        storage,
+        rules: Arc::new(RwLock::new(rules)),
+        normalizer: Arc::new(RwLock::new(normalizer)),
+        probe_stats: Arc::new(ProbeStats::default()),
+        stream: Arc::new(StreamManager::default()),
+        session: Default::default(),
+    });
+
+    daemon.start(session_id).await?;
+
+    let api = {
+        let daemon = daemon.clone();
+        tokio::spawn(server::serve(
+            listener,
+            daemon.stream.clone(),
+            move |call| {
+                let daemon = daemon.clone();
+                async move { daemon.execute(call).await }
+            },
+        ))
+    };
+
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    tokio::select! {
+        _ = terminate.recv() => {}
+        _ = interrupt.recv() => {}
+    }
+
+    api.abort();
+    if let Err(e) = daemon.stop().await {
+        tracing::error!(error = %e, "Unable to stop tracking");
+    }
+    fs::remove_file(&socket_path)?;
+
+    Ok(())
+}
+
+/// Takes the socket over from an open app, and with it the session the app
+/// was tracking, if any, to carry on with.
+async fn take_over(socket_path: &Path) -> Result<Option<String>, Box<dyn Error>> {
+    let answer = client::request_async(socket_path, "handover", Value::Null)
+        .await?
+        .map_err(|e| e.message)?;
+
+    Ok(serde_json::from_value(answer)?)
+}
@@ -0,0 +1,492 @@
+use serde::Serialize;
+use std::io;
+use thiserror::Error;
+use ts_rs::TS;
+
+use crate::events::{self, AppEvent};
+use crate::window_info::macos_window_info::osascript;
+
+/// A macOS privacy permission the tracker needs.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, TS)]
+#[serde(rename_all = "camelCase")]
+pub enum Permission {
+    /// Reading window titles through System Events.
+    Accessibility,
+    /// Sending Apple events to System Events and browsers; granted per app.
+    Automation,
+    ScreenRecording,
+}
+
+/// Whether `permission` is granted, for `target` if it is per app.
+#[derive(Serialize, Debug, Clone, PartialEq, TS)]
+pub struct PermissionStatus {
+    pub permission: Permission,
+    pub granted: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    pub target: Option<String>,
+}
+
+/// Why an AppleScript probe failed, as read from `osascript`'s stderr.
+#[derive(Error, Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum ScriptFailure {
+    #[error("{permission:?} permission denied")]
+    PermissionDenied { permission: Permission },
+    #[error("The app can't be scripted this way")]
+    NotScriptable,
+    #[error("The app isn't running")]
+    AppNotRunning,
+    /// The app answered but has no window to ask about.
+    #[error("The app has no window")]
+    NoWindow,
+    #[error("The script timed out")]
+    Timeout,
+    #[error("{message}")]
+    Other { message: String },
+}
+
+/// The `(-1743)` style code AppleScript ends its error messages with.
+fn error_code(stderr: &str) -> Option<i32> {
+    let stderr = stderr.trim().strip_suffix(')')?;
+    let (_, code) = stderr.rsplit_once('(')?;
+    code.parse().ok()
+}
+
+pub fn classify_osascript_error(stderr: &str) -> ScriptFailure {
+    let lower = stderr.to_lowercase();
+
+    // Some codes are shared, so the well-known messages are tried first
+    if lower.contains("not authorized to send apple events") {
+        return ScriptFailure::PermissionDenied {
+            permission: Permission::Automation,
+        };
+    }
+    if lower.contains("assistive access") {
+        return ScriptFailure::PermissionDenied {
+            permission: Permission::Accessibility,
+        };
+    }
+
+    match error_code(stderr) {
+        Some(-1743) => ScriptFailure::PermissionDenied {
+            permission: Permission::Automation,
+        },
+        Some(-25211) => ScriptFailure::PermissionDenied {
+            permission: Permission::Accessibility,
+        },
+        Some(-1712) => ScriptFailure::Timeout,
+        Some(-600) => ScriptFailure::AppNotRunning,
+        Some(-1719) | Some(-1728) => ScriptFailure::NoWindow,
+        Some(-1708) | Some(-2740) | Some(-2741) | Some(-10000) => ScriptFailure::NotScriptable,
+        _ if lower.contains("timed out") => ScriptFailure::Timeout,
+        _ => ScriptFailure::Other {
+            message: stderr.trim().to_string(),
+        },
+    }
+}
+
+/// The ways the tracker reads the Mac, abstracted so diagnostics can be
+/// tested without one.
+pub trait Probes {
+    /// The frontmost app through NSWorkspace, which needs no permission.
+    fn frontmost_app(&mut self) -> Result<String, String>;
+    fn run_script(&mut self, script: &str) -> Result<String, ScriptFailure>;
+    /// Whether the system says the permission is granted, if it can tell.
+    fn preflight(&mut self, permission: Permission) -> Option<bool>;
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "status", rename_all = "camelCase")]
+pub enum CheckStatus {
+    Working,
+    Failed { failure: ScriptFailure },
+    Skipped { reason: String },
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Check {
+    pub name: String,
+    #[serde(flatten)]
+    pub status: CheckStatus,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Diagnostics {
+    pub checks: Vec<Check>,
+    pub permissions: Vec<PermissionStatus>,
+}
+
+impl Diagnostics {
+    /// Permissions known to be missing.
+    pub fn missing(&self) -> impl Iterator<Item = &PermissionStatus> {
+        self.permissions.iter().filter(|status| !status.granted)
+    }
+}
+
+/// Browsers by the name their process runs under, with a script reading the
+/// active tab's URL. Firefox has no scripting dictionary for tabs.
+const BROWSER_SCRIPTS: &[(&str, Option<&str>)] = &[
+    (
+        "Safari",
+        Some(r#"tell application "Safari" to get URL of front document"#),
+    ),
+    (
+        "Google Chrome",
+        Some(r#"tell application "Google Chrome" to get URL of active tab of front window"#),
+    ),
+    (
+        "Brave Browser",
+        Some(r#"tell application "Brave Browser" to get URL of active tab of front window"#),
+    ),
+    (
+        "Arc",
+        Some(r#"tell application "Arc" to get URL of active tab of front window"#),
+    ),
+    (
+        "Microsoft Edge",
+        Some(r#"tell application "Microsoft Edge" to get URL of active tab of front window"#),
+    ),
+    ("firefox", None),
+];
+
+const FRONT_PROCESS: &str = r#"tell application "System Events" to get name of first application process whose frontmost is true"#;
+const FRONT_WINDOW: &str = r#"tell application "System Events" to get name of front window of (first application process whose frontmost is true)"#;
+const RUNNING_APPS: &str =
+    r#"tell application "System Events" to get name of every application process"#;
+
+fn check(name: &str, result: Result<(), ScriptFailure>) -> Check {
+    Check {
+        name: name.to_string(),
+        status: match result {
+            Ok(()) => CheckStatus::Working,
+            Err(failure) => CheckStatus::Failed { failure },
+        },
+    }
+}
+
+fn skipped(name: &str, reason: &str) -> Check {
+    Check {
+        name: name.to_string(),
+        status: CheckStatus::Skipped {
+            reason: reason.to_string(),
+        },
+    }
+}
+
+/// Whether a probe got through. An app with no window to report still
+/// answered, so scripting it is allowed.
+fn answered(result: Result<String, ScriptFailure>) -> Result<(), ScriptFailure> {
+    match result {
+        Ok(_) | Err(ScriptFailure::NoWindow) => Ok(()),
+        Err(failure) => Err(failure),
+    }
+}
+
+fn denied(check: &Check, permission: Permission) -> bool {
+    check.status
+        == CheckStatus::Failed {
+            failure: ScriptFailure::PermissionDenied { permission },
+        }
+}
+
+/// Runs every probe the tracker depends on and works out which permissions
+/// are missing.
+pub fn diagnose(probes: &mut impl Probes) -> Diagnostics {
+    let mut checks = vec![check(
+        "nsworkspace",
+        probes
+            .frontmost_app()
+            .map(|_| ())
+            .map_err(|message| ScriptFailure::Other { message }),
+    )];
+
+    let system_events = probes.run_script(FRONT_PROCESS).map(|_| ());
+    let system_events_denied = matches!(
+        system_events,
+        Err(ScriptFailure::PermissionDenied {
+            permission: Permission::Automation
+        })
+    );
+    checks.push(check("systemEvents", system_events));
+
+    checks.push(check(
+        "windowTitles",
+        answered(probes.run_script(FRONT_WINDOW)),
+    ));
+
+    let mut permissions = vec![PermissionStatus {
+        permission: Permission::Automation,
+        granted: !system_events_denied,
+        target: Some("System Events".to_string()),
+    }];
+
+    let running = probes.run_script(RUNNING_APPS);
+    for (app, script) in BROWSER_SCRIPTS {
+        let name = format!("browser:{}", app);
+        let is_running = match &running {
+            Ok(names) => names.split(',').any(|name| name.trim() == *app),
+            Err(_) => {
+                checks.push(skipped(&name, "Unable to list running apps"));
+                continue;
+            }
+        };
+
+        let browser = match script {
+            _ if !is_running => skipped(&name, "Not running"),
+            None => check(&name, Err(ScriptFailure::NotScriptable)),
+            Some(script) => {
+                let browser = check(&name, answered(probes.run_script(script)));
+                permissions.push(PermissionStatus {
+                    permission: Permission::Automation,
+                    granted: !denied(&browser, Permission::Automation),
+                    target: Some(app.to_string()),
+                });
+                browser
+            }
+        };
+        checks.push(browser);
+    }
+
+    let titles_denied = checks
+        .iter()
+        .any(|check| denied(check, Permission::Accessibility));
+    permissions.push(PermissionStatus {
+        permission: Permission::Accessibility,
+        granted: probes
+            .preflight(Permission::Accessibility)
+            .unwrap_or(!titles_denied),
+        target: None,
+    });
+    if let Some(granted) = probes.preflight(Permission::ScreenRecording) {
+        permissions.push(PermissionStatus {
+            permission: Permission::ScreenRecording,
+            granted,
+            target: None,
+        });
+    }
+
+    Diagnostics {
+        checks,
+        permissions,
+    }
+}
+
+/// Tells the webview about each permission's status.
+pub fn emit_status(app: &tauri::AppHandle, diagnostics: &Diagnostics) {
+    for status in &diagnostics.permissions {
+        events::emit(app, AppEvent::PermissionStatus(status.clone()));
+    }
+}
+
+/// Probes the live Mac.
+pub struct MacOSProbes;
+
+#[cfg(target_os = "macos")]
+#[link(name = "ApplicationServices", kind = "framework")]
+extern "C" {
+    fn AXIsProcessTrusted() -> bool;
+}
+
+#[cfg(target_os = "macos")]
+#[link(name = "CoreGraphics", kind = "framework")]
+extern "C" {
+    fn CGPreflightScreenCaptureAccess() -> bool;
+}
+
+impl Probes for MacOSProbes {
+    fn frontmost_app(&mut self) -> Result<String, String> {
+        #[cfg(target_os = "macos")]
+        return crate::window_info::native_macos::get_native_window_info()
+            .map(|window| window.class)
+            .map_err(|e| e.to_string());
+
+        #[cfg(not(target_os = "macos"))]
+        Err("NSWorkspace is only available on macOS".to_string())
+    }
+
+    fn run_script(&mut self, script: &str) -> Result<String, ScriptFailure> {
+        let output = osascript(script).map_err(|e| match e.kind() {
+            io::ErrorKind::TimedOut => ScriptFailure::Timeout,
+            _ => ScriptFailure::Other {
+                message: e.to_string(),
+            },
+        })?;
+
+        if output.status.success() {
+            Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+        } else {
+            Err(classify_osascript_error(&String::from_utf8_lossy(
+                &output.stderr,
+            )))
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    fn preflight(&mut self, permission: Permission) -> Option<bool> {
+        match permission {
+            Permission::Accessibility => Some(unsafe { AXIsProcessTrusted() }),
+            Permission::ScreenRecording => Some(unsafe { CGPreflightScreenCaptureAccess() }),
+            Permission::Automation => None,
+        }
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    fn preflight(&mut self, _permission: Permission) -> Option<bool> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn osascript_errors_are_classified() {
+        let fixtures = [
+            (
+                "36:120: execution error: Not authorized to send Apple events to System Events. (-1743)",
+                ScriptFailure::PermissionDenied {
+                    permission: Permission::Automation,
+                },
+            ),
+            (
+                "execution error: System Events got an error: osascript is not allowed assistive access. (-25211)",
+                ScriptFailure::PermissionDenied {
+                    permission: Permission::Accessibility,
+                },
+            ),
+            (
+                "execution error: Google Chrome got an error: AppleEvent timed out. (-1712)",
+                ScriptFailure::Timeout,
+            ),
+            (
+                "execution error: Google Chrome got an error: Can’t get window 1. Invalid index. (-1719)",
+                ScriptFailure::NoWindow,
+            ),
+            (
+                "execution error: Safari got an error: Can’t get document 1. (-1728)",
+                ScriptFailure::NoWindow,
+            ),
+            (
+                "execution error: Preview got an error: document 1 doesn’t understand the “get” message. (-1708)",
+                ScriptFailure::NotScriptable,
+            ),
+            (
+                "0:41: syntax error: Expected end of line but found identifier. (-2741)",
+                ScriptFailure::NotScriptable,
+            ),
+            (
+                "execution error: System Events got an error: osascript is not allowed assistive access. (-1719)",
+                ScriptFailure::PermissionDenied {
+                    permission: Permission::Accessibility,
+                },
+            ),
+            (
+                "execution error: Application isn’t running. (-600)",
+                ScriptFailure::AppNotRunning,
+            ),
+            (
+                "Not authorized to send Apple events to Arc.\n",
+                ScriptFailure::PermissionDenied {
+                    permission: Permission::Automation,
+                },
+            ),
+            (
+                "  something else broke \n",
+                ScriptFailure::Other {
+                    message: "something else broke".to_string(),
+                },
+            ),
+        ];
+
+        for (stderr, expected) in fixtures {
+            assert_eq!(classify_osascript_error(stderr), expected, "{}", stderr);
+        }
+    }
+
+    /// Answers scripts from a table; unknown scripts fail as not scriptable.
+    struct FakeProbes {
+        scripts: HashMap<&'static str, Result<&'static str, ScriptFailure>>,
+    }
+
+    impl Probes for FakeProbes {
+        fn frontmost_app(&mut self) -> Result<String, String> {
+            Ok("Google Chrome".to_string())
+        }
+
+        fn run_script(&mut self, script: &str) -> Result<String, ScriptFailure> {
+            self.scripts
+                .get(script)
+                .cloned()
+                .unwrap_or(Err(ScriptFailure::NotScriptable))
+                .map(str::to_string)
+        }
+
+        fn preflight(&mut self, _permission: Permission) -> Option<bool> {
+            None
+        }
+    }
+
+    #[test]
+    fn diagnostics_name_the_missing_permission() {
+        let chrome = BROWSER_SCRIPTS[1].1.unwrap();
+        let mut probes = FakeProbes {
+            scripts: HashMap::from([
+                (FRONT_PROCESS, Ok("Google Chrome")),
+                (
+                    FRONT_WINDOW,
+                    Err(ScriptFailure::PermissionDenied {
+                        permission: Permission::Accessibility,
+                    }),
+                ),
+                (RUNNING_APPS, Ok("Finder, Google Chrome, firefox")),
+                (
+                    chrome,
+                    Err(ScriptFailure::PermissionDenied {
+                        permission: Permission::Automation,
+                    }),
+                ),
+            ]),
+        };
+
+        let diagnostics = diagnose(&mut probes);
+        let status = |name: &str| {
+            diagnostics
+                .checks
+                .iter()
+                .find(|check| check.name == name)
+                .map(|check| check.status.clone())
+                .unwrap()
+        };
+
+        assert_eq!(status("nsworkspace"), CheckStatus::Working);
+        assert_eq!(status("systemEvents"), CheckStatus::Working);
+        assert_eq!(
+            status("browser:firefox"),
+            CheckStatus::Failed {
+                failure: ScriptFailure::NotScriptable
+            }
+        );
+        assert!(matches!(
+            status("browser:Safari"),
+            CheckStatus::Skipped { .. }
+        ));
+        assert_eq!(
+            diagnostics.missing().cloned().collect::<Vec<_>>(),
+            vec![
+                PermissionStatus {
+                    permission: Permission::Automation,
+                    granted: false,
+                    target: Some("Google Chrome".to_string()),
+                },
+                PermissionStatus {
+                    permission: Permission::Accessibility,
+                    granted: false,
+                    target: None,
+                },
+            ]
+        );
+    }
+}
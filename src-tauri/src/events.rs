@@ -0,0 +1,120 @@
+pub mod typescript;
+
+use serde::Serialize;
+use tauri::{Emitter, EventTarget};
+use ts_rs::TS;
+
+use crate::activity::now_millis;
+use crate::diagnostics::PermissionStatus;
+use crate::model::{ActiveWindow, AppError, ErrorInfo, IdleEvent};
+use crate::pomodoro::{PhaseChange, PomodoroSnapshot};
+use crate::rules::Productivity;
+use crate::window_info::macos_window_info::StreamEvent;
+
+/// Bumped whenever a payload changes in a way old listeners can't read.
+pub const EVENT_SCHEMA_VERSION: u32 = 1;
+
+/// The one Tauri event every backend message is sent as.
+pub const EVENT_NAME: &str = "locus-event";
+
+/// Everything the backend tells the webview about, serialized as
+/// `{ "version": 1, "type": "windowChanged", "payload": { ... } }`.
+///
+/// The TypeScript side is derived from these types; see
+/// `typescript::bindings`.
+#[derive(Serialize, Debug, Clone, PartialEq, TS)]
+#[serde(tag = "type", content = "payload", rename_all = "camelCase")]
+pub enum AppEvent {
+    WindowChanged(ActiveWindow),
+    IdleStarted(IdleEvent),
+    IdleEnded(IdleEvent),
+    PomodoroPhaseChanged(PhaseChange),
+    /// Sent every second while a focus or break phase runs.
+    PomodoroTick(PomodoroSnapshot),
+    /// The window that just became active matched a user rule.
+    RuleMatched(RuleMatch),
+    /// A failure in background work that no command is waiting on.
+    Error(ErrorInfo),
+    /// Whether a permission the tracker needs is granted; see `diagnostics`.
+    PermissionStatus(PermissionStatus),
+}
+
+impl From<&StreamEvent> for AppEvent {
+    fn from(event: &StreamEvent) -> Self {
+        match event {
+            StreamEvent::Window(window_info) => AppEvent::WindowChanged(window_info.clone()),
+            StreamEvent::IdleStart { idle_for } => AppEvent::IdleStarted(IdleEvent {
+                at: now_millis() - idle_for.as_millis() as i64,
+            }),
+            StreamEvent::IdleEnd => AppEvent::IdleEnded(IdleEvent { at: now_millis() }),
+            StreamEvent::Error(error) => AppEvent::Error(error.clone()),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Envelope<'a> {
+    version: u32,
+    #[serde(flatten)]
+    event: &'a AppEvent,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct RuleMatch {
+    pub class: String,
+    pub title: String,
+    pub rule: String,
+    pub category: String,
+    pub productivity: Productivity,
+}
+
+/// The JSON an event is sent as, with its schema version.
+pub fn to_json(event: &AppEvent) -> serde_json::Value {
+    serde_json::to_value(Envelope {
+        version: EVENT_SCHEMA_VERSION,
+        event,
+    })
+    .expect("events serialize")
+}
+
+/// Sends `event` to the webview.
+pub fn emit(app: &tauri::AppHandle, event: AppEvent) {
+    let json = to_json(&event);
+    if let Err(e) = app.emit_to(EventTarget::app(), EVENT_NAME, &json) {
+        tracing::warn!(event = %json["type"], error = %e, "Unable to emit an event");
+    }
+}
+
+/// Tells the webview about a failure no command is waiting on.
+pub fn emit_error(app: &tauri::AppHandle, error: &AppError) {
+    tracing::warn!(code = %error.code(), error = %error, "Background task failed");
+    emit(app, AppEvent::Error(ErrorInfo::from(error)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn events_carry_their_version_type_and_payload() {
+        assert_eq!(
+            to_json(&AppEvent::WindowChanged(ActiveWindow {
+                class: "Code".to_string(),
+                title: "main.rs".to_string(),
+                url: None,
+                raw_title: None,
+            })),
+            json!({
+                "version": EVENT_SCHEMA_VERSION,
+                "type": "windowChanged",
+                "payload": { "class": "Code", "title": "main.rs" }
+            })
+        );
+        assert_eq!(
+            to_json(&AppEvent::IdleEnded(IdleEvent { at: 42 })),
+            json!({ "version": 1, "type": "idleEnded", "payload": { "at": 42 } })
+        );
+    }
+}
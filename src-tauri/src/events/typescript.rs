@@ -0,0 +1,91 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use ts_rs::{TypeVisitor, TS};
+
+use crate::events::{AppEvent, EVENT_NAME, EVENT_SCHEMA_VERSION};
+
+/// Where the generated bindings live, relative to the crate.
+pub const BINDINGS_PATH: &str = "../src/model/LocusEvents.ts";
+
+/// Collects the declaration of every type an event's payload refers to, by
+/// name, so each is written once and in a stable order.
+#[derive(Default)]
+struct Declarations(BTreeMap<String, String>);
+
+impl TypeVisitor for Declarations {
+    fn visit<T: TS + 'static + ?Sized>(&mut self) {
+        // Builtins and wrappers have no declaration of their own
+        if T::output_path().is_none() || self.0.contains_key(&T::name()) {
+            return;
+        }
+        self.0.insert(T::name(), T::decl());
+        T::visit_dependencies(self);
+    }
+}
+
+/// The contents of the generated TypeScript file, derived from `AppEvent`
+/// and the types it carries.
+pub fn bindings() -> String {
+    let mut declarations = Declarations::default();
+    declarations.visit::<AppEvent>();
+
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "// Generated from the Rust event types by src-tauri/src/events/typescript.rs;\n\
+         // do not edit. Regenerate with `LOCUS_UPDATE_BINDINGS=1 cargo test bindings`.\n"
+    );
+    let _ = writeln!(
+        out,
+        "export const EVENT_SCHEMA_VERSION = {};",
+        EVENT_SCHEMA_VERSION
+    );
+    let _ = writeln!(out, "export const EVENT_NAME = \"{}\";", EVENT_NAME);
+
+    for declaration in declarations.0.values() {
+        let _ = writeln!(out, "\nexport {}", declaration);
+    }
+
+    let _ = writeln!(
+        out,
+        "\nexport type LocusEvent = {{ version: typeof EVENT_SCHEMA_VERSION }} & {};",
+        AppEvent::name()
+    );
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::to_json;
+    use crate::model::{ActiveWindow, IdleEvent};
+
+    #[test]
+    fn bindings_declare_every_payload_type_once() {
+        let bindings = bindings();
+
+        for name in ["AppEvent", "ActiveWindow", "PhaseChange", "Phase"] {
+            let declaration = format!("export type {} =", name);
+            assert_eq!(bindings.matches(&declaration).count(), 1, "{}", name);
+        }
+        assert!(bindings.contains("url?: string"));
+
+        let json = to_json(&AppEvent::IdleEnded(IdleEvent { at: 1 }));
+        assert!(bindings.contains(&format!("\"type\": {}", json["type"])));
+        let json = to_json(&AppEvent::WindowChanged(ActiveWindow::none()));
+        assert!(bindings.contains(&format!("\"type\": {}", json["type"])));
+    }
+
+    /// Fails when the checked-in bindings are stale. Run with
+    /// `LOCUS_UPDATE_BINDINGS=1` to rewrite them.
+    #[test]
+    fn bindings_are_up_to_date() {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join(BINDINGS_PATH);
+
+        if std::env::var_os("LOCUS_UPDATE_BINDINGS").is_some() {
+            std::fs::write(&path, bindings()).unwrap();
+        }
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), bindings());
+    }
+}
@@ -0,0 +1,295 @@
+use chrono::{DateTime, TimeZone};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    io::{self, Write},
+};
+
+use crate::activity::{ActivitySegment, SegmentState, Session};
+use crate::model::StorageError;
+use crate::rules::Productivity;
+use crate::storage::{SegmentFilter, Storage};
+
+/// Bumped when the JSON layout changes in a way readers have to handle.
+pub const EXPORT_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum ExportFormat {
+    /// One row per segment, times in the local timezone.
+    #[default]
+    Csv,
+    /// Sessions and segments as separate lists, times in unix milliseconds.
+    Json,
+}
+
+impl ExportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Json => "json",
+        }
+    }
+}
+
+/// A segment as exported; `session_id` refers to one of the export's sessions.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportedSegment {
+    pub session_id: String,
+    pub class: String,
+    pub title: String,
+    pub url: Option<String>,
+    pub domain: Option<String>,
+    pub state: SegmentState,
+    pub category: Option<String>,
+    pub productivity: Option<Productivity>,
+    pub start: i64,
+    pub end: i64,
+    pub duration_millis: i64,
+}
+
+impl From<&ActivitySegment> for ExportedSegment {
+    fn from(segment: &ActivitySegment) -> Self {
+        ExportedSegment {
+            session_id: segment.session_id.clone(),
+            class: segment.class.clone(),
+            title: segment.title.clone(),
+            url: segment.fields.url.clone(),
+            domain: segment.domain.clone(),
+            state: segment.state,
+            category: segment.category.clone(),
+            productivity: segment.productivity,
+            start: segment.start,
+            end: segment.end,
+            duration_millis: segment.end - segment.start,
+        }
+    }
+}
+
+/// Everything recorded in `[from, to)`. Sessions are listed once however many
+/// segments they have, so every segment's `session_id` names one of them.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Export {
+    pub version: u32,
+    pub from: i64,
+    pub to: i64,
+    pub sessions: Vec<Session>,
+    pub segments: Vec<ExportedSegment>,
+}
+
+/// Reads the segments overlapping `[from, to)`, oldest first, and their
+/// sessions.
+pub fn collect(storage: &Storage, from: i64, to: i64) -> Result<Export, StorageError> {
+    let segments = storage.query_segments(&SegmentFilter {
+        from: Some(from),
+        to: Some(to),
+        ..Default::default()
+    })?;
+
+    // Segments come oldest first, so the first one seen is a session's start
+    let mut first_starts: BTreeMap<&str, i64> = BTreeMap::new();
+    for segment in &segments {
+        first_starts
+            .entry(segment.session_id.as_str())
+            .or_insert(segment.start);
+    }
+    let mut sessions = Vec::new();
+    for (id, first_start) in first_starts {
+        sessions.push(match storage.session(id)? {
+            Some(session) => session,
+            None => untimed_session(id, first_start),
+        });
+    }
+    sessions.sort_by_key(|session| session.session_started_on);
+
+    Ok(Export {
+        version: EXPORT_VERSION,
+        from,
+        to,
+        sessions,
+        segments: segments.iter().map(ExportedSegment::from).collect(),
+    })
+}
+
+/// Stands in for a session storage has no row for, such as tracking the
+/// daemon did without a timer: no lengths, started at its first segment.
+fn untimed_session(id: &str, started_on: i64) -> Session {
+    Session {
+        id: id.to_string(),
+        pomodoro_length_in_seconds: 0,
+        break_length_in_seconds: 0,
+        session_started_on: started_on,
+        number_of_sessions: None,
+    }
+}
+
+/// Writes `export` to `out` as `format`, with CSV times read in `tz`.
+pub fn write<Tz>(export: &Export, format: ExportFormat, tz: &Tz, out: impl Write) -> io::Result<()>
+where
+    Tz: TimeZone,
+    Tz::Offset: Display,
+{
+    match format {
+        ExportFormat::Csv => write_csv(&export.segments, tz, out),
+        ExportFormat::Json => write_json(export, out),
+    }
+}
+
+pub fn write_json(export: &Export, mut out: impl Write) -> io::Result<()> {
+    serde_json::to_writer_pretty(&mut out, export)?;
+    writeln!(out)?;
+    out.flush()
+}
+
+pub fn write_csv<Tz>(segments: &[ExportedSegment], tz: &Tz, mut out: impl Write) -> io::Result<()>
+where
+    Tz: TimeZone,
+    Tz::Offset: Display,
+{
+    writeln!(
+        out,
+        "session_id,class,title,url,domain,state,category,productivity,start,end,duration_seconds"
+    )?;
+
+    for segment in segments {
+        let fields = [
+            segment.session_id.clone(),
+            segment.class.clone(),
+            segment.title.clone(),
+            segment.url.clone().unwrap_or_default(),
+            segment.domain.clone().unwrap_or_default(),
+            segment.state.as_str().to_string(),
+            segment.category.clone().unwrap_or_default(),
+            segment
+                .productivity
+                .map(|productivity| productivity.as_str().to_string())
+                .unwrap_or_default(),
+            rfc3339(segment.start, tz),
+            rfc3339(segment.end, tz),
+            (segment.duration_millis / 1000).to_string(),
+        ];
+        let row: Vec<_> = fields.iter().map(|field| csv_field(field)).collect();
+        writeln!(out, "{}", row.join(","))?;
+    }
+
+    out.flush()
+}
+
+/// Quotes `value` if it needs it, and disarms values a spreadsheet would run
+/// as a formula, which window titles and URLs are free to look like.
+fn csv_field(value: &str) -> String {
+    let value = if value.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{}", value)
+    } else {
+        value.to_string()
+    };
+
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+fn rfc3339<Tz>(millis: i64, tz: &Tz) -> String
+where
+    Tz: TimeZone,
+    Tz::Offset: Display,
+{
+    DateTime::from_timestamp_millis(millis)
+        .map(|time| time.with_timezone(tz).to_rfc3339())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::activity;
+    use chrono::FixedOffset;
+
+    fn segment(session_id: &str, title: &str, start: i64, end: i64) -> ActivitySegment {
+        activity::segment("Code", title, start, end)
+            .with_session(session_id)
+            .with_category("Development")
+            .with_productivity(Productivity::Productive)
+    }
+
+    #[test]
+    fn csv_fields_are_quoted_only_when_needed() {
+        assert_eq!(csv_field("main.rs"), "main.rs");
+        assert_eq!(csv_field("a, b"), "\"a, b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+
+    #[test]
+    fn csv_fields_that_look_like_formulas_are_disarmed() {
+        assert_eq!(csv_field("=HYPERLINK(\"x\")"), "\"'=HYPERLINK(\"\"x\"\")\"");
+        assert_eq!(csv_field("+1"), "'+1");
+        assert_eq!(csv_field("-2"), "'-2");
+        assert_eq!(csv_field("@SUM(A1)"), "'@SUM(A1)");
+        assert_eq!(csv_field("\tcmd"), "'\tcmd");
+        assert_eq!(csv_field("\rcmd"), "\"'\rcmd\"");
+        assert_eq!(csv_field("a-b"), "a-b");
+    }
+
+    #[test]
+    fn exports_list_each_session_once_in_both_formats() {
+        let storage = Storage::open_in_memory().unwrap();
+        let session = Session {
+            id: "a".to_string(),
+            pomodoro_length_in_seconds: 1800,
+            break_length_in_seconds: 300,
+            session_started_on: 0,
+            number_of_sessions: Some(1),
+        };
+        storage
+            .import_session(
+                &session,
+                &[
+                    segment("a", "main.rs", 0, 60_000),
+                    segment("a", "a, b", 60_000, 90_000),
+                ],
+            )
+            .unwrap();
+        storage
+            .append_segment(&segment("daemon", "lib.rs", 90_000, 120_000))
+            .unwrap();
+        // Outside the range
+        storage
+            .append_segment(&segment("a", "old.rs", -60_000, -1))
+            .unwrap();
+
+        let export = collect(&storage, 0, 100_000).unwrap();
+        assert_eq!(
+            export.sessions,
+            vec![session, untimed_session("daemon", 90_000)]
+        );
+        assert_eq!(export.segments.len(), 3);
+
+        let mut csv = Vec::new();
+        write(
+            &export,
+            ExportFormat::Csv,
+            &FixedOffset::east_opt(3600).unwrap(),
+            &mut csv,
+        )
+        .unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(
+            lines[2],
+            "a,Code,\"a, b\",,,active,Development,productive,\
+             1970-01-01T01:01:00+01:00,1970-01-01T01:01:30+01:00,30"
+        );
+
+        let mut json = Vec::new();
+        write(&export, ExportFormat::Json, &chrono::Utc, &mut json).unwrap();
+        let read: Export = serde_json::from_slice(&json).unwrap();
+        assert_eq!(read, export);
+        assert_eq!(read.segments[1].duration_millis, 30_000);
+    }
+}
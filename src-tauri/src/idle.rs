@@ -0,0 +1,111 @@
+pub mod macos_idle;
+#[cfg(test)]
+pub mod scripted_idle;
+
+use std::time::Duration;
+
+/// Settings key for the idle threshold, in seconds.
+pub const IDLE_THRESHOLD_SETTING: &str = "idle.thresholdSeconds";
+
+/// How long without keyboard or mouse input before the user counts as away.
+pub const DEFAULT_IDLE_THRESHOLD: Duration = Duration::from_secs(5 * 60);
+
+/// Something the stream loop can ask how long the user has been inactive.
+pub trait IdleSource: Send + 'static {
+    /// Time since the last keyboard or mouse input.
+    fn idle_time(&mut self) -> Duration;
+
+    /// Whether the screen is locked. Sources that can't tell say it isn't.
+    fn screen_locked(&mut self) -> bool {
+        false
+    }
+}
+
+/// An `IdleSource` for streams with no user behind them, like trace replays.
+pub struct NeverIdle;
+
+impl IdleSource for NeverIdle {
+    fn idle_time(&mut self) -> Duration {
+        Duration::ZERO
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IdleTransition {
+    /// The user went away `idle_for` ago.
+    Started {
+        idle_for: Duration,
+    },
+    Ended,
+}
+
+/// Turns idle times into transitions across `threshold`.
+pub struct IdleDetector<I> {
+    source: I,
+    threshold: Duration,
+    idle: bool,
+}
+
+impl<I: IdleSource> IdleDetector<I> {
+    pub fn new(source: I, threshold: Duration) -> Self {
+        IdleDetector {
+            source,
+            threshold,
+            idle: false,
+        }
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.idle
+    }
+
+    pub fn screen_locked(&mut self) -> bool {
+        self.source.screen_locked()
+    }
+
+    /// Samples the source and returns the transition, if the state changed.
+    pub fn poll(&mut self) -> Option<IdleTransition> {
+        let idle_for = self.source.idle_time();
+        let idle = idle_for >= self.threshold;
+
+        if idle == self.idle {
+            return None;
+        }
+
+        self.idle = idle;
+        Some(if idle {
+            IdleTransition::Started { idle_for }
+        } else {
+            IdleTransition::Ended
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::scripted_idle::ScriptedIdleSource;
+    use super::*;
+    use tokio::time::advance;
+
+    #[tokio::test(start_paused = true)]
+    async fn detector_reports_each_crossing_of_the_threshold_once() {
+        let source = ScriptedIdleSource::new(vec![Duration::ZERO, Duration::from_secs(200)]);
+        let mut detector = IdleDetector::new(source, Duration::from_secs(60));
+
+        assert_eq!(detector.poll(), None);
+        advance(Duration::from_secs(90)).await;
+        assert_eq!(
+            detector.poll(),
+            Some(IdleTransition::Started {
+                idle_for: Duration::from_secs(90)
+            })
+        );
+        advance(Duration::from_secs(60)).await;
+        assert_eq!(detector.poll(), None);
+        assert!(detector.is_idle());
+
+        advance(Duration::from_secs(50)).await;
+        assert_eq!(detector.poll(), Some(IdleTransition::Ended));
+        assert_eq!(detector.poll(), None);
+    }
+}
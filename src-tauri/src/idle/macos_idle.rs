@@ -0,0 +1,54 @@
+use std::time::Duration;
+
+use crate::idle::IdleSource;
+
+/// Reads the HID idle time: how long since the last keyboard, mouse or
+/// trackpad event on the whole system, and the login session's lock state.
+pub struct MacOSIdleSource;
+
+#[cfg(target_os = "macos")]
+#[link(name = "CoreGraphics", kind = "framework")]
+extern "C" {
+    fn CGEventSourceSecondsSinceLastEventType(state_id: i32, event_type: u32) -> f64;
+    fn CGSessionCopyCurrentDictionary() -> core_foundation::dictionary::CFDictionaryRef;
+}
+
+#[cfg(target_os = "macos")]
+const HID_SYSTEM_STATE: i32 = 1;
+#[cfg(target_os = "macos")]
+const ANY_INPUT_EVENT: u32 = !0;
+
+impl IdleSource for MacOSIdleSource {
+    #[cfg(target_os = "macos")]
+    fn idle_time(&mut self) -> Duration {
+        let seconds =
+            unsafe { CGEventSourceSecondsSinceLastEventType(HID_SYSTEM_STATE, ANY_INPUT_EVENT) };
+
+        Duration::try_from_secs_f64(seconds).unwrap_or_default()
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    fn idle_time(&mut self) -> Duration {
+        Duration::ZERO
+    }
+
+    #[cfg(target_os = "macos")]
+    fn screen_locked(&mut self) -> bool {
+        use core_foundation::base::{CFType, TCFType};
+        use core_foundation::boolean::CFBoolean;
+        use core_foundation::dictionary::CFDictionary;
+        use core_foundation::string::CFString;
+
+        let session = unsafe { CGSessionCopyCurrentDictionary() };
+        if session.is_null() {
+            return false;
+        }
+
+        let session: CFDictionary<CFString, CFType> =
+            unsafe { CFDictionary::wrap_under_create_rule(session) };
+        session
+            .find(CFString::from_static_string("CGSSessionScreenIsLocked"))
+            .and_then(|locked| locked.downcast::<CFBoolean>())
+            .is_some_and(bool::from)
+    }
+}
@@ -0,0 +1,37 @@
+use tokio::time::{Duration, Instant};
+
+use crate::idle::IdleSource;
+
+/// An `IdleSource` driven by a script of input times, offsets from the first
+/// sample on tokio's clock. Idle time is the time since the latest input, or
+/// since the first sample if there has been none yet.
+pub struct ScriptedIdleSource {
+    inputs: Vec<Duration>,
+    started_at: Option<Instant>,
+}
+
+impl ScriptedIdleSource {
+    pub fn new(mut inputs: Vec<Duration>) -> Self {
+        inputs.sort();
+
+        ScriptedIdleSource {
+            inputs,
+            started_at: None,
+        }
+    }
+}
+
+impl IdleSource for ScriptedIdleSource {
+    fn idle_time(&mut self) -> Duration {
+        let elapsed = self.started_at.get_or_insert_with(Instant::now).elapsed();
+        let last_input = self
+            .inputs
+            .iter()
+            .take_while(|at| **at <= elapsed)
+            .last()
+            .copied()
+            .unwrap_or_default();
+
+        elapsed - last_input
+    }
+}
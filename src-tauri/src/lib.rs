@@ -1,58 +1,675 @@
-mod model;
+pub mod activity;
+pub mod analytics;
+pub mod browser;
+mod chart_import;
+mod clock;
+mod daemon;
+pub mod diagnostics;
+pub mod events;
+pub mod export;
+mod idle;
+pub mod logging;
+pub mod model;
+pub mod paths;
+mod pomodoro;
+mod rpc;
+pub mod rules;
+pub mod storage;
 mod stream;
+pub mod titles;
 mod window_info;
 
-use std::{env, fs, path::Path, sync::Arc};
-use tauri::{generate_handler, State};
-use tokio::sync::Mutex;
+use std::{
+    fs, io,
+    path::Path,
+    sync::{Arc, RwLock},
+    time::Instant,
+};
+use tauri::{generate_handler, Manager, State};
+use tauri_plugin_dialog::DialogExt;
 use uuid::Uuid;
 
-use model::StreamState;
+pub use daemon::run_headless;
+
+use activity::{now_millis, ActivitySegment, Session};
+use analytics::{
+    focus::{self, FocusScore, FocusWeek, FocusWeights, FOCUS_WEIGHTS_SETTING},
+    switches::{self, DaySwitches, SwitchMetrics},
+    Period, Rollup, Totals,
+};
+use browser::DomainTime;
+use chart_import::ImportReport;
+use chrono::{Local, NaiveDate};
+use clock::SystemClock;
+use diagnostics::Diagnostics;
+use export::ExportFormat;
+use idle::{IdleDetector, NeverIdle, DEFAULT_IDLE_THRESHOLD, IDLE_THRESHOLD_SETTING};
+use logging::LogState;
+use model::{ActiveWindow, AppError, Context, PomodoroError, StorageError, StreamState};
+use pomodoro::{
+    PomodoroSettings, PomodoroSnapshot, PomodoroState, PomodoroTimer, POMODORO_SETTINGS_SETTING,
+};
+use rpc::RpcError;
+use rules::{Classification, RuleSet, RulesConfig, RulesState, RULES_SETTING};
+use storage::{SegmentFilter, Storage};
+use stream::manager::StreamManager;
+use stream::normalize::{
+    NormalizeConfig, NormalizerState, NormalizingSource, TitleNormalizer, NORMALIZATION_SETTING,
+};
+use stream::schedule::{PollSchedule, PollingConfig, POLLING_SETTING};
 use stream::stream_utils;
+use window_info::cached_probe::{ProbeCounters, ProbeStats};
+use window_info::trace;
+
+#[tauri::command]
+async fn stream_title(
+    app: tauri::AppHandle,
+    session_id: Option<String>,
+    record_to: Option<String>,
+) -> Result<String, AppError> {
+    stream_utils::start_tracking(&app, session_id, record_to).await
+}
 
 #[tauri::command]
-async fn stream_title<'r>(
+async fn replay_trace<'r>(
     app: tauri::AppHandle,
     stream_state: State<'r, StreamState>,
-) -> Result<(), ()> {
-    stream_utils::stream_title(app, stream_state).await;
+    normalizer: State<'r, NormalizerState>,
+    name: String,
+) -> Result<(), AppError> {
+    let path = trace::trace_path(&stream_state.data_dir, &name)?;
+    let source = trace::replay_source(&path).context("Unable to read the trace file")?;
+    let source = NormalizingSource::new(source, normalizer.inner().clone());
+    let idle = IdleDetector::new(NeverIdle, DEFAULT_IDLE_THRESHOLD);
+    let schedule = PollSchedule::fixed(stream_utils::FIXED_INTERVAL);
+    stream_utils::start(&stream_state.stream, source, idle, schedule).await?;
+    stream_utils::subscribe_webview(&app, &stream_state.stream).await?;
 
     Ok(())
 }
 
 #[tauri::command]
-async fn stop_stream(stream_state: State<'_, StreamState>) -> Result<(), ()> {
-    stream_utils::stop_stream(stream_state).await;
+fn query_activity(
+    storage: State<'_, Arc<Storage>>,
+    filter: SegmentFilter,
+) -> Result<Vec<ActivitySegment>, AppError> {
+    storage
+        .query_segments(&filter)
+        .context("Unable to read activity")
+}
 
-    Ok(())
+/// Active time per website for the segments matching `filter`.
+#[tauri::command]
+fn query_domains(
+    storage: State<'_, Arc<Storage>>,
+    filter: SegmentFilter,
+) -> Result<Vec<DomainTime>, AppError> {
+    storage
+        .query_segments(&filter)
+        .map(|segments| browser::time_per_domain(&segments))
+        .context("Unable to read activity")
+}
+
+/// Where the time in `[from, to)` went, per app, title, category and local
+/// hour.
+#[tauri::command]
+fn activity_summary(
+    storage: State<'_, Arc<Storage>>,
+    from: i64,
+    to: i64,
+) -> Result<Totals, AppError> {
+    let segments = storage
+        .query_segments(&SegmentFilter {
+            from: Some(from),
+            to: Some(to),
+            ..Default::default()
+        })
+        .context("Unable to read activity")?;
+
+    Ok(analytics::summarize(&segments, from, to, &Local))
+}
+
+/// Totals per day, week or month for the local days `from` through `to`.
+#[tauri::command]
+fn activity_rollups(
+    storage: State<'_, Arc<Storage>>,
+    from: NaiveDate,
+    to: NaiveDate,
+    period: Option<Period>,
+) -> Result<Vec<Rollup>, AppError> {
+    if to < from {
+        return Err(AppError::Validation(format!("{} is before {}", to, from)));
+    }
+
+    analytics::rollups(
+        &storage,
+        from,
+        to,
+        period.unwrap_or_default(),
+        &Local,
+        now_millis(),
+    )
+    .context("Unable to compute the rollups")
 }
 
+/// How often the user switched windows during a session.
 #[tauri::command]
-fn save_file(from: String, to: String, target_folder: String) -> Result<String, String> {
+fn session_switches(
+    storage: State<'_, Arc<Storage>>,
+    session_id: String,
+) -> Result<SwitchMetrics, AppError> {
+    let segments = storage
+        .query_segments(&SegmentFilter {
+            session_id: Some(session_id),
+            ..Default::default()
+        })
+        .context("Unable to read activity")?;
+
+    Ok(switches::switch_metrics(&segments, i64::MIN, i64::MAX))
+}
+
+/// How often the user switched windows on each local day `from` through `to`.
+#[tauri::command]
+fn daily_switches(
+    storage: State<'_, Arc<Storage>>,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<Vec<DaySwitches>, AppError> {
+    if to < from {
+        return Err(AppError::Validation(format!("{} is before {}", to, from)));
+    }
+    let (start, _) = analytics::day_bounds(from, &Local);
+    let (_, end) = analytics::day_bounds(to, &Local);
+    let segments = storage
+        .query_segments(&SegmentFilter {
+            from: Some(start),
+            to: Some(end),
+            ..Default::default()
+        })
+        .context("Unable to read activity")?;
+
+    Ok(switches::per_day(&segments, from, to, &Local))
+}
+
+/// How focused the user stayed during a session's focus phases.
+#[tauri::command]
+fn session_focus_score(
+    storage: State<'_, Arc<Storage>>,
+    session_id: String,
+) -> Result<FocusScore, AppError> {
+    let session = storage
+        .session(&session_id)
+        .context("Unable to read the session")?
+        .ok_or_else(|| AppError::Validation(format!("No session {}", session_id)))?;
+    let segments = storage
+        .query_segments(&SegmentFilter {
+            session_id: Some(session_id),
+            ..Default::default()
+        })
+        .context("Unable to read activity")?;
+
+    Ok(focus::focus_score(
+        &session,
+        &segments,
+        &focus_weights(&storage)?,
+        &timer_settings(&storage)?,
+    ))
+}
+
+/// Focus scores of the sessions started on local days `from` through `to`,
+/// by week.
+#[tauri::command]
+fn focus_trend(
+    storage: State<'_, Arc<Storage>>,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<Vec<FocusWeek>, AppError> {
+    if to < from {
+        return Err(AppError::Validation(format!("{} is before {}", to, from)));
+    }
+    let (start, _) = analytics::day_bounds(from, &Local);
+    let (_, end) = analytics::day_bounds(to, &Local);
+    let weights = focus_weights(&storage)?;
+    let timer_settings = timer_settings(&storage)?;
+
+    let scores = storage
+        .sessions_between(start, end)
+        .context("Unable to read sessions")?
+        .iter()
+        .map(|session| {
+            let segments = storage.query_segments(&SegmentFilter {
+                session_id: Some(session.id.clone()),
+                ..Default::default()
+            })?;
+            Ok(focus::focus_score(
+                session,
+                &segments,
+                &weights,
+                &timer_settings,
+            ))
+        })
+        .collect::<Result<Vec<_>, StorageError>>()
+        .context("Unable to read activity")?;
+
+    Ok(focus::weekly_trend(scores, &Local))
+}
+
+fn focus_weights(storage: &Storage) -> Result<FocusWeights, AppError> {
+    Ok(storage
+        .setting::<FocusWeights>(FOCUS_WEIGHTS_SETTING)
+        .context("Unable to read the focus weights")?
+        .unwrap_or_default())
+}
+
+/// Settings to work out an imported session's phase count with: the ones the
+/// timer last ran with, then the defaults.
+fn timer_settings(storage: &Storage) -> Result<Vec<PomodoroSettings>, AppError> {
+    let saved = storage
+        .setting::<PomodoroSettings>(POMODORO_SETTINGS_SETTING)
+        .context("Unable to read the timer settings")?;
+
+    Ok(saved
+        .into_iter()
+        .chain([PomodoroSettings::default()])
+        .collect())
+}
+
+#[tauri::command]
+fn save_session(storage: State<'_, Arc<Storage>>, session: Session) -> Result<(), AppError> {
+    storage
+        .insert_session(&session)
+        .map(|_| ())
+        .context("Unable to save the session")
+}
+
+/// Imports the frontend's `chart_data.json` history, by default from the
+/// location tauri-plugin-store writes it to.
+#[tauri::command]
+fn import_chart_history(
+    app: tauri::AppHandle,
+    storage: State<'_, Arc<Storage>>,
+    path: Option<String>,
+) -> Result<ImportReport, AppError> {
+    let path = match path {
+        Some(path) => path.into(),
+        None => app
+            .path()
+            .app_data_dir()
+            .map_err(|e| AppError::DataDirUnavailable(e.to_string()))?
+            .join("chart_data.json"),
+    };
+
+    let contents =
+        fs::read_to_string(&path).context(&format!("Unable to read {}", path.display()))?;
+
+    chart_import::import_chart_history(&storage, &contents)
+        .context("Unable to import chart history")
+}
+
+#[tauri::command]
+fn get_setting(
+    storage: State<'_, Arc<Storage>>,
+    key: String,
+) -> Result<Option<serde_json::Value>, AppError> {
+    storage.setting(&key).context("Unable to read the setting")
+}
+
+/// Saves a setting that has no command of its own. Settings the backend
+/// reads are checked against their type first.
+#[tauri::command]
+fn set_setting(
+    storage: State<'_, Arc<Storage>>,
+    key: String,
+    value: serde_json::Value,
+) -> Result<(), AppError> {
+    check_setting(&key, &value)?;
+    storage
+        .set_setting(&key, &value)
+        .context("Unable to save the setting")
+}
+
+/// Rejects writes `set_setting` mustn't make: settings that are compiled and
+/// held in memory go through their own commands, and the rest must decode.
+fn check_setting(key: &str, value: &serde_json::Value) -> Result<(), AppError> {
+    fn decodes<T: serde::de::DeserializeOwned>(
+        key: &str,
+        value: &serde_json::Value,
+    ) -> Result<(), AppError> {
+        T::deserialize(value)
+            .map(|_| ())
+            .map_err(|e| AppError::Validation(format!("Invalid value for {}: {}", key, e)))
+    }
+
+    match key {
+        RULES_SETTING => Err(AppError::Validation(
+            "Rules are saved with set_rules".to_string(),
+        )),
+        NORMALIZATION_SETTING => Err(AppError::Validation(
+            "Title normalization is saved with set_title_normalization".to_string(),
+        )),
+        POLLING_SETTING => decodes::<PollingConfig>(key, value),
+        IDLE_THRESHOLD_SETTING => decodes::<u64>(key, value),
+        FOCUS_WEIGHTS_SETTING => decodes::<FocusWeights>(key, value),
+        POMODORO_SETTINGS_SETTING => decodes::<PomodoroSettings>(key, value),
+        _ => Ok(()),
+    }
+}
+
+#[tauri::command]
+fn query_sessions(
+    storage: State<'_, Arc<Storage>>,
+    from: i64,
+    to: i64,
+) -> Result<Vec<Session>, AppError> {
+    storage
+        .sessions_between(from, to)
+        .context("Unable to read sessions")
+}
+
+#[tauri::command]
+fn get_rules(rules: State<'_, RulesState>) -> Result<RulesConfig, AppError> {
+    rules
+        .read()
+        .map(|rules| rules.config().clone())
+        .map_err(|_| AppError::Poisoned("Rules"))
+}
+
+/// Validates and saves a new rules config. New segments are classified with it
+/// right away; existing ones keep their category until `reclassify_history`.
+#[tauri::command]
+async fn set_rules(
+    rules: State<'_, RulesState>,
+    storage: State<'_, Arc<Storage>>,
+    stream_state: State<'_, StreamState>,
+    config: RulesConfig,
+) -> Result<(), AppError> {
+    let compiled = RuleSet::compile(config)?;
+    storage
+        .set_setting(RULES_SETTING, compiled.config())
+        .context("Unable to save the rules")?;
+
+    *rules.write().map_err(|_| AppError::Poisoned("Rules"))? = compiled;
+
+    // An attached daemon classifies new segments, so it needs them too
+    reload_in_daemon(&stream_state, "reload_rules")
+        .await
+        .context("Unable to update the daemon's rules")
+}
+
+/// Asks an attached daemon to re-read a setting this app just saved.
+async fn reload_in_daemon(stream_state: &StreamState, method: &str) -> Result<(), AppError> {
+    match stream_state.daemon_socket() {
+        Some(socket) => rpc::client::request_async(&socket, method, serde_json::Value::Null)
+            .await?
+            .map(|_| ())
+            .map_err(RpcError::into_app_error),
+        None => Ok(()),
+    }
+}
+
+#[tauri::command]
+fn get_title_normalization(
+    normalizer: State<'_, NormalizerState>,
+) -> Result<NormalizeConfig, AppError> {
+    normalizer
+        .read()
+        .map(|normalizer| normalizer.config().clone())
+        .map_err(|_| AppError::Poisoned("Title normalization"))
+}
+
+/// Validates and saves a new normalization pipeline. It applies to windows
+/// observed from now on; stored titles are left alone.
+#[tauri::command]
+async fn set_title_normalization(
+    normalizer: State<'_, NormalizerState>,
+    storage: State<'_, Arc<Storage>>,
+    stream_state: State<'_, StreamState>,
+    config: NormalizeConfig,
+) -> Result<(), AppError> {
+    let compiled = TitleNormalizer::compile(config)?;
+    storage
+        .set_setting(NORMALIZATION_SETTING, compiled.config())
+        .context("Unable to save the title normalization")?;
+
+    *normalizer
+        .write()
+        .map_err(|_| AppError::Poisoned("Title normalization"))? = compiled;
+
+    reload_in_daemon(&stream_state, "reload_normalization")
+        .await
+        .context("Unable to update the daemon's title normalization")
+}
+
+/// What the window probe has cost since tracking started, from the attached
+/// daemon if there is one.
+#[tauri::command]
+async fn probe_counters(stream_state: State<'_, StreamState>) -> Result<ProbeCounters, AppError> {
+    match stream_state.daemon_socket() {
+        Some(socket) => {
+            rpc::client::request_async(&socket, "probe_counters", serde_json::Value::Null)
+                .await
+                .map_err(AppError::from)
+                .and_then(|answer| answer.map_err(RpcError::into_app_error))
+                .and_then(|value| serde_json::from_value(value).map_err(AppError::Decode))
+                .context("Unable to read the daemon's probe counters")
+        }
+        None => Ok(stream_state.probe_stats.counters(Instant::now())),
+    }
+}
+
+/// Probes everything tracking relies on and reports which permissions are
+/// missing, also sending each as a `permissionStatus` event.
+#[tauri::command]
+async fn permission_diagnostics(app: tauri::AppHandle) -> Result<Diagnostics, AppError> {
+    let diagnostics = stream_utils::run_diagnostics().await?;
+    diagnostics::emit_status(&app, &diagnostics);
+
+    Ok(diagnostics)
+}
+
+/// The newest lines of the log files, oldest first, to attach to bug reports:
+/// the app's, or with `daemon` the headless daemon's.
+#[tauri::command]
+fn recent_logs(
+    logs: State<'_, LogState>,
+    limit: Option<usize>,
+    daemon: Option<bool>,
+) -> Result<Vec<String>, AppError> {
+    let prefix = if daemon.unwrap_or(false) {
+        logging::DAEMON_LOG_PREFIX
+    } else {
+        logs.prefix
+    };
+    logging::recent_logs(
+        &logs.dir,
+        prefix,
+        limit.unwrap_or(logging::DEFAULT_RECENT_LINES),
+    )
+    .context("Unable to read the logs")
+}
+
+#[tauri::command]
+fn classify_window(
+    rules: State<'_, RulesState>,
+    window: ActiveWindow,
+) -> Result<Classification, AppError> {
+    rules
+        .read()
+        .map(|rules| rules.classify_window(&window))
+        .map_err(|_| AppError::Poisoned("Rules"))
+}
+
+/// Applies the current rules to stored segments. Returns how many were updated.
+#[tauri::command]
+fn reclassify_history(
+    rules: State<'_, RulesState>,
+    storage: State<'_, Arc<Storage>>,
+    filter: Option<SegmentFilter>,
+) -> Result<usize, AppError> {
+    let rules = rules.read().map_err(|_| AppError::Poisoned("Rules"))?;
+
+    storage
+        .reclassify(&filter.unwrap_or_default(), |class, title| {
+            rules.classify(class, title)
+        })
+        .context("Unable to reclassify activity")
+}
+
+#[tauri::command]
+async fn stop_stream(stream_state: State<'_, StreamState>) -> Result<(), AppError> {
+    stream_utils::stop_stream(stream_state).await
+}
+
+#[tauri::command]
+async fn stream_running(stream_state: State<'_, StreamState>) -> Result<bool, AppError> {
+    Ok(stream_state.stream.is_running().await)
+}
+
+/// Starts the timer and tracks under its session, so the activity recorded
+/// can be scored against the timer's phases.
+#[tauri::command]
+async fn pomodoro_start(
+    app: tauri::AppHandle,
+    pomodoro: State<'_, PomodoroState>,
+    storage: State<'_, Arc<Storage>>,
+    settings: PomodoroSettings,
+) -> Result<PomodoroSnapshot, AppError> {
+    let session_id = Uuid::new_v4().to_string();
+    storage
+        .insert_session(&settings.session(session_id.clone(), now_millis()))
+        .context("Unable to save the session")?;
+
+    // Only a saved session may run, so a timer that won't start takes it back
+    let snapshot = match pomodoro::apply(&app, &pomodoro, |timer| {
+        timer.start(settings, session_id.clone())
+    }) {
+        Ok(snapshot) => snapshot,
+        Err(error) => {
+            storage
+                .delete_session(&session_id)
+                .context("Unable to discard the unstarted session")?;
+            return Err(error.into());
+        }
+    };
+    storage
+        .set_setting(POMODORO_SETTINGS_SETTING, &settings)
+        .context("Unable to save the timer settings")?;
+    pomodoro::track(&app, Some(session_id), snapshot.phase).await?;
+
+    Ok(snapshot)
+}
+
+#[tauri::command]
+async fn pomodoro_pause(
+    app: tauri::AppHandle,
+    pomodoro: State<'_, PomodoroState>,
+) -> Result<PomodoroSnapshot, AppError> {
+    pomodoro::apply_and_track(&app, &pomodoro, |timer| timer.pause()).await
+}
+
+#[tauri::command]
+async fn pomodoro_resume(
+    app: tauri::AppHandle,
+    pomodoro: State<'_, PomodoroState>,
+) -> Result<PomodoroSnapshot, AppError> {
+    pomodoro::apply_and_track(&app, &pomodoro, |timer| timer.resume()).await
+}
+
+#[tauri::command]
+async fn pomodoro_skip(
+    app: tauri::AppHandle,
+    pomodoro: State<'_, PomodoroState>,
+) -> Result<PomodoroSnapshot, AppError> {
+    pomodoro::apply_and_track(&app, &pomodoro, |timer| timer.skip()).await
+}
+
+#[tauri::command]
+async fn pomodoro_abort(
+    app: tauri::AppHandle,
+    pomodoro: State<'_, PomodoroState>,
+) -> Result<PomodoroSnapshot, AppError> {
+    pomodoro::apply_and_track(&app, &pomodoro, |timer| timer.abort()).await
+}
+
+#[tauri::command]
+fn pomodoro_state(pomodoro: State<'_, PomodoroState>) -> Result<PomodoroSnapshot, AppError> {
+    pomodoro
+        .lock()
+        .map(|timer| timer.snapshot())
+        .map_err(|_| AppError::from(PomodoroError::Poisoned))
+}
+
+/// Asks where to save, then exports the local days `from` through `to`.
+/// Returns the file written, or `None` if the user cancelled.
+#[tauri::command]
+async fn export_activity(
+    app: tauri::AppHandle,
+    storage: State<'_, Arc<Storage>>,
+    from: NaiveDate,
+    to: NaiveDate,
+    format: ExportFormat,
+) -> Result<Option<String>, AppError> {
+    if to < from {
+        return Err(AppError::Validation(format!("{} is before {}", to, from)));
+    }
+    let (start, _) = analytics::day_bounds(from, &Local);
+    let (_, end) = analytics::day_bounds(to, &Local);
+    let storage = storage.inner().clone();
+
+    // The dialog blocks until the user answers, and so does the writing
+    tokio::task::spawn_blocking(move || {
+        let Some(chosen) = app
+            .dialog()
+            .file()
+            .set_title("Export activity")
+            .add_filter(format.extension().to_uppercase(), &[format.extension()])
+            .set_file_name(format!("locus-{}-{}.{}", from, to, format.extension()))
+            .blocking_save_file()
+        else {
+            return Ok(None);
+        };
+        let path = chosen
+            .into_path()
+            .map_err(|_| AppError::Validation("Exports can only be saved to a file".to_string()))?;
+
+        let export = export::collect(&storage, start, end).context("Unable to read activity")?;
+        let file = fs::File::create(&path).context("Unable to create the export file")?;
+        export::write(&export, format, &Local, io::BufWriter::new(file))
+            .context("Unable to write the export")?;
+
+        Ok(Some(path.to_string_lossy().into_owned()))
+    })
+    .await
+    .map_err(io::Error::from)
+    .context("Unable to export activity")?
+}
+
+#[tauri::command]
+fn save_file(from: String, to: String, target_folder: String) -> Result<String, AppError> {
     let source_path = Path::new(&from);
 
     let file_extension = source_path
         .extension()
         .and_then(|ext| ext.to_str())
-        .ok_or("Unable to extract file extension")?;
+        .ok_or_else(|| AppError::Validation("Unable to extract file extension".to_string()))?;
 
     let unique_file_name = format!("{}.{}", Uuid::new_v4(), file_extension);
     let destination_path = Path::new(&to).join(&target_folder);
 
     if !destination_path.exists() {
-        fs::create_dir_all(&destination_path)
-            .map_err(|e| format!("Unable to create the destination folder: {:?}", e))?;
+        fs::create_dir_all(&destination_path).context("Unable to create the destination folder")?;
     }
 
     let destination_file_path = destination_path.join(unique_file_name);
 
-    fs::copy(source_path, &destination_file_path)
-        .map_err(|e| format!("Unable to copy file to destination: {:?}", e))?;
+    fs::copy(source_path, &destination_file_path).context("Unable to copy file to destination")?;
 
     destination_file_path
         .to_str()
-        .ok_or("Failed to convert path to a valid UTF-8 string".to_string())
         .map(|s| s.to_string())
+        .ok_or_else(|| {
+            AppError::Validation("Failed to convert path to a valid UTF-8 string".to_string())
+        })
 }
 
 #[tauri::command]
@@ -63,21 +680,103 @@ fn supported_display_server() -> bool {
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
-    let stream_state = StreamState {
-        cancel_flag: Arc::new(Mutex::new(false)),
-    };
-
     tauri::Builder::default()
         .plugin(tauri_plugin_store::Builder::new().build())
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_dialog::init())
-        .manage(stream_state)
         .plugin(tauri_plugin_shell::init())
+        .setup(|app| {
+            app.manage(logging::init(
+                &app.path().app_log_dir()?,
+                logging::APP_LOG_PREFIX,
+            )?);
+
+            let data_dir = app.path().app_data_dir()?;
+            fs::create_dir_all(&data_dir)?;
+
+            // A running `locus --headless` already serves the socket and
+            // records; otherwise the app serves it until a daemon takes over
+            let socket_path = rpc::server::socket_path(&data_dir)?;
+            let stream = Arc::new(StreamManager::default());
+            let server = if rpc::server::is_listening(&socket_path) {
+                None
+            } else {
+                let listener = rpc::server::bind(&socket_path)?;
+                let handle = app.handle().clone();
+                Some(tauri::async_runtime::spawn(rpc::server::serve(
+                    listener,
+                    stream.clone(),
+                    move |call| {
+                        let app = handle.clone();
+                        async move { rpc::server::execute(&app, call).await }
+                    },
+                )))
+            };
+            app.manage(StreamState {
+                stream,
+                socket_path,
+                server: std::sync::Mutex::new(server),
+                probe_stats: Arc::new(ProbeStats::default()),
+                data_dir: data_dir.clone(),
+                tracking: Default::default(),
+            });
+
+            let storage = Storage::open(data_dir.join(paths::DATABASE_FILE_NAME))?;
+            let rules = RuleSet::load(&storage).unwrap_or_else(|e| {
+                tracing::warn!(error = %e, "Ignoring saved rules");
+                RuleSet::default()
+            });
+            let normalizer = TitleNormalizer::load(&storage).unwrap_or_else(|e| {
+                tracing::warn!(error = %e, "Ignoring saved title normalization");
+                TitleNormalizer::default()
+            });
+            app.manage(Arc::new(storage));
+            app.manage::<RulesState>(Arc::new(RwLock::new(rules)));
+            app.manage::<NormalizerState>(Arc::new(RwLock::new(normalizer)));
+
+            let pomodoro: PomodoroState =
+                Arc::new(std::sync::Mutex::new(PomodoroTimer::new(SystemClock)));
+            app.manage(pomodoro.clone());
+            tauri::async_runtime::spawn(pomodoro::run_ticker(app.handle().clone(), pomodoro));
+
+            Ok(())
+        })
         .invoke_handler(generate_handler![
             stream_title,
             stop_stream,
+            stream_running,
+            replay_trace,
+            query_activity,
+            query_domains,
+            activity_summary,
+            activity_rollups,
+            session_switches,
+            daily_switches,
+            session_focus_score,
+            focus_trend,
+            query_sessions,
+            save_session,
+            get_setting,
+            set_setting,
+            import_chart_history,
+            get_rules,
+            set_rules,
+            classify_window,
+            reclassify_history,
+            get_title_normalization,
+            set_title_normalization,
+            pomodoro_start,
+            pomodoro_pause,
+            pomodoro_resume,
+            pomodoro_skip,
+            pomodoro_abort,
+            pomodoro_state,
             save_file,
-            supported_display_server
+            export_activity,
+            supported_display_server,
+            probe_counters,
+            recent_logs,
+            permission_diagnostics
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -100,4 +799,17 @@ mod tests {
         let session_string = session_id.to_string();
         assert!(!session_string.is_empty());
     }
+
+    #[test]
+    fn generic_settings_cannot_bypass_their_commands() {
+        let rules = serde_json::json!({ "rules": [] });
+        assert_eq!(
+            check_setting(RULES_SETTING, &rules).unwrap_err().code(),
+            "validation"
+        );
+        assert!(check_setting(NORMALIZATION_SETTING, &serde_json::json!({})).is_err());
+        assert!(check_setting(IDLE_THRESHOLD_SETTING, &serde_json::json!("soon")).is_err());
+        assert!(check_setting(IDLE_THRESHOLD_SETTING, &serde_json::json!(300)).is_ok());
+        assert!(check_setting("theme", &serde_json::json!("dark")).is_ok());
+    }
 }
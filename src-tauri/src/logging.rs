@@ -0,0 +1,135 @@
+use std::{
+    error::Error,
+    fs,
+    io::{self, IsTerminal},
+    path::{Path, PathBuf},
+};
+use tracing_appender::{
+    non_blocking::WorkerGuard,
+    rolling::{RollingFileAppender, Rotation},
+};
+use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+
+/// The app's log files are named `locus-app.<date>.log`, one per day.
+pub const APP_LOG_PREFIX: &str = "locus-app";
+/// The headless daemon's, which share the folder, `locus-daemon.<date>.log`.
+pub const DAEMON_LOG_PREFIX: &str = "locus-daemon";
+pub const LOG_FILE_SUFFIX: &str = "log";
+
+/// Days of logs kept; older files are deleted as new ones start.
+pub const KEEP_LOG_FILES: usize = 7;
+
+/// Overrides the level filter, e.g. `LOCUS_LOG=locus_lib=debug`.
+pub const LOG_FILTER_ENV: &str = "LOCUS_LOG";
+
+/// Lines `recent_logs` returns when not told otherwise.
+pub const DEFAULT_RECENT_LINES: usize = 500;
+
+/// Where logs go; dropping it flushes and stops the writer thread.
+pub struct LogState {
+    pub dir: PathBuf,
+    pub prefix: &'static str,
+    _guard: WorkerGuard,
+}
+
+/// Sends `tracing` events, as JSON lines, to daily files in `dir` named after
+/// `prefix`, and to stderr too when it is a terminal. Panics are logged
+/// before they unwind.
+pub fn init(dir: &Path, prefix: &'static str) -> Result<LogState, Box<dyn Error>> {
+    fs::create_dir_all(dir)?;
+
+    let appender = RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix(prefix)
+        .filename_suffix(LOG_FILE_SUFFIX)
+        .max_log_files(KEEP_LOG_FILES)
+        .build(dir)?;
+    let (writer, guard) = tracing_appender::non_blocking(appender);
+
+    let filter = EnvFilter::try_from_env(LOG_FILTER_ENV).unwrap_or_else(|_| EnvFilter::new("info"));
+    let terminal = io::stderr()
+        .is_terminal()
+        .then(|| fmt::layer().with_writer(io::stderr));
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt::layer().json().with_writer(writer))
+        .with(terminal)
+        .try_init()?;
+
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        tracing::error!(panic = %info, "Panicked");
+        default_hook(info);
+    }));
+
+    Ok(LogState {
+        dir: dir.to_path_buf(),
+        prefix,
+        _guard: guard,
+    })
+}
+
+/// The last `limit` lines logged to `dir` under `prefix`, oldest first, for
+/// bug reports.
+pub fn recent_logs(dir: &Path, prefix: &str, limit: usize) -> io::Result<Vec<String>> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| is_log_file(path, prefix))
+        .collect();
+    // The date in the name sorts them oldest first
+    files.sort();
+
+    let mut lines = Vec::new();
+    for file in files.iter().rev() {
+        let contents = fs::read_to_string(file)?;
+        let mut newest: Vec<String> = contents
+            .lines()
+            .rev()
+            .take(limit - lines.len())
+            .map(str::to_string)
+            .collect();
+        newest.reverse();
+        newest.append(&mut lines);
+        lines = newest;
+
+        if lines.len() == limit {
+            break;
+        }
+    }
+
+    Ok(lines)
+}
+
+fn is_log_file(path: &Path, prefix: &str) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| {
+            name.starts_with(&format!("{}.", prefix))
+                && name.ends_with(&format!(".{}", LOG_FILE_SUFFIX))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recent_logs_reads_back_across_rotated_files() {
+        let dir = std::env::temp_dir().join(format!("locus-logs-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("locus-app.2024-10-01.log"), "a\nb\nc\n").unwrap();
+        fs::write(dir.join("locus-app.2024-10-02.log"), "d\ne\n").unwrap();
+        fs::write(dir.join("locus-daemon.2024-10-02.log"), "daemon\n").unwrap();
+        fs::write(dir.join("other.txt"), "ignored\n").unwrap();
+
+        let lines = recent_logs(&dir, APP_LOG_PREFIX, 3).unwrap();
+        let all = recent_logs(&dir, APP_LOG_PREFIX, 10).unwrap();
+        let daemon = recent_logs(&dir, DAEMON_LOG_PREFIX, 10).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(lines, ["c", "d", "e"]);
+        assert_eq!(all, ["a", "b", "c", "d", "e"]);
+        assert_eq!(daemon, ["daemon"]);
+    }
+}
@@ -2,5 +2,14 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 fn main() {
+    // `--headless` tracks without the GUI, for the launchd agent
+    if std::env::args().any(|arg| arg == "--headless") {
+        if let Err(e) = locus_lib::run_headless() {
+            eprintln!("locus: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     locus_lib::run()
 }
@@ -1,10 +1,42 @@
-use serde::Serialize;
-use std::sync::Arc;
+use rusqlite::ErrorCode;
+use serde::{Deserialize, Serialize, Serializer};
+use std::{
+    io,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
 use thiserror::Error;
-use tokio::sync::Mutex;
+use ts_rs::TS;
+
+use crate::diagnostics::{Permission, ScriptFailure};
+use crate::pomodoro::Phase;
+use crate::rpc::server;
+use crate::stream::manager::StreamManager;
+use crate::window_info::cached_probe::ProbeStats;
 
 pub struct StreamState {
-    pub cancel_flag: Arc<Mutex<bool>>,
+    pub stream: Arc<StreamManager>,
+    /// Where the socket API is served, by the app or by a headless daemon.
+    pub socket_path: PathBuf,
+    /// The app's own socket server, while no daemon runs.
+    pub server: Mutex<Option<tauri::async_runtime::JoinHandle<()>>>,
+    /// What the window probe has cost since tracking last started.
+    pub probe_stats: Arc<ProbeStats>,
+    /// The app data folder, which holds the socket and traces.
+    pub data_dir: PathBuf,
+    /// Session the live Mac is being tracked under, if it is. Held while
+    /// tracking starts or stops so concurrent calls take turns.
+    pub tracking: tokio::sync::Mutex<Option<String>>,
+}
+
+impl StreamState {
+    /// The socket of a headless daemon, if one is serving it; the app then
+    /// mirrors it instead of recording on its own. Checked on every call, so
+    /// a daemon that started after the app is found too.
+    pub fn daemon_socket(&self) -> Option<PathBuf> {
+        let serving = self.server.lock().map_or(true, |server| server.is_some());
+        (!serving && server::is_listening(&self.socket_path)).then(|| self.socket_path.clone())
+    }
 }
 
 // macOS-only error types
@@ -12,14 +44,301 @@ pub struct StreamState {
 pub enum MacOSError {
     #[error("No active window found")]
     NoActiveWindow,
-    #[error("CoreGraphics error: {0}")]
-    CoreGraphicsError(String),
+    #[error("Unable to run the {script} AppleScript: {source}")]
+    Launch {
+        script: &'static str,
+        source: io::Error,
+    },
+    #[error("{0} has no scriptable tabs")]
+    UnsupportedApp(String),
+    #[error("Locus daemon unavailable: {0}")]
+    DaemonUnavailable(String),
+    #[error("AppleScript failed: {0}")]
+    Script(#[from] ScriptFailure),
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum StreamError {
+    #[error("The stream is already running; stop it first")]
+    AlreadyRunning,
+    #[error("The stream is not running")]
+    NotRunning,
+}
+
+#[derive(Error, Debug)]
+pub enum StorageError {
+    #[error("Database error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+    #[error("Invalid stored value: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Invalid `{key}` setting: {message}")]
+    InvalidSetting { key: &'static str, message: String },
+    #[error("Storage lock poisoned")]
+    Poisoned,
+}
+
+#[derive(Error, Debug)]
+pub enum PomodoroError {
+    #[error("Cannot {action} the timer while it is {phase:?}")]
+    InvalidTransition { action: &'static str, phase: Phase },
+    #[error("Session length and number of sessions must be at least 1")]
+    InvalidSettings,
+    #[error("Pomodoro state lock poisoned")]
+    Poisoned,
+}
+
+#[derive(Error, Debug)]
+pub enum RuleError {
+    #[error("Rule `{rule}` has an invalid title pattern: {source}")]
+    InvalidPattern { rule: String, source: regex::Error },
+}
+
+#[derive(Error, Debug)]
+pub enum NormalizeError {
+    #[error("Invalid title normalization pattern `{pattern}`: {source}")]
+    InvalidPattern {
+        pattern: String,
+        source: regex::Error,
+    },
+}
+
+/// What a command or background task failed with. The webview gets it as an
+/// `ErrorInfo`: `{ "code": "storage.busy", "message": "...", "retryable": true }`.
+#[derive(Error, Debug)]
+pub enum AppError {
+    #[error(transparent)]
+    Probe(MacOSError),
+    #[error("{}", permission_message(*.permission, .target.as_deref()))]
+    Permission {
+        permission: Permission,
+        target: Option<String>,
+    },
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("{0}")]
+    Validation(String),
+    #[error(transparent)]
+    Stream(#[from] StreamError),
+    #[error(transparent)]
+    Pomodoro(#[from] PomodoroError),
+    #[error("{0} lock poisoned")]
+    Poisoned(&'static str),
+    #[error("Unable to resolve the app data folder: {0}")]
+    DataDirUnavailable(String),
+    /// A reply, such as the daemon's, that doesn't have the expected shape.
+    #[error("Unexpected reply: {0}")]
+    Decode(serde_json::Error),
+    /// A failure the daemon reported over the socket.
+    #[error("{}", .0.message)]
+    Remote(ErrorInfo),
+    #[error("{context}: {source}")]
+    Context {
+        context: String,
+        source: Box<AppError>,
+    },
+}
+
+fn permission_message(permission: Permission, target: Option<&str>) -> String {
+    let name = match permission {
+        Permission::Accessibility => "Accessibility",
+        Permission::Automation => "Automation",
+        Permission::ScreenRecording => "Screen Recording",
+    };
+    match target {
+        Some(target) => format!("{} permission for {} is not granted", name, target),
+        None => format!("{} permission is not granted", name),
+    }
+}
+
+impl AppError {
+    /// Prefixes the message with what was being attempted; the code stays.
+    pub fn context(self, context: impl Into<String>) -> Self {
+        AppError::Context {
+            context: context.into(),
+            source: Box::new(self),
+        }
+    }
+
+    /// A stable, dotted identifier the UI can branch on.
+    pub fn code(&self) -> String {
+        let code = match self {
+            AppError::Probe(error) => match error {
+                MacOSError::NoActiveWindow => "probe.noActiveWindow",
+                MacOSError::Launch { .. } => "probe.launchFailed",
+                MacOSError::UnsupportedApp(_) => "probe.unsupportedApp",
+                MacOSError::DaemonUnavailable(_) => "probe.daemonUnavailable",
+                MacOSError::Script(failure) => match failure {
+                    ScriptFailure::PermissionDenied { permission } => {
+                        return permission_code(*permission)
+                    }
+                    ScriptFailure::NotScriptable => "probe.notScriptable",
+                    ScriptFailure::AppNotRunning => "probe.appNotRunning",
+                    ScriptFailure::NoWindow => "probe.noWindow",
+                    ScriptFailure::Timeout => "probe.timeout",
+                    ScriptFailure::Other { .. } => "probe.scriptFailed",
+                },
+            },
+            AppError::Permission { permission, .. } => return permission_code(*permission),
+            AppError::Storage(error) => match error {
+                StorageError::Sqlite(e) if is_busy(e) => "storage.busy",
+                StorageError::Sqlite(_) => "storage.database",
+                StorageError::Json(_) => "storage.invalidValue",
+                StorageError::InvalidSetting { .. } => "storage.invalidSetting",
+                StorageError::Poisoned => "internal.poisoned",
+            },
+            AppError::Io(error) => match error.kind() {
+                io::ErrorKind::NotFound => "io.notFound",
+                io::ErrorKind::PermissionDenied => "io.permissionDenied",
+                _ => "io.failed",
+            },
+            AppError::Validation(_) => "validation",
+            AppError::Stream(error) => match error {
+                StreamError::AlreadyRunning => "stream.alreadyRunning",
+                StreamError::NotRunning => "stream.notRunning",
+            },
+            AppError::Pomodoro(error) => match error {
+                PomodoroError::InvalidTransition { .. } => "pomodoro.invalidTransition",
+                PomodoroError::InvalidSettings => "validation",
+                PomodoroError::Poisoned => "internal.poisoned",
+            },
+            AppError::Poisoned(_) => "internal.poisoned",
+            AppError::DataDirUnavailable(_) => "internal.dataDirUnavailable",
+            AppError::Decode(_) => "internal.decode",
+            AppError::Remote(info) => return info.code.clone(),
+            AppError::Context { source, .. } => return source.code(),
+        };
+
+        code.to_string()
+    }
+
+    /// Whether trying the same thing again later may work, without the user
+    /// changing anything.
+    pub fn retryable(&self) -> bool {
+        match self {
+            AppError::Probe(error) => match error {
+                MacOSError::NoActiveWindow | MacOSError::DaemonUnavailable(_) => true,
+                MacOSError::Launch { .. } | MacOSError::UnsupportedApp(_) => false,
+                MacOSError::Script(failure) => matches!(
+                    failure,
+                    ScriptFailure::AppNotRunning
+                        | ScriptFailure::NoWindow
+                        | ScriptFailure::Timeout
+                        | ScriptFailure::Other { .. }
+                ),
+            },
+            AppError::Storage(StorageError::Sqlite(e)) => is_busy(e),
+            AppError::Io(error) => matches!(
+                error.kind(),
+                io::ErrorKind::Interrupted | io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+            ),
+            AppError::Remote(info) => info.retryable,
+            AppError::Context { source, .. } => source.retryable(),
+            _ => false,
+        }
+    }
+}
+
+fn permission_code(permission: Permission) -> String {
+    match permission {
+        Permission::Accessibility => "permission.accessibility",
+        Permission::Automation => "permission.automation",
+        Permission::ScreenRecording => "permission.screenRecording",
+    }
+    .to_string()
+}
+
+/// Another connection holds the database; it frees up on its own.
+fn is_busy(error: &rusqlite::Error) -> bool {
+    matches!(
+        error.sqlite_error_code(),
+        Some(ErrorCode::DatabaseBusy) | Some(ErrorCode::DatabaseLocked)
+    )
+}
+
+impl From<MacOSError> for AppError {
+    fn from(error: MacOSError) -> Self {
+        match error {
+            MacOSError::Script(ScriptFailure::PermissionDenied { permission }) => {
+                AppError::Permission {
+                    permission,
+                    target: None,
+                }
+            }
+            error => AppError::Probe(error),
+        }
+    }
+}
+
+impl From<RuleError> for AppError {
+    fn from(error: RuleError) -> Self {
+        AppError::Validation(error.to_string())
+    }
+}
+
+impl From<NormalizeError> for AppError {
+    fn from(error: NormalizeError) -> Self {
+        AppError::Validation(error.to_string())
+    }
 }
 
-#[derive(Serialize, Debug, Clone)]
+impl Serialize for AppError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ErrorInfo::from(self).serialize(serializer)
+    }
+}
+
+/// Adds what was being attempted to a failed result.
+pub trait Context<T> {
+    fn context(self, context: &str) -> Result<T, AppError>;
+}
+
+impl<T, E: Into<AppError>> Context<T> for Result<T, E> {
+    fn context(self, context: &str) -> Result<T, AppError> {
+        self.map_err(|e| e.into().context(context))
+    }
+}
+
+/// An `AppError` as it is sent to the webview and over the socket.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, TS)]
+pub struct ErrorInfo {
+    pub code: String,
+    pub message: String,
+    pub retryable: bool,
+}
+
+impl From<&AppError> for ErrorInfo {
+    fn from(error: &AppError) -> Self {
+        ErrorInfo {
+            code: error.code(),
+            message: error.to_string(),
+            retryable: error.retryable(),
+        }
+    }
+}
+
+/// Payload of the `idleStarted` and `idleEnded` events: when the user went
+/// away or came back, in unix milliseconds.
+#[derive(Serialize, Debug, Clone, PartialEq, TS)]
+pub struct IdleEvent {
+    #[ts(type = "number")]
+    pub at: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, TS)]
 pub struct ActiveWindow {
     pub class: String,
     pub title: String,
+    /// The active tab's address, for browsers whose scripting dictionary
+    /// exposes it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    pub url: Option<String>,
+    /// The title as the app reported it, when `title` has been normalized.
+    #[serde(rename = "rawTitle", default, skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    pub raw_title: Option<String>,
 }
 
 impl ActiveWindow {
@@ -27,12 +346,46 @@ impl ActiveWindow {
         ActiveWindow {
             title: "none".to_string(),
             class: "none".to_string(),
+            url: None,
+            raw_title: None,
         }
     }
 }
 
+// The raw title is left out, so noise the normalizer removed is not a change
 impl PartialEq for ActiveWindow {
     fn eq(&self, other: &Self) -> bool {
-        self.class == other.class && self.title == other.title
+        self.class == other.class && self.title == other.title && self.url == other.url
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errors_serialize_with_a_code_and_whether_to_retry() {
+        let denied = AppError::from(MacOSError::Script(ScriptFailure::PermissionDenied {
+            permission: Permission::Automation,
+        }))
+        .context("Unable to read the active tab");
+
+        assert_eq!(
+            serde_json::to_value(&denied).unwrap(),
+            serde_json::json!({
+                "code": "permission.automation",
+                "message": "Unable to read the active tab: Automation permission is not granted",
+                "retryable": false,
+            })
+        );
+        assert!(AppError::from(MacOSError::Script(ScriptFailure::Timeout)).retryable());
+        assert_eq!(
+            AppError::from(io::Error::from(io::ErrorKind::NotFound)).code(),
+            "io.notFound"
+        );
+        assert_eq!(
+            AppError::DataDirUnavailable("no home folder".to_string()).code(),
+            "internal.dataDirUnavailable"
+        );
     }
 }
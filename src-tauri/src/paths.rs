@@ -0,0 +1,38 @@
+use std::{env, path::PathBuf};
+
+/// Must match `identifier` in `tauri.conf.json`.
+pub const APP_IDENTIFIER: &str = "com.sushant.locus";
+
+pub const DATABASE_FILE_NAME: &str = "locus.db";
+
+/// The folder Tauri's `app_data_dir` resolves to, for code that runs without
+/// an app handle, like the CLI.
+pub fn app_data_dir() -> Option<PathBuf> {
+    #[cfg(target_os = "macos")]
+    let base =
+        env::var_os("HOME").map(|home| PathBuf::from(home).join("Library/Application Support"));
+
+    #[cfg(not(target_os = "macos"))]
+    let base = env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")));
+
+    base.map(|base| base.join(APP_IDENTIFIER))
+}
+
+/// The folder Tauri's `app_log_dir` resolves to.
+pub fn app_log_dir() -> Option<PathBuf> {
+    #[cfg(target_os = "macos")]
+    return env::var_os("HOME").map(|home| {
+        PathBuf::from(home)
+            .join("Library/Logs")
+            .join(APP_IDENTIFIER)
+    });
+
+    #[cfg(not(target_os = "macos"))]
+    return app_data_dir().map(|dir| dir.join("logs"));
+}
+
+pub fn database_path() -> Option<PathBuf> {
+    app_data_dir().map(|dir| dir.join(DATABASE_FILE_NAME))
+}
@@ -0,0 +1,504 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::time::{interval, MissedTickBehavior};
+use ts_rs::TS;
+
+use crate::activity::Session;
+use crate::clock::{SystemClock, WallClock};
+use crate::events::{self, AppEvent};
+use crate::model::{AppError, PomodoroError};
+use crate::stream::stream_utils;
+
+/// Setting the last timer settings started with are saved under, to lay out
+/// the phases of sessions that don't record their own.
+pub const POMODORO_SETTINGS_SETTING: &str = "pomodoro.settings";
+
+/// Mirrors the `timer.settings` store key written by the frontend.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct PomodoroSettings {
+    #[ts(type = "number")]
+    pub session_length_in_seconds: u64,
+    pub number_of_sessions: u32,
+    #[ts(type = "number")]
+    pub break_length_in_seconds: u64,
+}
+
+impl PomodoroSettings {
+    /// The session a run with these settings records, with the same totals
+    /// the frontend stores on `SessionHistory`.
+    pub fn session(&self, id: String, started_on: i64) -> Session {
+        let sessions = u64::from(self.number_of_sessions);
+        Session {
+            id,
+            pomodoro_length_in_seconds: ((self.session_length_in_seconds
+                + self.break_length_in_seconds)
+                * sessions) as i64,
+            break_length_in_seconds: (self.break_length_in_seconds * sessions) as i64,
+            session_started_on: started_on,
+            number_of_sessions: Some(self.number_of_sessions),
+        }
+    }
+}
+
+impl Default for PomodoroSettings {
+    fn default() -> Self {
+        PomodoroSettings {
+            session_length_in_seconds: 25 * 60,
+            number_of_sessions: 2,
+            break_length_in_seconds: 5 * 60,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, TS)]
+#[serde(rename_all = "camelCase")]
+pub enum Phase {
+    Idle,
+    Focus,
+    ShortBreak,
+    Paused,
+    Finished,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TimerState {
+    Idle,
+    /// `ends_at` is in unix milliseconds.
+    Running {
+        phase: Phase,
+        ends_at: i64,
+    },
+    Paused {
+        phase: Phase,
+        remaining: Duration,
+    },
+    Finished,
+}
+
+/// What the UI needs to render the timer.
+#[derive(Serialize, Debug, Clone, PartialEq, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct PomodoroSnapshot {
+    pub phase: Phase,
+    /// The phase a paused timer resumes into.
+    pub paused_phase: Option<Phase>,
+    #[ts(type = "number")]
+    pub remaining_seconds: u64,
+    pub completed_sessions: u32,
+    pub settings: PomodoroSettings,
+    pub session_id: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct PhaseChange {
+    pub from: Phase,
+    pub to: Phase,
+    pub snapshot: PomodoroSnapshot,
+}
+
+/// Pomodoro timer as an explicit state machine.
+///
+/// Remaining time is derived from deadlines on the wall clock rather than
+/// counted ticks, so a late or throttled `tick` catches up instead of
+/// drifting, and time the Mac spent asleep counts.
+pub struct PomodoroTimer<C: WallClock> {
+    clock: C,
+    settings: PomodoroSettings,
+    state: TimerState,
+    completed_sessions: u32,
+    session_id: Option<String>,
+}
+
+impl<C: WallClock> PomodoroTimer<C> {
+    pub fn new(clock: C) -> Self {
+        PomodoroTimer {
+            clock,
+            settings: PomodoroSettings::default(),
+            state: TimerState::Idle,
+            completed_sessions: 0,
+            session_id: None,
+        }
+    }
+
+    pub fn phase(&self) -> Phase {
+        match self.state {
+            TimerState::Idle => Phase::Idle,
+            TimerState::Running { phase, .. } => phase,
+            TimerState::Paused { .. } => Phase::Paused,
+            TimerState::Finished => Phase::Finished,
+        }
+    }
+
+    pub fn snapshot(&self) -> PomodoroSnapshot {
+        let (paused_phase, remaining) = match self.state {
+            TimerState::Running { ends_at, .. } => (None, self.until(ends_at)),
+            TimerState::Paused { phase, remaining } => (Some(phase), remaining),
+            TimerState::Idle => (
+                None,
+                Duration::from_secs(self.settings.session_length_in_seconds),
+            ),
+            TimerState::Finished => (None, Duration::ZERO),
+        };
+
+        PomodoroSnapshot {
+            phase: self.phase(),
+            paused_phase,
+            // Round up so the display shows 00:00 only once time is really up
+            remaining_seconds: remaining.as_millis().div_ceil(1000) as u64,
+            completed_sessions: self.completed_sessions,
+            settings: self.settings,
+            session_id: self.session_id.clone(),
+        }
+    }
+
+    /// Starts a fresh run of focus sessions from `Idle` or `Finished`.
+    pub fn start(
+        &mut self,
+        settings: PomodoroSettings,
+        session_id: String,
+    ) -> Result<PhaseChange, PomodoroError> {
+        if !matches!(self.state, TimerState::Idle | TimerState::Finished) {
+            return Err(self.invalid("start"));
+        }
+        if settings.session_length_in_seconds == 0 || settings.number_of_sessions == 0 {
+            return Err(PomodoroError::InvalidSettings);
+        }
+
+        self.settings = settings;
+        self.completed_sessions = 0;
+        self.session_id = Some(session_id);
+
+        let now = self.clock.now_millis();
+        Ok(self.transition(TimerState::Running {
+            phase: Phase::Focus,
+            ends_at: now + self.phase_length(Phase::Focus),
+        }))
+    }
+
+    pub fn pause(&mut self) -> Result<PhaseChange, PomodoroError> {
+        let TimerState::Running { phase, ends_at } = self.state else {
+            return Err(self.invalid("pause"));
+        };
+
+        let remaining = self.until(ends_at);
+        Ok(self.transition(TimerState::Paused { phase, remaining }))
+    }
+
+    pub fn resume(&mut self) -> Result<PhaseChange, PomodoroError> {
+        let TimerState::Paused { phase, remaining } = self.state else {
+            return Err(self.invalid("resume"));
+        };
+
+        let ends_at = self.clock.now_millis() + remaining.as_millis() as i64;
+        Ok(self.transition(TimerState::Running { phase, ends_at }))
+    }
+
+    /// Ends the current focus or break early, as if its time had run out.
+    pub fn skip(&mut self) -> Result<PhaseChange, PomodoroError> {
+        let phase = match self.state {
+            TimerState::Running { phase, .. } | TimerState::Paused { phase, .. } => phase,
+            _ => return Err(self.invalid("skip")),
+        };
+
+        let next = self.next_state(phase, self.clock.now_millis());
+        Ok(self.transition(next))
+    }
+
+    pub fn abort(&mut self) -> Result<PhaseChange, PomodoroError> {
+        if self.state == TimerState::Idle {
+            return Err(self.invalid("abort"));
+        }
+
+        self.completed_sessions = 0;
+        self.session_id = None;
+        Ok(self.transition(TimerState::Idle))
+    }
+
+    /// Advances past every deadline that has passed, returning the phase
+    /// changes in order.
+    pub fn tick(&mut self) -> Vec<PhaseChange> {
+        let now = self.clock.now_millis();
+        let mut changes = Vec::new();
+
+        while let TimerState::Running { phase, ends_at } = self.state {
+            if now < ends_at {
+                break;
+            }
+
+            // Chain from the deadline, not from `now`, so late ticks don't stretch phases
+            let next = self.next_state(phase, ends_at);
+            changes.push(self.transition(next));
+        }
+
+        changes
+    }
+
+    fn next_state(&mut self, finished: Phase, at: i64) -> TimerState {
+        match finished {
+            Phase::Focus => {
+                self.completed_sessions += 1;
+                TimerState::Running {
+                    phase: Phase::ShortBreak,
+                    ends_at: at + self.phase_length(Phase::ShortBreak),
+                }
+            }
+            _ if self.completed_sessions >= self.settings.number_of_sessions => {
+                TimerState::Finished
+            }
+            _ => TimerState::Running {
+                phase: Phase::Focus,
+                ends_at: at + self.phase_length(Phase::Focus),
+            },
+        }
+    }
+
+    /// Length of `phase` in milliseconds.
+    fn phase_length(&self, phase: Phase) -> i64 {
+        let seconds = match phase {
+            Phase::Focus => self.settings.session_length_in_seconds,
+            Phase::ShortBreak => self.settings.break_length_in_seconds,
+            _ => 0,
+        };
+        seconds as i64 * 1000
+    }
+
+    fn until(&self, deadline: i64) -> Duration {
+        Duration::from_millis((deadline - self.clock.now_millis()).max(0) as u64)
+    }
+
+    fn transition(&mut self, to: TimerState) -> PhaseChange {
+        let from = self.phase();
+        self.state = to;
+
+        PhaseChange {
+            from,
+            to: self.phase(),
+            snapshot: self.snapshot(),
+        }
+    }
+
+    fn invalid(&self, action: &'static str) -> PomodoroError {
+        PomodoroError::InvalidTransition {
+            action,
+            phase: self.phase(),
+        }
+    }
+}
+
+pub type PomodoroState = Arc<Mutex<PomodoroTimer<SystemClock>>>;
+
+pub fn emit_phase_change(app: &tauri::AppHandle, change: &PhaseChange) {
+    events::emit(app, AppEvent::PomodoroPhaseChanged(change.clone()));
+}
+
+/// Applies a user action to the shared timer and tells the UI about the
+/// resulting phase change.
+pub fn apply<F>(
+    app: &tauri::AppHandle,
+    pomodoro: &PomodoroState,
+    action: F,
+) -> Result<PomodoroSnapshot, PomodoroError>
+where
+    F: FnOnce(&mut PomodoroTimer<SystemClock>) -> Result<PhaseChange, PomodoroError>,
+{
+    let change = {
+        let mut timer = pomodoro.lock().map_err(|_| PomodoroError::Poisoned)?;
+        action(&mut timer)?
+    };
+
+    emit_phase_change(app, &change);
+    Ok(change.snapshot)
+}
+
+/// Applies `action` like `apply`, then starts or stops tracking to match.
+pub async fn apply_and_track<F>(
+    app: &tauri::AppHandle,
+    pomodoro: &PomodoroState,
+    action: F,
+) -> Result<PomodoroSnapshot, AppError>
+where
+    F: FnOnce(&mut PomodoroTimer<SystemClock>) -> Result<PhaseChange, PomodoroError>,
+{
+    // Aborting forgets the session, which tracking still has to leave
+    let session_id = pomodoro
+        .lock()
+        .map_err(|_| PomodoroError::Poisoned)?
+        .snapshot()
+        .session_id;
+    let snapshot = apply(app, pomodoro, action)?;
+
+    track(
+        app,
+        snapshot.session_id.clone().or(session_id),
+        snapshot.phase,
+    )
+    .await?;
+    Ok(snapshot)
+}
+
+/// Keeps tracking in step with the timer: recording under the timer's session
+/// while a focus or break runs, and not while it is paused or over.
+pub async fn track(
+    app: &tauri::AppHandle,
+    session_id: Option<String>,
+    phase: Phase,
+) -> Result<(), AppError> {
+    let Some(session_id) = session_id else {
+        return Ok(());
+    };
+
+    match phase {
+        Phase::Focus | Phase::ShortBreak => {
+            stream_utils::start_tracking(app, Some(session_id), None).await?;
+            Ok(())
+        }
+        Phase::Idle | Phase::Paused | Phase::Finished => {
+            stream_utils::stop_tracking(app, &session_id).await
+        }
+    }
+}
+
+/// Drives the timer from the backend once a second, independent of the
+/// webview's (possibly throttled) JS timers.
+pub async fn run_ticker(app: tauri::AppHandle, pomodoro: PomodoroState) {
+    let mut ticker = interval(Duration::from_secs(1));
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+    loop {
+        ticker.tick().await;
+
+        let (changes, snapshot) = match pomodoro.lock() {
+            Ok(mut timer) => (timer.tick(), timer.snapshot()),
+            Err(_) => {
+                let error = AppError::from(PomodoroError::Poisoned)
+                    .context("The pomodoro timer stopped after an internal error");
+                events::emit_error(&app, &error);
+                break;
+            }
+        };
+
+        for change in &changes {
+            emit_phase_change(&app, change);
+        }
+        if let Some(change) = changes.last() {
+            let session_id = change.snapshot.session_id.clone();
+            if let Err(e) = track(&app, session_id, change.to).await {
+                events::emit_error(&app, &e);
+            }
+        }
+
+        if matches!(snapshot.phase, Phase::Focus | Phase::ShortBreak) {
+            events::emit(&app, AppEvent::PomodoroTick(snapshot));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    fn settings() -> PomodoroSettings {
+        PomodoroSettings {
+            session_length_in_seconds: 25 * 60,
+            number_of_sessions: 2,
+            break_length_in_seconds: 5 * 60,
+        }
+    }
+
+    fn started(clock: &ManualClock) -> PomodoroTimer<ManualClock> {
+        let mut timer = PomodoroTimer::new(clock.clone());
+        timer.start(settings(), "session".to_string()).unwrap();
+        timer
+    }
+
+    #[test]
+    fn runs_focus_and_breaks_until_finished() {
+        let clock = ManualClock::new();
+        let mut timer = started(&clock);
+
+        clock.advance(Duration::from_secs(25 * 60));
+        let changes = timer.tick();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(
+            (changes[0].from, changes[0].to),
+            (Phase::Focus, Phase::ShortBreak)
+        );
+        assert_eq!(timer.snapshot().completed_sessions, 1);
+
+        clock.advance(Duration::from_secs(5 * 60));
+        assert_eq!(timer.tick()[0].to, Phase::Focus);
+
+        clock.advance(Duration::from_secs(30 * 60));
+        let phases: Vec<_> = timer.tick().iter().map(|change| change.to).collect();
+        assert_eq!(phases, vec![Phase::ShortBreak, Phase::Finished]);
+        assert_eq!(timer.snapshot().completed_sessions, 2);
+    }
+
+    #[test]
+    fn a_late_tick_catches_up_without_drift() {
+        let clock = ManualClock::new();
+        let mut timer = started(&clock);
+
+        // Webview asleep for 26 minutes: focus ended a minute into the break
+        clock.advance(Duration::from_secs(26 * 60));
+        timer.tick();
+
+        assert_eq!(timer.phase(), Phase::ShortBreak);
+        assert_eq!(timer.snapshot().remaining_seconds, 4 * 60);
+    }
+
+    #[test]
+    fn pausing_freezes_the_remaining_time() {
+        let clock = ManualClock::new();
+        let mut timer = started(&clock);
+
+        clock.advance(Duration::from_secs(10 * 60));
+        timer.pause().unwrap();
+        clock.advance(Duration::from_secs(60 * 60));
+        assert!(timer.tick().is_empty());
+
+        let snapshot = timer.snapshot();
+        assert_eq!(snapshot.phase, Phase::Paused);
+        assert_eq!(snapshot.paused_phase, Some(Phase::Focus));
+        assert_eq!(snapshot.remaining_seconds, 15 * 60);
+
+        timer.resume().unwrap();
+        assert_eq!(timer.phase(), Phase::Focus);
+        assert_eq!(timer.snapshot().remaining_seconds, 15 * 60);
+    }
+
+    #[test]
+    fn skip_and_abort_follow_the_same_transitions() {
+        let clock = ManualClock::new();
+        let mut timer = started(&clock);
+
+        assert_eq!(timer.skip().unwrap().to, Phase::ShortBreak);
+        assert_eq!(timer.skip().unwrap().to, Phase::Focus);
+        assert_eq!(timer.abort().unwrap().to, Phase::Idle);
+        assert_eq!(timer.snapshot().session_id, None);
+    }
+
+    #[test]
+    fn invalid_transitions_are_rejected() {
+        let clock = ManualClock::new();
+        let mut timer = PomodoroTimer::new(clock.clone());
+
+        assert!(matches!(
+            timer.pause(),
+            Err(PomodoroError::InvalidTransition {
+                action: "pause",
+                phase: Phase::Idle
+            })
+        ));
+        assert!(timer.resume().is_err());
+
+        timer.start(settings(), "session".to_string()).unwrap();
+        assert!(timer.start(settings(), "other".to_string()).is_err());
+    }
+}
@@ -0,0 +1,328 @@
+pub mod client;
+pub mod server;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::model::{AppError, ErrorInfo};
+use crate::storage::SegmentFilter;
+
+/// A JSON-RPC 2.0 error object.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RpcError {
+    pub code: i32,
+    pub message: String,
+    /// The structured error, for failures while carrying out a call.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Box<ErrorInfo>>,
+}
+
+impl RpcError {
+    pub const PARSE_ERROR: i32 = -32700;
+    pub const INVALID_REQUEST: i32 = -32600;
+    pub const METHOD_NOT_FOUND: i32 = -32601;
+    pub const INVALID_PARAMS: i32 = -32602;
+    /// Any failure while carrying out a valid call.
+    pub const SERVER_ERROR: i32 = -32000;
+
+    pub fn new(code: i32, message: impl Into<String>) -> Self {
+        RpcError {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+
+    /// The failure as the app reports it, keeping the daemon's error code.
+    pub fn into_app_error(self) -> AppError {
+        AppError::Remote(self.data.map_or_else(
+            || ErrorInfo {
+                code: "daemon.failed".to_string(),
+                message: self.message,
+                retryable: false,
+            },
+            |data| *data,
+        ))
+    }
+}
+
+impl From<AppError> for RpcError {
+    fn from(error: AppError) -> Self {
+        let data = ErrorInfo::from(&error);
+        RpcError {
+            code: RpcError::SERVER_ERROR,
+            message: data.message.clone(),
+            data: Some(Box::new(data)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Response {
+    pub jsonrpc: String,
+    pub id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<RpcError>,
+}
+
+impl Response {
+    pub fn new(id: Value, outcome: Result<Value, RpcError>) -> Self {
+        let (result, error) = match outcome {
+            Ok(result) => (Some(result), None),
+            Err(error) => (None, Some(error)),
+        };
+
+        Response {
+            jsonrpc: "2.0".to_string(),
+            id,
+            result,
+            error,
+        }
+    }
+}
+
+/// A message the server sends unasked: one event of a subscription.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Notification {
+    pub jsonrpc: String,
+    pub method: String,
+    pub params: EventParams,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EventParams {
+    pub subscription: u64,
+    /// The event as the webview receives it.
+    pub event: Value,
+}
+
+impl Notification {
+    pub fn event(subscription: u64, event: Value) -> Self {
+        Notification {
+            jsonrpc: "2.0".to_string(),
+            method: "event".to_string(),
+            params: EventParams {
+                subscription,
+                event,
+            },
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Request {
+    jsonrpc: String,
+    #[serde(default)]
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct StartStreamParams {
+    pub session_id: Option<String>,
+    pub record_to: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct SubscriptionParams {
+    pub subscription: u64,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct SessionRange {
+    pub from: i64,
+    pub to: i64,
+}
+
+/// The calls the socket accepts. Method names are the snake_case variant names.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Call {
+    CurrentWindow,
+    PomodoroState,
+    StartStream(StartStreamParams),
+    StopStream,
+    /// Whether the tracking loop is running.
+    StreamRunning,
+    QueryActivity(SegmentFilter),
+    QuerySessions(SessionRange),
+    /// Re-read the rules from storage after another process changed them.
+    ReloadRules,
+    /// What the window probe has cost since tracking started.
+    ProbeCounters,
+    /// Re-read the title normalization config, likewise.
+    ReloadNormalization,
+    /// Send the running stream's events to this connection, as `event`
+    /// notifications, until unsubscribed or the stream stops. Returns the
+    /// subscription id.
+    Subscribe,
+    Unsubscribe(SubscriptionParams),
+    /// Give the socket, and the live tracking, to a headless daemon that is
+    /// starting. Returns the session being tracked, if any.
+    Handover,
+}
+
+/// Parses one request line. On failure, returns the error response to send.
+///
+/// Requests without an `id` are still answered, with a `null` id; the socket
+/// has no use for fire-and-forget notifications.
+pub fn parse_request(line: &str) -> Result<(Value, Call), Box<Response>> {
+    let request: Request = serde_json::from_str(line).map_err(|e| {
+        let code = if e.is_data() {
+            RpcError::INVALID_REQUEST
+        } else {
+            RpcError::PARSE_ERROR
+        };
+        Box::new(Response::new(
+            Value::Null,
+            Err(RpcError::new(code, e.to_string())),
+        ))
+    })?;
+
+    if request.jsonrpc != "2.0" {
+        return Err(Box::new(Response::new(
+            request.id,
+            Err(RpcError::new(
+                RpcError::INVALID_REQUEST,
+                "Only JSON-RPC 2.0 is supported",
+            )),
+        )));
+    }
+
+    match parse_call(&request.method, request.params) {
+        Ok(call) => Ok((request.id, call)),
+        Err(error) => Err(Box::new(Response::new(request.id, Err(error)))),
+    }
+}
+
+fn parse_call(method: &str, params: Value) -> Result<Call, RpcError> {
+    let call = match method {
+        "current_window" => Call::CurrentWindow,
+        "pomodoro_state" => Call::PomodoroState,
+        "start_stream" => Call::StartStream(params_or_default(params)?),
+        "stop_stream" => Call::StopStream,
+        "stream_running" => Call::StreamRunning,
+        "reload_rules" => Call::ReloadRules,
+        "reload_normalization" => Call::ReloadNormalization,
+        "probe_counters" => Call::ProbeCounters,
+        "query_activity" => Call::QueryActivity(params_or_default(params)?),
+        "query_sessions" => Call::QuerySessions(required_params(params)?),
+        "subscribe" => Call::Subscribe,
+        "unsubscribe" => Call::Unsubscribe(required_params(params)?),
+        "handover" => Call::Handover,
+        other => {
+            return Err(RpcError::new(
+                RpcError::METHOD_NOT_FOUND,
+                format!("Unknown method `{}`", other),
+            ))
+        }
+    };
+
+    Ok(call)
+}
+
+fn required_params<T: for<'de> Deserialize<'de>>(params: Value) -> Result<T, RpcError> {
+    serde_json::from_value(params)
+        .map_err(|e| RpcError::new(RpcError::INVALID_PARAMS, e.to_string()))
+}
+
+fn params_or_default<T: Default + for<'de> Deserialize<'de>>(params: Value) -> Result<T, RpcError> {
+    if params.is_null() {
+        return Ok(T::default());
+    }
+
+    required_params(params)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn error_code(line: &str) -> i32 {
+        parse_request(line).unwrap_err().error.unwrap().code
+    }
+
+    #[test]
+    fn parses_calls_with_and_without_params() {
+        assert_eq!(
+            parse_request(r#"{"jsonrpc":"2.0","id":1,"method":"current_window"}"#).unwrap(),
+            (json!(1), Call::CurrentWindow)
+        );
+        assert_eq!(
+            parse_request(
+                r#"{"jsonrpc":"2.0","id":"a","method":"query_activity","params":{"from":10,"class":"Code"}}"#
+            )
+            .unwrap()
+            .1,
+            Call::QueryActivity(SegmentFilter {
+                from: Some(10),
+                class: Some("Code".to_string()),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            parse_request(r#"{"jsonrpc":"2.0","id":2,"method":"start_stream"}"#)
+                .unwrap()
+                .1,
+            Call::StartStream(StartStreamParams::default())
+        );
+    }
+
+    #[test]
+    fn malformed_requests_map_to_json_rpc_error_codes() {
+        assert_eq!(error_code("{"), RpcError::PARSE_ERROR);
+        assert_eq!(error_code(r#"{"id":1}"#), RpcError::INVALID_REQUEST);
+        assert_eq!(
+            error_code(r#"{"jsonrpc":"1.0","id":1,"method":"stop_stream"}"#),
+            RpcError::INVALID_REQUEST
+        );
+        assert_eq!(
+            error_code(r#"{"jsonrpc":"2.0","id":1,"method":"reboot"}"#),
+            RpcError::METHOD_NOT_FOUND
+        );
+        assert_eq!(
+            error_code(r#"{"jsonrpc":"2.0","id":1,"method":"query_sessions","params":{"from":1}}"#),
+            RpcError::INVALID_PARAMS
+        );
+    }
+
+    #[test]
+    fn responses_carry_either_a_result_or_an_error() {
+        let ok = serde_json::to_value(Response::new(json!(7), Ok(json!(null)))).unwrap();
+        assert_eq!(ok, json!({ "jsonrpc": "2.0", "id": 7, "result": null }));
+
+        let failed = serde_json::to_value(Response::new(
+            json!(7),
+            Err(RpcError::new(RpcError::SERVER_ERROR, "boom")),
+        ))
+        .unwrap();
+        assert_eq!(
+            failed,
+            json!({ "jsonrpc": "2.0", "id": 7, "error": { "code": -32000, "message": "boom" } })
+        );
+    }
+
+    #[test]
+    fn server_errors_keep_their_code_across_the_socket() {
+        let error = AppError::from(crate::model::StreamError::NotRunning)
+            .context("Unable to stop the stream");
+        let line = serde_json::to_string(&RpcError::from(error)).unwrap();
+        let relayed = serde_json::from_str::<RpcError>(&line)
+            .unwrap()
+            .into_app_error();
+
+        assert_eq!(
+            ErrorInfo::from(&relayed),
+            ErrorInfo {
+                code: "stream.notRunning".to_string(),
+                message: "Unable to stop the stream: The stream is not running".to_string(),
+                retryable: false,
+            }
+        );
+    }
+}
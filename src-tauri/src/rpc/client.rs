@@ -0,0 +1,54 @@
+use serde_json::{json, Value};
+use std::{io, path::Path, time::Duration};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+
+use crate::rpc::{Response, RpcError};
+
+/// How long to wait on a local server before giving up on it.
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Sends one request to the socket at `path` and waits, without blocking,
+/// for the answer.
+///
+/// The outer error is a transport failure; the inner one is what the server
+/// answered.
+pub async fn request_async(
+    path: &Path,
+    method: &str,
+    params: Value,
+) -> io::Result<Result<Value, RpcError>> {
+    let exchange = async {
+        let mut stream = tokio::net::UnixStream::connect(path).await?;
+        stream.write_all(&request_line(method, params)?).await?;
+
+        let mut answer = String::new();
+        tokio::io::BufReader::new(stream)
+            .read_line(&mut answer)
+            .await?;
+        outcome(&answer)
+    };
+
+    tokio::time::timeout(TIMEOUT, exchange)
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "The server didn't answer"))?
+}
+
+fn request_line(method: &str, params: Value) -> io::Result<Vec<u8>> {
+    let mut line = serde_json::to_vec(&json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": method,
+        "params": params,
+    }))?;
+    line.push(b'\n');
+    Ok(line)
+}
+
+fn outcome(answer: &str) -> io::Result<Result<Value, RpcError>> {
+    let response: Response = serde_json::from_str(answer)?;
+
+    Ok(match response.error {
+        Some(error) => Err(error),
+        None => Ok(response.result.unwrap_or(Value::Null)),
+    })
+}
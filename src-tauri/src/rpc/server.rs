@@ -0,0 +1,442 @@
+use serde::Serialize;
+use serde_json::Value;
+use socket2::{Domain, SockAddr, Socket, Type};
+use std::{
+    collections::HashMap,
+    fs,
+    future::Future,
+    io,
+    os::{
+        fd::OwnedFd,
+        unix::{
+            fs::{DirBuilderExt, PermissionsExt},
+            net::{UnixListener as StdUnixListener, UnixStream as StdUnixStream},
+        },
+    },
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Instant,
+};
+use tauri::Manager;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader},
+    net::UnixListener,
+    sync::mpsc::{unbounded_channel, UnboundedSender},
+};
+
+use crate::events::{self, AppEvent};
+use crate::model::{AppError, Context, PomodoroError, StreamState};
+use crate::pomodoro::PomodoroState;
+use crate::rpc::{parse_request, Call, Notification, Response, RpcError};
+use crate::rules::{RuleSet, RulesState};
+use crate::storage::Storage;
+use crate::stream::manager::{StreamManager, SubscriberHandle};
+use crate::stream::normalize::{NormalizerState, TitleNormalizer};
+use crate::stream::stream_utils;
+use crate::window_info::macos_window_info::StreamEvent;
+
+/// Folder inside the app data folder that holds the socket, private to the
+/// current user.
+pub const SOCKET_DIR_NAME: &str = "api";
+/// File name of the socket inside `SOCKET_DIR_NAME`.
+pub const SOCKET_FILE_NAME: &str = "locus.sock";
+
+/// The socket's path under `data_dir`, creating its folder readable by the
+/// current user only so nobody else can reach the socket, whatever mode it
+/// is bound with.
+pub fn socket_path(data_dir: &Path) -> io::Result<PathBuf> {
+    let dir = data_dir.join(SOCKET_DIR_NAME);
+    fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(&dir)?;
+    // An existing folder keeps its mode, so tighten it too
+    fs::set_permissions(&dir, fs::Permissions::from_mode(0o700))?;
+
+    Ok(dir.join(SOCKET_FILE_NAME))
+}
+
+/// Whether something is already answering on `path`.
+pub fn is_listening(path: &Path) -> bool {
+    StdUnixStream::connect(path).is_ok()
+}
+
+/// Binds the socket at `path`, replacing a stale one. `path` should come from
+/// `socket_path`, whose folder keeps others out from the moment of binding.
+/// Fails with `AddrInUse` if another process is serving it.
+pub fn bind(path: &Path) -> io::Result<UnixListener> {
+    if is_listening(path) {
+        return Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            format!("{} is already being served", path.display()),
+        ));
+    }
+    if path.exists() {
+        fs::remove_file(path)?;
+    }
+
+    let socket = Socket::new(Domain::UNIX, Type::STREAM, None)?;
+    socket.bind(&SockAddr::unix(path)?)?;
+    socket.listen(16)?;
+    socket.set_nonblocking(true)?;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+
+    UnixListener::from_std(StdUnixListener::from(OwnedFd::from(socket)))
+}
+
+/// Accepts connections on `listener` forever, answering calls with `handle`
+/// and subscriptions from `tracking`.
+pub async fn serve<H, Fut>(listener: UnixListener, tracking: Arc<StreamManager>, handle: H)
+where
+    H: Fn(Call) -> Fut + Clone + Send + 'static,
+    Fut: Future<Output = Result<Value, RpcError>> + Send,
+{
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                let handle = handle.clone();
+                let tracking = tracking.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = serve_connection(stream, &tracking, handle).await {
+                        tracing::warn!(error = %e, "API connection failed");
+                    }
+                });
+            }
+            Err(e) => tracing::warn!(error = %e, "Unable to accept an API connection"),
+        }
+    }
+}
+
+/// Answers newline-delimited requests on `stream` until the client hangs up,
+/// interleaved with the events of its subscriptions to `tracking`, which end
+/// with the connection.
+pub async fn serve_connection<S, F, Fut>(
+    stream: S,
+    tracking: &StreamManager,
+    mut handle: F,
+) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    F: FnMut(Call) -> Fut,
+    Fut: Future<Output = Result<Value, RpcError>>,
+{
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut lines = BufReader::new(reader).lines();
+    let (notify, mut notifications) = unbounded_channel();
+    let mut subscriptions = Subscriptions::default();
+
+    let served = loop {
+        let message = tokio::select! {
+            line = lines.next_line() => match line {
+                Ok(Some(line)) if line.trim().is_empty() => continue,
+                Ok(Some(line)) => serde_json::to_vec(&match parse_request(&line) {
+                    Ok((id, Call::Subscribe)) => {
+                        Response::new(id, subscriptions.add(tracking, &notify).await)
+                    }
+                    Ok((id, Call::Unsubscribe(params))) => {
+                        Response::new(id, subscriptions.remove(params.subscription).await)
+                    }
+                    Ok((id, call)) => Response::new(id, handle(call).await),
+                    Err(response) => *response,
+                }),
+                Ok(None) => break Ok(()),
+                Err(e) => break Err(e),
+            },
+            Some(notification) = notifications.recv() => serde_json::to_vec(&notification),
+        };
+
+        let mut encoded = match message {
+            Ok(encoded) => encoded,
+            Err(e) => break Err(e.into()),
+        };
+        encoded.push(b'\n');
+        if let Err(e) = writer.write_all(&encoded).await {
+            break Err(e);
+        }
+    };
+
+    subscriptions.cancel_all().await;
+    served
+}
+
+/// One connection's subscriptions, by the ids its client was given.
+#[derive(Default)]
+struct Subscriptions {
+    next_id: u64,
+    handles: HashMap<u64, SubscriberHandle>,
+}
+
+impl Subscriptions {
+    async fn add(
+        &mut self,
+        tracking: &StreamManager,
+        notify: &UnboundedSender<Notification>,
+    ) -> Result<Value, RpcError> {
+        let id = self.next_id;
+        let notify = notify.clone();
+        let handle = stream_utils::subscribe(tracking, move |event: &StreamEvent| {
+            let event = events::to_json(&AppEvent::from(event));
+            let _ = notify.send(Notification::event(id, event));
+        })
+        .await?;
+
+        self.next_id += 1;
+        self.handles.insert(id, handle);
+        Ok(Value::from(id))
+    }
+
+    async fn remove(&mut self, id: u64) -> Result<Value, RpcError> {
+        let handle = self.handles.remove(&id).ok_or_else(|| {
+            RpcError::new(
+                RpcError::INVALID_PARAMS,
+                format!("No subscription {} on this connection", id),
+            )
+        })?;
+
+        handle.cancel().await;
+        Ok(Value::Null)
+    }
+
+    async fn cancel_all(&mut self) {
+        for (_, handle) in self.handles.drain() {
+            handle.cancel().await;
+        }
+    }
+}
+
+/// The answer to calls `serve_connection` handles itself, should they reach
+/// a handler some other way.
+pub fn connection_only() -> RpcError {
+    RpcError::new(
+        RpcError::INVALID_REQUEST,
+        "Subscriptions are only available over the socket",
+    )
+}
+
+/// Carries out `call` against the state the app manages.
+pub async fn execute(app: &tauri::AppHandle, call: Call) -> Result<Value, RpcError> {
+    match call {
+        Call::CurrentWindow => to_value(app.state::<StreamState>().stream.current_window()),
+        Call::PomodoroState => {
+            let snapshot = app
+                .state::<PomodoroState>()
+                .lock()
+                .map_err(|_| AppError::from(PomodoroError::Poisoned))?
+                .snapshot();
+            to_value(snapshot)
+        }
+        Call::StartStream(params) => {
+            let session_id =
+                stream_utils::start_tracking(app, params.session_id, params.record_to).await?;
+            to_value(session_id)
+        }
+        Call::StopStream => {
+            stream_utils::stop_stream(app.state::<StreamState>()).await?;
+            Ok(Value::Null)
+        }
+        Call::StreamRunning => to_value(app.state::<StreamState>().stream.is_running().await),
+        Call::QueryActivity(filter) => app
+            .state::<Arc<Storage>>()
+            .query_segments(&filter)
+            .context("Unable to read activity")
+            .map_err(RpcError::from)
+            .and_then(to_value),
+        Call::QuerySessions(range) => app
+            .state::<Arc<Storage>>()
+            .sessions_between(range.from, range.to)
+            .context("Unable to read sessions")
+            .map_err(RpcError::from)
+            .and_then(to_value),
+        Call::ProbeCounters => to_value(
+            app.state::<StreamState>()
+                .probe_stats
+                .counters(Instant::now()),
+        ),
+        Call::ReloadRules => {
+            reload_rules(&app.state::<Arc<Storage>>(), &app.state::<RulesState>())?;
+            Ok(Value::Null)
+        }
+        Call::ReloadNormalization => {
+            reload_normalization(
+                &app.state::<Arc<Storage>>(),
+                &app.state::<NormalizerState>(),
+            )?;
+            Ok(Value::Null)
+        }
+        Call::Subscribe | Call::Unsubscribe(_) => Err(connection_only()),
+        Call::Handover => to_value(stream_utils::hand_over(app).await?),
+    }
+}
+
+pub fn reload_rules(storage: &Storage, rules: &RulesState) -> Result<(), AppError> {
+    let loaded = RuleSet::load(storage)?;
+
+    *rules.write().map_err(|_| AppError::Poisoned("Rules"))? = loaded;
+    Ok(())
+}
+
+pub fn reload_normalization(
+    storage: &Storage,
+    normalizer: &NormalizerState,
+) -> Result<(), AppError> {
+    let loaded = TitleNormalizer::load(storage)?;
+
+    *normalizer
+        .write()
+        .map_err(|_| AppError::Poisoned("Title normalization"))? = loaded;
+    Ok(())
+}
+
+fn to_value(value: impl Serialize) -> Result<Value, RpcError> {
+    serde_json::to_value(value).map_err(|e| RpcError::new(RpcError::SERVER_ERROR, e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::idle::{IdleDetector, NeverIdle};
+    use crate::model::ActiveWindow;
+    use crate::rpc::EventParams;
+    use crate::stream::schedule::PollSchedule;
+    use crate::window_info::scripted_source::ScriptedWindowSource;
+    use serde_json::json;
+    use tokio::{io::Lines, net::UnixStream, time::Duration};
+
+    #[tokio::test]
+    async fn answers_each_line_over_the_socket() {
+        let data_dir = std::env::temp_dir().join(format!("locus-{}", uuid::Uuid::new_v4()));
+        let path = socket_path(&data_dir).unwrap();
+        let mode = fs::metadata(path.parent().unwrap())
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o700);
+        let listener = bind(&path).unwrap();
+
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            serve_connection(stream, &StreamManager::default(), |call| async move {
+                match call {
+                    Call::CurrentWindow => Ok(json!({ "class": "Code", "title": "main.rs" })),
+                    _ => Err(RpcError::new(RpcError::SERVER_ERROR, "unsupported")),
+                }
+            })
+            .await
+        });
+
+        let mut client = BufReader::new(UnixStream::connect(&path).await.unwrap());
+        client
+            .get_mut()
+            .write_all(
+                concat!(
+                    r#"{"jsonrpc":"2.0","id":1,"method":"current_window"}"#,
+                    "\n\n",
+                    r#"{"jsonrpc":"2.0","id":2,"method":"stop_stream"}"#,
+                    "\n",
+                    "nonsense\n",
+                )
+                .as_bytes(),
+            )
+            .await
+            .unwrap();
+
+        let mut responses = Vec::new();
+        for _ in 0..3 {
+            let mut line = String::new();
+            client.read_line(&mut line).await.unwrap();
+            responses.push(serde_json::from_str::<Response>(&line).unwrap());
+        }
+        assert_eq!(bind(&path).unwrap_err().kind(), io::ErrorKind::AddrInUse);
+        drop(client);
+        server.await.unwrap().unwrap();
+        fs::remove_dir_all(&data_dir).unwrap();
+
+        assert_eq!(
+            responses[0].result,
+            Some(json!({ "class": "Code", "title": "main.rs" }))
+        );
+        assert_eq!(responses[1].id, json!(2));
+        assert_eq!(responses[1].error.as_ref().unwrap().message, "unsupported");
+        assert_eq!(
+            responses[2].error.as_ref().unwrap().code,
+            RpcError::PARSE_ERROR
+        );
+    }
+
+    async fn next_message<R: tokio::io::AsyncBufRead + Unpin>(lines: &mut Lines<R>) -> Value {
+        serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn subscribers_get_events_until_they_unsubscribe() {
+        let tracking = Arc::new(StreamManager::default());
+        let window = |title: &str| ActiveWindow {
+            class: "Code".to_string(),
+            title: title.to_string(),
+            url: None,
+            raw_title: None,
+        };
+        tracking
+            .start(
+                ScriptedWindowSource::new(vec![
+                    (Duration::ZERO, Some(window("a"))),
+                    (Duration::from_secs(2), Some(window("b"))),
+                    (Duration::from_secs(4), Some(window("c"))),
+                ]),
+                IdleDetector::new(NeverIdle, Duration::from_secs(60)),
+                PollSchedule::fixed(Duration::from_millis(300)),
+            )
+            .await
+            .unwrap();
+
+        let (client, server) = tokio::io::duplex(4096);
+        let server = tokio::spawn({
+            let tracking = tracking.clone();
+            async move { serve_connection(server, &tracking, |_| async { Ok(Value::Null) }).await }
+        });
+        let (reader, mut writer) = tokio::io::split(client);
+        let mut lines = BufReader::new(reader).lines();
+
+        writer
+            .write_all(b"{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"subscribe\"}\n")
+            .await
+            .unwrap();
+        assert_eq!(next_message(&mut lines).await["result"], json!(0));
+
+        let event = |message: Value| -> (u64, String) {
+            let notification: Notification = serde_json::from_value(message).unwrap();
+            let EventParams {
+                subscription,
+                event,
+            } = notification.params;
+            (
+                subscription,
+                event["payload"]["title"].as_str().unwrap().to_string(),
+            )
+        };
+        assert_eq!(event(next_message(&mut lines).await), (0, "a".to_string()));
+        assert_eq!(event(next_message(&mut lines).await), (0, "b".to_string()));
+
+        writer
+            .write_all(
+                concat!(
+                    r#"{"jsonrpc":"2.0","id":2,"method":"unsubscribe","params":{"subscription":0}}"#,
+                    "\n",
+                    r#"{"jsonrpc":"2.0","id":3,"method":"unsubscribe","params":{"subscription":0}}"#,
+                    "\n",
+                )
+                .as_bytes(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(next_message(&mut lines).await["id"], json!(2));
+        // Nothing more from the first subscription, "c" included
+        let answer: Response = serde_json::from_value(next_message(&mut lines).await).unwrap();
+        assert_eq!(answer.error.unwrap().code, RpcError::INVALID_PARAMS);
+
+        drop(writer);
+        drop(lines);
+        server.await.unwrap().unwrap();
+        tracking.stop().await.unwrap();
+    }
+}
@@ -0,0 +1,285 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::{
+    str::FromStr,
+    sync::{Arc, RwLock},
+};
+use ts_rs::TS;
+
+use crate::model::{ActiveWindow, RuleError, StorageError};
+use crate::storage::Storage;
+
+/// Settings key the rules config is stored under.
+pub const RULES_SETTING: &str = "rules";
+
+pub type RulesState = Arc<RwLock<RuleSet>>;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default, TS)]
+#[serde(rename_all = "camelCase")]
+pub enum Productivity {
+    Productive,
+    #[default]
+    Neutral,
+    Distracting,
+}
+
+impl Productivity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Productivity::Productive => "productive",
+            Productivity::Neutral => "neutral",
+            Productivity::Distracting => "distracting",
+        }
+    }
+}
+
+impl FromStr for Productivity {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "productive" => Ok(Productivity::Productive),
+            "neutral" => Ok(Productivity::Neutral),
+            "distracting" => Ok(Productivity::Distracting),
+            other => Err(format!("Unknown productivity `{}`", other)),
+        }
+    }
+}
+
+/// One user-defined rule as written in the rules config.
+///
+/// `class` matches `ActiveWindow.class` exactly, ignoring case; `title` is a
+/// regex searched in the title. A rule with neither matches everything.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Rule {
+    pub name: String,
+    #[serde(default)]
+    pub class: Option<String>,
+    #[serde(default)]
+    pub title: Option<String>,
+    pub category: String,
+    #[serde(default)]
+    pub productivity: Productivity,
+    /// Higher priorities are tried first; equal priorities keep config order.
+    #[serde(default)]
+    pub priority: i32,
+}
+
+/// The rules config, stored as JSON under the [`RULES_SETTING`] key:
+///
+/// ```json
+/// {
+///   "rules": [
+///     { "name": "Rust", "class": "VS Code", "title": "\\.rs\\b",
+///       "category": "Development", "productivity": "productive", "priority": 10 },
+///     { "name": "Social", "title": "(?i)twitter|reddit",
+///       "category": "Social", "productivity": "distracting" }
+///   ],
+///   "fallbackCategory": "Uncategorized"
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RulesConfig {
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+    #[serde(default = "default_fallback_category")]
+    pub fallback_category: String,
+}
+
+fn default_fallback_category() -> String {
+    "Uncategorized".to_string()
+}
+
+impl Default for RulesConfig {
+    fn default() -> Self {
+        RulesConfig {
+            rules: Vec::new(),
+            fallback_category: default_fallback_category(),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Classification {
+    pub category: String,
+    pub productivity: Productivity,
+    /// Name of the rule that matched, if any.
+    pub rule: Option<String>,
+}
+
+struct CompiledRule {
+    rule: Rule,
+    title: Option<Regex>,
+}
+
+impl CompiledRule {
+    fn matches(&self, class: &str, title: &str) -> bool {
+        let class_matches = self
+            .rule
+            .class
+            .as_ref()
+            .is_none_or(|expected| expected.eq_ignore_ascii_case(class));
+        let title_matches = self
+            .title
+            .as_ref()
+            .is_none_or(|pattern| pattern.is_match(title));
+
+        class_matches && title_matches
+    }
+}
+
+/// Rules compiled and sorted for first-match classification.
+#[derive(Default)]
+pub struct RuleSet {
+    config: RulesConfig,
+    rules: Vec<CompiledRule>,
+}
+
+impl RuleSet {
+    pub fn compile(config: RulesConfig) -> Result<Self, RuleError> {
+        let mut rules = config
+            .rules
+            .iter()
+            .map(|rule| {
+                let title =
+                    rule.title
+                        .as_deref()
+                        .map(Regex::new)
+                        .transpose()
+                        .map_err(|source| RuleError::InvalidPattern {
+                            rule: rule.name.clone(),
+                            source,
+                        })?;
+
+                Ok(CompiledRule {
+                    rule: rule.clone(),
+                    title,
+                })
+            })
+            .collect::<Result<Vec<_>, RuleError>>()?;
+
+        // Stable, so rules with equal priority keep their config order
+        rules.sort_by_key(|compiled| std::cmp::Reverse(compiled.rule.priority));
+
+        Ok(RuleSet { config, rules })
+    }
+
+    /// Compiles the rules saved in `storage`, or an empty rule set if none are.
+    pub fn load(storage: &Storage) -> Result<Self, StorageError> {
+        let config = storage.setting(RULES_SETTING)?.unwrap_or_default();
+
+        RuleSet::compile(config).map_err(|e| StorageError::InvalidSetting {
+            key: RULES_SETTING,
+            message: e.to_string(),
+        })
+    }
+
+    pub fn config(&self) -> &RulesConfig {
+        &self.config
+    }
+
+    pub fn classify(&self, class: &str, title: &str) -> Classification {
+        match self.rules.iter().find(|rule| rule.matches(class, title)) {
+            Some(compiled) => Classification {
+                category: compiled.rule.category.clone(),
+                productivity: compiled.rule.productivity,
+                rule: Some(compiled.rule.name.clone()),
+            },
+            None => Classification {
+                category: self.config.fallback_category.clone(),
+                productivity: Productivity::Neutral,
+                rule: None,
+            },
+        }
+    }
+
+    pub fn classify_window(&self, window: &ActiveWindow) -> Classification {
+        self.classify(&window.class, &window.title)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(name: &str, class: Option<&str>, title: Option<&str>, priority: i32) -> Rule {
+        Rule {
+            name: name.to_string(),
+            class: class.map(str::to_string),
+            title: title.map(str::to_string),
+            category: name.to_string(),
+            productivity: Productivity::Productive,
+            priority,
+        }
+    }
+
+    fn rule_set(rules: Vec<Rule>) -> RuleSet {
+        RuleSet::compile(RulesConfig {
+            rules,
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn first_matching_rule_wins_within_a_priority() {
+        let rules = rule_set(vec![
+            rule("Code", Some("vs code"), None, 0),
+            rule("Rust", Some("VS Code"), Some(r"\.rs\b"), 0),
+        ]);
+
+        assert_eq!(
+            rules.classify("VS Code", "main.rs").rule.as_deref(),
+            Some("Code")
+        );
+    }
+
+    #[test]
+    fn higher_priority_rules_are_tried_first() {
+        let rules = rule_set(vec![
+            rule("Code", Some("VS Code"), None, 0),
+            rule("Rust", Some("VS Code"), Some(r"\.rs\b"), 5),
+        ]);
+
+        assert_eq!(
+            rules.classify("VS Code", "main.rs").rule.as_deref(),
+            Some("Rust")
+        );
+        assert_eq!(
+            rules.classify("VS Code", "App.tsx").rule.as_deref(),
+            Some("Code")
+        );
+    }
+
+    #[test]
+    fn unmatched_windows_fall_back_to_neutral() {
+        let rules = rule_set(vec![rule("Social", None, Some("(?i)reddit"), 0)]);
+
+        assert_eq!(
+            rules.classify("Finder", "Downloads"),
+            Classification {
+                category: "Uncategorized".to_string(),
+                productivity: Productivity::Neutral,
+                rule: None,
+            }
+        );
+        assert_eq!(rules.classify("Safari", "Reddit - Home").category, "Social");
+    }
+
+    #[test]
+    fn config_parses_from_json_and_rejects_bad_patterns() {
+        let config: RulesConfig = serde_json::from_str(
+            r#"{ "rules": [{ "name": "Broken", "title": "(", "category": "x" }] }"#,
+        )
+        .unwrap();
+
+        assert_eq!(config.rules[0].productivity, Productivity::Neutral);
+        assert!(matches!(
+            RuleSet::compile(config),
+            Err(RuleError::InvalidPattern { rule, .. }) if rule == "Broken"
+        ));
+    }
+}
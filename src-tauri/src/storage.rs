@@ -0,0 +1,670 @@
+pub mod migrations;
+
+use rusqlite::{
+    params, params_from_iter, types::Value, Connection, OptionalExtension, Params, Row,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    path::Path,
+    sync::{Mutex, MutexGuard},
+    time::Duration,
+};
+
+use crate::activity::{ActivitySegment, SegmentState, Session};
+use crate::model::StorageError;
+use crate::rules::{Classification, Productivity};
+use crate::titles::TitleFields;
+
+/// Narrows a segment query. Ranges are half-open, in unix milliseconds, and
+/// match every segment that overlaps them.
+#[derive(Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SegmentFilter {
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+    pub session_id: Option<String>,
+    pub class: Option<String>,
+    pub title: Option<String>,
+    pub category: Option<String>,
+    pub state: Option<SegmentState>,
+    pub domain: Option<String>,
+    pub project: Option<String>,
+}
+
+const INSERT_SEGMENT: &str = "INSERT INTO segments
+        (session_id, class, title, start, end, category, productivity, state,
+         url, domain, project, file, document, channel, raw_title)
+     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)";
+
+const SELECT_SESSION: &str = "SELECT id, pomodoro_length_seconds, break_length_seconds,
+        started_on, number_of_sessions
+     FROM sessions";
+
+/// Embedded SQLite store for sessions, window segments and settings.
+pub struct Storage {
+    conn: Mutex<Connection>,
+}
+
+impl Storage {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, StorageError> {
+        Self::with_connection(Connection::open(path)?)
+    }
+
+    #[cfg(test)]
+    pub fn open_in_memory() -> Result<Self, StorageError> {
+        Self::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(mut conn: Connection) -> Result<Self, StorageError> {
+        // The app, the headless daemon and the CLI can share one database
+        conn.busy_timeout(Duration::from_secs(5))?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        migrations::migrate(&mut conn)?;
+
+        Ok(Storage {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    fn conn(&self) -> Result<MutexGuard<'_, Connection>, StorageError> {
+        self.conn.lock().map_err(|_| StorageError::Poisoned)
+    }
+
+    /// Inserts a session unless one with the same id already exists.
+    /// Returns whether a row was written.
+    pub fn insert_session(&self, session: &Session) -> Result<bool, StorageError> {
+        self.import_session(session, &[])
+    }
+
+    /// Writes a session and its segments in one transaction, unless the session
+    /// already exists. Returns whether anything was written.
+    pub fn import_session(
+        &self,
+        session: &Session,
+        segments: &[ActivitySegment],
+    ) -> Result<bool, StorageError> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+
+        let inserted = tx.execute(
+            "INSERT OR IGNORE INTO sessions
+                (id, pomodoro_length_seconds, break_length_seconds, started_on,
+                 number_of_sessions)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                session.id,
+                session.pomodoro_length_in_seconds,
+                session.break_length_in_seconds,
+                session.session_started_on,
+                session.number_of_sessions
+            ],
+        )?;
+
+        if inserted == 0 {
+            return Ok(false);
+        }
+
+        {
+            let mut stmt = tx.prepare(INSERT_SEGMENT)?;
+            for segment in segments {
+                stmt.execute(segment_params(segment))?;
+            }
+        }
+
+        tx.commit()?;
+        Ok(true)
+    }
+
+    pub fn session(&self, id: &str) -> Result<Option<Session>, StorageError> {
+        let session = self
+            .conn()?
+            .query_row(
+                &format!("{} WHERE id = ?1", SELECT_SESSION),
+                [id],
+                session_from_row,
+            )
+            .optional()?;
+
+        Ok(session)
+    }
+
+    /// Deletes a session that nothing was recorded under yet.
+    pub fn delete_session(&self, id: &str) -> Result<(), StorageError> {
+        self.conn()?
+            .execute("DELETE FROM sessions WHERE id = ?1", [id])?;
+
+        Ok(())
+    }
+
+    /// Sessions started in `[from, to)`, oldest first.
+    pub fn sessions_between(&self, from: i64, to: i64) -> Result<Vec<Session>, StorageError> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(&format!(
+            "{} WHERE started_on >= ?1 AND started_on < ?2 ORDER BY started_on",
+            SELECT_SESSION
+        ))?;
+        let sessions = stmt
+            .query_map([from, to], session_from_row)?
+            .collect::<Result<_, _>>()?;
+
+        Ok(sessions)
+    }
+
+    /// Stores `segment` and returns its row id.
+    pub fn append_segment(&self, segment: &ActivitySegment) -> Result<i64, StorageError> {
+        let conn = self.conn()?;
+        conn.execute(INSERT_SEGMENT, segment_params(segment))?;
+
+        Ok(conn.last_insert_rowid())
+    }
+
+    /// Moves the end of the segment in row `id`, as the recorder does while
+    /// the segment is still open.
+    pub fn set_segment_end(&self, id: i64, end: i64) -> Result<(), StorageError> {
+        self.conn()?.execute(
+            "UPDATE segments SET end = ?2 WHERE id = ?1",
+            params![id, end],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn delete_segment(&self, id: i64) -> Result<(), StorageError> {
+        self.conn()?
+            .execute("DELETE FROM segments WHERE id = ?1", [id])?;
+
+        Ok(())
+    }
+
+    /// Segments matching `filter`, oldest first.
+    pub fn query_segments(
+        &self,
+        filter: &SegmentFilter,
+    ) -> Result<Vec<ActivitySegment>, StorageError> {
+        let conn = self.conn()?;
+        let (clause, values) = filter_clause(filter);
+        let mut stmt = conn.prepare(&format!(
+            "SELECT session_id, class, title, start, end, category, productivity, state,
+                url, domain, project, file, document, channel, raw_title
+             FROM segments WHERE {} ORDER BY start",
+            clause
+        ))?;
+        let segments = stmt
+            .query_map(params_from_iter(values), segment_from_row)?
+            .collect::<Result<_, _>>()?;
+
+        Ok(segments)
+    }
+
+    /// Re-runs `classify` over every active segment matching `filter` and
+    /// stores the result. Returns the number of segments updated.
+    pub fn reclassify(
+        &self,
+        filter: &SegmentFilter,
+        classify: impl Fn(&str, &str) -> Classification,
+    ) -> Result<usize, StorageError> {
+        let mut conn = self.conn()?;
+        let tx = conn.transaction()?;
+        let mut updated = 0;
+
+        {
+            let (clause, values) = filter_clause(filter);
+            let mut select = tx.prepare(&format!(
+                "SELECT id, class, title FROM segments WHERE {} AND state = 'active'",
+                clause
+            ))?;
+            let mut update =
+                tx.prepare("UPDATE segments SET category = ?2, productivity = ?3 WHERE id = ?1")?;
+
+            let rows = select
+                .query_map(params_from_iter(values), |row| {
+                    Ok((
+                        row.get::<_, i64>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, String>(2)?,
+                    ))
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            for (id, class, title) in rows {
+                let classification = classify(&class, &title);
+                updated += update.execute(params![
+                    id,
+                    classification.category,
+                    classification.productivity.as_str()
+                ])?;
+            }
+        }
+
+        tx.commit()?;
+        Ok(updated)
+    }
+
+    pub fn setting<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>, StorageError> {
+        let value: Option<String> = self
+            .conn()?
+            .query_row("SELECT value FROM settings WHERE key = ?1", [key], |row| {
+                row.get(0)
+            })
+            .optional()?;
+
+        Ok(value
+            .map(|value| serde_json::from_str(&value))
+            .transpose()?)
+    }
+
+    pub fn set_setting<T: Serialize>(&self, key: &str, value: &T) -> Result<(), StorageError> {
+        self.conn()?.execute(
+            "INSERT INTO settings (key, value) VALUES (?1, ?2)
+             ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+            params![key, serde_json::to_string(value)?],
+        )?;
+
+        Ok(())
+    }
+
+    /// The totals cached for `day`, if they were computed for the same
+    /// `[start, end)`; a timezone change moves the bounds.
+    pub fn daily_rollup<T: DeserializeOwned>(
+        &self,
+        day: &str,
+        start: i64,
+        end: i64,
+    ) -> Result<Option<T>, StorageError> {
+        let totals: Option<String> = self
+            .conn()?
+            .query_row(
+                "SELECT totals FROM daily_rollups WHERE day = ?1 AND start = ?2 AND end = ?3",
+                params![day, start, end],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        Ok(totals
+            .map(|totals| serde_json::from_str(&totals))
+            .transpose()?)
+    }
+
+    /// Caches the totals of a finished day. Writing a segment that overlaps
+    /// it drops them again.
+    pub fn set_daily_rollup<T: Serialize>(
+        &self,
+        day: &str,
+        start: i64,
+        end: i64,
+        totals: &T,
+    ) -> Result<(), StorageError> {
+        self.conn()?.execute(
+            "INSERT INTO daily_rollups (day, start, end, totals) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT (day) DO UPDATE
+             SET start = excluded.start, end = excluded.end, totals = excluded.totals",
+            params![day, start, end, serde_json::to_string(totals)?],
+        )?;
+
+        Ok(())
+    }
+}
+
+fn session_from_row(row: &Row) -> rusqlite::Result<Session> {
+    Ok(Session {
+        id: row.get(0)?,
+        pomodoro_length_in_seconds: row.get(1)?,
+        break_length_in_seconds: row.get(2)?,
+        session_started_on: row.get(3)?,
+        number_of_sessions: row.get(4)?,
+    })
+}
+
+fn segment_from_row(row: &Row) -> rusqlite::Result<ActivitySegment> {
+    Ok(ActivitySegment {
+        session_id: row.get(0)?,
+        class: row.get(1)?,
+        title: row.get(2)?,
+        start: row.get(3)?,
+        end: row.get(4)?,
+        category: row.get(5)?,
+        // Unknown values are treated as unclassified rather than failing the query
+        productivity: row
+            .get::<_, Option<String>>(6)?
+            .and_then(|value| value.parse::<Productivity>().ok()),
+        state: row
+            .get::<_, String>(7)?
+            .parse::<SegmentState>()
+            .unwrap_or_default(),
+        fields: TitleFields {
+            url: row.get(8)?,
+            project: row.get(10)?,
+            file: row.get(11)?,
+            document: row.get(12)?,
+            channel: row.get(13)?,
+        },
+        domain: row.get(9)?,
+        raw_title: row.get(14)?,
+    })
+}
+
+fn segment_params(segment: &ActivitySegment) -> impl Params + '_ {
+    (
+        &segment.session_id,
+        &segment.class,
+        &segment.title,
+        segment.start,
+        segment.end,
+        &segment.category,
+        segment
+            .productivity
+            .map(|productivity| productivity.as_str()),
+        segment.state.as_str(),
+        &segment.fields.url,
+        &segment.domain,
+        &segment.fields.project,
+        &segment.fields.file,
+        &segment.fields.document,
+        &segment.fields.channel,
+        &segment.raw_title,
+    )
+}
+
+/// The WHERE clause for `filter` and its parameters. Only the fields that are
+/// set become conditions, so SQLite can use their indexes.
+fn filter_clause(filter: &SegmentFilter) -> (String, Vec<Value>) {
+    let conditions = [
+        ("end >", filter.from.map(Value::from)),
+        ("start <", filter.to.map(Value::from)),
+        ("session_id =", filter.session_id.clone().map(Value::from)),
+        ("class =", filter.class.clone().map(Value::from)),
+        ("title =", filter.title.clone().map(Value::from)),
+        ("category =", filter.category.clone().map(Value::from)),
+        (
+            "state =",
+            filter
+                .state
+                .map(|state| Value::from(state.as_str().to_string())),
+        ),
+        ("domain =", filter.domain.clone().map(Value::from)),
+        ("project =", filter.project.clone().map(Value::from)),
+    ];
+
+    let mut clause = Vec::new();
+    let mut values = Vec::new();
+    for (condition, value) in conditions {
+        if let Some(value) = value {
+            values.push(value);
+            clause.push(format!("{} ?{}", condition, values.len()));
+        }
+    }
+
+    if clause.is_empty() {
+        ("1".to_string(), values)
+    } else {
+        (clause.join(" AND "), values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::activity::segment;
+
+    #[test]
+    fn filters_only_constrain_the_fields_they_set() {
+        let filter = SegmentFilter {
+            from: Some(0),
+            class: Some("Code".to_string()),
+            ..Default::default()
+        };
+        let (clause, values) = filter_clause(&filter);
+        assert_eq!(clause, "end > ?1 AND class = ?2");
+        assert_eq!(filter_clause(&SegmentFilter::default()).0, "1");
+
+        let storage = Storage::open_in_memory().unwrap();
+        let plan: String = storage
+            .conn()
+            .unwrap()
+            .query_row(
+                &format!(
+                    "EXPLAIN QUERY PLAN SELECT id FROM segments WHERE {}",
+                    clause
+                ),
+                params_from_iter(values),
+                |row| row.get(3),
+            )
+            .unwrap();
+        assert!(plan.contains("USING INDEX segments_class"), "{}", plan);
+    }
+
+    #[test]
+    fn migrations_bring_a_fresh_database_to_the_latest_version() {
+        let storage = Storage::open_in_memory().unwrap();
+        let conn = storage.conn().unwrap();
+
+        assert_eq!(
+            migrations::schema_version(&conn).unwrap(),
+            migrations::latest_version()
+        );
+    }
+
+    #[test]
+    fn processes_opening_a_new_database_together_migrate_it_once() {
+        let data_dir = std::env::temp_dir().join(format!("locus-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&data_dir).unwrap();
+        let path = data_dir.join("locus.db");
+        let barrier = std::sync::Arc::new(std::sync::Barrier::new(4));
+
+        let opened: Vec<_> = (0..4)
+            .map(|_| {
+                let (path, barrier) = (path.clone(), barrier.clone());
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    Storage::open(path).map(|_| ())
+                })
+            })
+            .collect();
+        for handle in opened {
+            handle.join().unwrap().unwrap();
+        }
+
+        let storage = Storage::open(&path).unwrap();
+        assert_eq!(
+            migrations::schema_version(&storage.conn().unwrap()).unwrap(),
+            migrations::latest_version()
+        );
+        drop(storage);
+        std::fs::remove_dir_all(&data_dir).unwrap();
+    }
+
+    #[test]
+    fn sessions_are_inserted_once() {
+        let storage = Storage::open_in_memory().unwrap();
+        let session = Session {
+            id: "a".to_string(),
+            pomodoro_length_in_seconds: 1500,
+            break_length_in_seconds: 300,
+            session_started_on: 1_000,
+            number_of_sessions: Some(2),
+        };
+
+        assert!(storage.insert_session(&session).unwrap());
+        assert!(!storage.insert_session(&session).unwrap());
+        assert_eq!(
+            storage.sessions_between(0, 2_000).unwrap(),
+            vec![session.clone()]
+        );
+        assert_eq!(storage.session("a").unwrap(), Some(session));
+        assert_eq!(storage.session("b").unwrap(), None);
+
+        storage.delete_session("a").unwrap();
+        assert_eq!(storage.session("a").unwrap(), None);
+    }
+
+    #[test]
+    fn segments_can_be_filtered_by_range_app_and_title() {
+        let storage = Storage::open_in_memory().unwrap();
+        storage
+            .append_segment(&segment("Code", "main.rs", 0, 1_000).with_session("a"))
+            .unwrap();
+        storage
+            .append_segment(&segment("Slack", "general", 1_000, 2_000).with_session("a"))
+            .unwrap();
+        storage
+            .append_segment(&segment("Code", "lib.rs", 5_000, 6_000).with_session("b"))
+            .unwrap();
+
+        let in_range = storage
+            .query_segments(&SegmentFilter {
+                from: Some(500),
+                to: Some(1_500),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(in_range.len(), 2);
+
+        let code = storage
+            .query_segments(&SegmentFilter {
+                class: Some("Code".to_string()),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(
+            code,
+            vec![
+                segment("Code", "main.rs", 0, 1_000).with_session("a"),
+                segment("Code", "lib.rs", 5_000, 6_000).with_session("b"),
+            ]
+        );
+
+        let general = storage
+            .query_segments(&SegmentFilter {
+                title: Some("general".to_string()),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(
+            general,
+            vec![segment("Slack", "general", 1_000, 2_000).with_session("a")]
+        );
+    }
+
+    #[test]
+    fn reclassify_updates_matching_segments_only() {
+        let storage = Storage::open_in_memory().unwrap();
+        storage
+            .append_segment(&segment("Code", "main.rs", 0, 1_000).with_session("a"))
+            .unwrap();
+        storage
+            .append_segment(&segment("Slack", "general", 1_000, 2_000).with_session("a"))
+            .unwrap();
+        storage
+            .append_segment(
+                &segment("Code", "", 2_000, 3_000)
+                    .with_session("a")
+                    .with_state(SegmentState::Afk),
+            )
+            .unwrap();
+
+        let updated = storage
+            .reclassify(
+                &SegmentFilter {
+                    class: Some("Code".to_string()),
+                    ..Default::default()
+                },
+                |_, title| Classification {
+                    category: format!("Editing {}", title),
+                    productivity: Productivity::Productive,
+                    rule: None,
+                },
+            )
+            .unwrap();
+        assert_eq!(updated, 1);
+
+        let edited = storage
+            .query_segments(&SegmentFilter {
+                category: Some("Editing main.rs".to_string()),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(edited.len(), 1);
+        assert_eq!(edited[0].productivity, Some(Productivity::Productive));
+
+        let slack = storage
+            .query_segments(&SegmentFilter {
+                class: Some("Slack".to_string()),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(slack[0].category, None);
+
+        let away = storage
+            .query_segments(&SegmentFilter {
+                state: Some(SegmentState::Afk),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(away.len(), 1);
+        assert_eq!(away[0].category, None);
+    }
+
+    #[test]
+    fn daily_rollups_are_dropped_when_their_day_gets_a_segment() {
+        let storage = Storage::open_in_memory().unwrap();
+        storage
+            .set_daily_rollup("2024-10-01", 1_000, 2_000, &42)
+            .unwrap();
+        storage
+            .set_daily_rollup("2024-10-02", 2_000, 3_000, &7)
+            .unwrap();
+
+        assert_eq!(
+            storage
+                .daily_rollup::<i64>("2024-10-01", 1_000, 2_000)
+                .unwrap(),
+            Some(42)
+        );
+        assert_eq!(
+            storage
+                .daily_rollup::<i64>("2024-10-01", 1_500, 2_500)
+                .unwrap(),
+            None
+        );
+
+        storage
+            .append_segment(&segment("Code", "main.rs", 1_900, 1_950).with_session("s"))
+            .unwrap();
+        assert_eq!(
+            storage
+                .daily_rollup::<i64>("2024-10-01", 1_000, 2_000)
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            storage
+                .daily_rollup::<i64>("2024-10-02", 2_000, 3_000)
+                .unwrap(),
+            Some(7)
+        );
+    }
+
+    #[test]
+    fn settings_round_trip_as_json() {
+        let storage = Storage::open_in_memory().unwrap();
+
+        assert_eq!(
+            storage
+                .setting::<u32>("chart.minimumActivityDuration")
+                .unwrap(),
+            None
+        );
+        storage
+            .set_setting("chart.minimumActivityDuration", &9)
+            .unwrap();
+        storage
+            .set_setting("chart.minimumActivityDuration", &12)
+            .unwrap();
+        assert_eq!(
+            storage
+                .setting::<u32>("chart.minimumActivityDuration")
+                .unwrap(),
+            Some(12)
+        );
+    }
+}
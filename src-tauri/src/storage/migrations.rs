@@ -0,0 +1,120 @@
+use rusqlite::{Connection, TransactionBehavior};
+
+/// Schema migrations, applied in order. The schema version is the number of
+/// migrations applied and is kept in `PRAGMA user_version`.
+///
+/// Never edit a migration that has shipped; append a new one instead.
+const MIGRATIONS: &[&str] = &[
+    // 1: sessions, window segments and settings
+    r#"
+    CREATE TABLE sessions (
+        id TEXT PRIMARY KEY NOT NULL,
+        pomodoro_length_seconds INTEGER NOT NULL,
+        break_length_seconds INTEGER NOT NULL,
+        started_on INTEGER NOT NULL
+    );
+    CREATE INDEX sessions_started_on ON sessions (started_on);
+
+    CREATE TABLE segments (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        session_id TEXT NOT NULL,
+        class TEXT NOT NULL,
+        title TEXT NOT NULL,
+        start INTEGER NOT NULL,
+        end INTEGER NOT NULL
+    );
+    CREATE INDEX segments_start ON segments (start);
+    CREATE INDEX segments_session ON segments (session_id, start);
+    CREATE INDEX segments_class ON segments (class, start);
+    CREATE INDEX segments_title ON segments (title, start);
+
+    CREATE TABLE settings (
+        key TEXT PRIMARY KEY NOT NULL,
+        value TEXT NOT NULL
+    );
+    "#,
+    // 2: rule-based classification of segments
+    r#"
+    ALTER TABLE segments ADD COLUMN category TEXT;
+    ALTER TABLE segments ADD COLUMN productivity TEXT;
+    CREATE INDEX segments_category ON segments (category, start);
+    "#,
+    // 3: AFK segments
+    r#"
+    ALTER TABLE segments ADD COLUMN state TEXT NOT NULL DEFAULT 'active';
+    "#,
+    // 4: browser tab addresses
+    r#"
+    ALTER TABLE segments ADD COLUMN url TEXT;
+    ALTER TABLE segments ADD COLUMN domain TEXT;
+    CREATE INDEX segments_domain ON segments (domain, start);
+    "#,
+    // 5: fields parsed from window titles
+    r#"
+    ALTER TABLE segments ADD COLUMN project TEXT;
+    ALTER TABLE segments ADD COLUMN file TEXT;
+    ALTER TABLE segments ADD COLUMN document TEXT;
+    ALTER TABLE segments ADD COLUMN channel TEXT;
+    CREATE INDEX segments_project ON segments (project, start);
+    "#,
+    // 6: titles as reported, before normalization
+    r#"
+    ALTER TABLE segments ADD COLUMN raw_title TEXT;
+    "#,
+    // 7: precomputed daily totals, dropped when a segment in their day changes
+    r#"
+    CREATE TABLE daily_rollups (
+        day TEXT PRIMARY KEY NOT NULL,
+        start INTEGER NOT NULL,
+        end INTEGER NOT NULL,
+        totals TEXT NOT NULL
+    );
+
+    CREATE TRIGGER segments_insert_rollups AFTER INSERT ON segments BEGIN
+        DELETE FROM daily_rollups WHERE end > NEW.start AND start < NEW.end;
+    END;
+    CREATE TRIGGER segments_update_rollups AFTER UPDATE ON segments BEGIN
+        DELETE FROM daily_rollups
+        WHERE (end > OLD.start AND start < OLD.end) OR (end > NEW.start AND start < NEW.end);
+    END;
+    CREATE TRIGGER segments_delete_rollups AFTER DELETE ON segments BEGIN
+        DELETE FROM daily_rollups WHERE end > OLD.start AND start < OLD.end;
+    END;
+    "#,
+    // 8: how many focus phases a session was set up with
+    r#"
+    ALTER TABLE sessions ADD COLUMN number_of_sessions INTEGER;
+    "#,
+];
+
+pub fn schema_version(conn: &Connection) -> rusqlite::Result<usize> {
+    conn.query_row("PRAGMA user_version", [], |row| row.get(0))
+}
+
+/// Brings the database up to the latest schema, one transaction per migration.
+///
+/// The app, the daemon and the CLI may open the database at once, so each
+/// transaction takes the write lock up front and checks the version again
+/// under it: a migration another process just applied is skipped.
+pub fn migrate(conn: &mut Connection) -> rusqlite::Result<()> {
+    for (index, migration) in MIGRATIONS.iter().enumerate() {
+        if schema_version(conn)? > index {
+            continue;
+        }
+
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        if schema_version(&tx)? > index {
+            continue;
+        }
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", index + 1)?;
+        tx.commit()?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+pub fn latest_version() -> usize {
+    MIGRATIONS.len()
+}
@@ -1 +1,4 @@
+pub mod manager;
+pub mod normalize;
+pub mod schedule;
 pub mod stream_utils;
@@ -0,0 +1,398 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use tokio::{
+    sync::mpsc::{unbounded_channel, UnboundedSender},
+    task::JoinHandle,
+    time::{interval_at, Duration, Instant, MissedTickBehavior},
+};
+
+use crate::activity::ActivityRecorder;
+use crate::clock::Clock;
+use crate::idle::{IdleDetector, IdleSource};
+use crate::model::{ActiveWindow, StreamError};
+use crate::stream::schedule::PollSchedule;
+use crate::window_info::macos_window_info::{poll_windows, StreamEvent};
+use crate::window_info::window_source::WindowSource;
+
+/// How often subscribers get to save what they have built up so far.
+pub const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Something fed every event of a running stream, on its own task.
+pub trait Subscriber: Send + 'static {
+    fn event(&mut self, event: &StreamEvent);
+
+    /// Called every `CHECKPOINT_INTERVAL` while subscribed.
+    fn checkpoint(&mut self) {}
+
+    /// Called once when the subscription ends: the stream stopped or the
+    /// subscriber was cancelled.
+    fn finish(&mut self) {}
+}
+
+impl<F: FnMut(&StreamEvent) + Send + 'static> Subscriber for F {
+    fn event(&mut self, event: &StreamEvent) {
+        self(event)
+    }
+}
+
+impl Subscriber for ActivityRecorder {
+    fn event(&mut self, event: &StreamEvent) {
+        match event {
+            StreamEvent::Window(window_info) => self.observe(window_info),
+            StreamEvent::IdleStart { idle_for } => self.idle_start(*idle_for),
+            StreamEvent::IdleEnd => self.idle_end(),
+            StreamEvent::Error(_) => {}
+        }
+    }
+
+    fn checkpoint(&mut self) {
+        ActivityRecorder::checkpoint(self)
+    }
+
+    fn finish(&mut self) {
+        ActivityRecorder::finish(self)
+    }
+}
+
+#[derive(Default)]
+struct Subscribers {
+    next_id: u64,
+    senders: HashMap<u64, UnboundedSender<StreamEvent>>,
+    tasks: HashMap<u64, JoinHandle<()>>,
+}
+
+/// State shared by the manager, its producer task and subscriber handles.
+#[derive(Default)]
+struct Shared {
+    subscribers: Mutex<Subscribers>,
+    /// Latest window the stream saw; `None` while stopped or idle.
+    current_window: Mutex<Option<ActiveWindow>>,
+}
+
+impl Shared {
+    fn publish(&self, event: StreamEvent) {
+        let mut subscribers = self.subscribers.lock().unwrap_or_else(|e| e.into_inner());
+
+        if let Ok(mut current) = self.current_window.lock() {
+            match &event {
+                StreamEvent::Window(window_info) => *current = Some(window_info.clone()),
+                StreamEvent::IdleStart { .. } => *current = None,
+                StreamEvent::IdleEnd | StreamEvent::Error(_) => {}
+            }
+        }
+
+        subscribers
+            .senders
+            .retain(|_, sender| sender.send(event.clone()).is_ok());
+    }
+
+    /// Ends every subscription and returns the tasks still draining theirs.
+    fn close(&self) -> Vec<JoinHandle<()>> {
+        let mut subscribers = self.subscribers.lock().unwrap_or_else(|e| e.into_inner());
+        subscribers.senders.clear();
+
+        if let Ok(mut current) = self.current_window.lock() {
+            *current = None;
+        }
+
+        subscribers.tasks.drain().map(|(_, task)| task).collect()
+    }
+}
+
+struct Run {
+    cancel_flag: Arc<tokio::sync::Mutex<bool>>,
+    producer: JoinHandle<()>,
+}
+
+/// Whether `run` is still polling. Replays stop once their trace runs out.
+fn is_live(run: &Option<Run>) -> bool {
+    run.as_ref().is_some_and(|run| !run.producer.is_finished())
+}
+
+/// Runs one polling loop at a time and fans its events out to any number of
+/// subscribers, such as the webview, the activity recorder or a trace replay.
+#[derive(Default)]
+pub struct StreamManager {
+    shared: Arc<Shared>,
+    run: tokio::sync::Mutex<Option<Run>>,
+}
+
+impl StreamManager {
+    /// Starts polling `source`. Fails if a stream is already running; stop it
+    /// first.
+    pub async fn start<S, I, C>(
+        &self,
+        source: S,
+        idle: IdleDetector<I>,
+        schedule: PollSchedule<C>,
+    ) -> Result<(), StreamError>
+    where
+        S: WindowSource,
+        I: IdleSource,
+        C: Clock,
+    {
+        let mut run = self.run.lock().await;
+        if is_live(&run) {
+            return Err(StreamError::AlreadyRunning);
+        }
+        // A stream that ran out on its own still has subscribers to wait for
+        if run.take().is_some() {
+            join(self.shared.close()).await;
+        }
+
+        let cancel_flag = Arc::new(tokio::sync::Mutex::new(false));
+        let shared = self.shared.clone();
+        let flag = cancel_flag.clone();
+        let producer = tokio::spawn(async move {
+            poll_windows(source, idle, flag, schedule, |event| shared.publish(event)).await;
+
+            // Replays end by themselves; their subscribers end with them
+            drop(shared.close());
+        });
+
+        *run = Some(Run {
+            cancel_flag,
+            producer,
+        });
+        Ok(())
+    }
+
+    /// Stops polling and waits for every subscriber to finish, so the last
+    /// segment is written by the time this returns.
+    pub async fn stop(&self) -> Result<(), StreamError> {
+        let run = self
+            .run
+            .lock()
+            .await
+            .take()
+            .ok_or(StreamError::NotRunning)?;
+        let was_running = !run.producer.is_finished();
+
+        *run.cancel_flag.lock().await = true;
+        if let Err(e) = run.producer.await {
+            tracing::error!(error = %e, "Stream task failed");
+        }
+        join(self.shared.close()).await;
+
+        if was_running {
+            Ok(())
+        } else {
+            Err(StreamError::NotRunning)
+        }
+    }
+
+    pub async fn is_running(&self) -> bool {
+        is_live(&*self.run.lock().await)
+    }
+
+    pub fn current_window(&self) -> Option<ActiveWindow> {
+        self.shared
+            .current_window
+            .lock()
+            .ok()
+            .and_then(|current| current.clone())
+    }
+
+    /// Feeds `subscriber` the running stream's events until it is cancelled
+    /// or the stream stops. It first sees the current window, if there is
+    /// one.
+    pub async fn subscribe(
+        &self,
+        mut subscriber: impl Subscriber,
+    ) -> Result<SubscriberHandle, StreamError> {
+        // Held so the stream can't stop between the check and the insert
+        let run = self.run.lock().await;
+        if !is_live(&run) {
+            return Err(StreamError::NotRunning);
+        }
+
+        let (sender, mut events) = unbounded_channel();
+        let mut subscribers = self
+            .shared
+            .subscribers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+
+        if let Some(window_info) = self.current_window() {
+            let _ = sender.send(StreamEvent::Window(window_info));
+        }
+
+        let id = subscribers.next_id;
+        subscribers.next_id += 1;
+        subscribers.senders.insert(id, sender);
+        subscribers.tasks.insert(
+            id,
+            tokio::spawn(async move {
+                let mut checkpoints =
+                    interval_at(Instant::now() + CHECKPOINT_INTERVAL, CHECKPOINT_INTERVAL);
+                checkpoints.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                loop {
+                    tokio::select! {
+                        event = events.recv() => match event {
+                            Some(event) => subscriber.event(&event),
+                            None => break,
+                        },
+                        _ = checkpoints.tick() => subscriber.checkpoint(),
+                    }
+                }
+                subscriber.finish();
+            }),
+        );
+
+        Ok(SubscriberHandle {
+            id,
+            shared: self.shared.clone(),
+        })
+    }
+}
+
+async fn join(tasks: Vec<JoinHandle<()>>) {
+    for task in tasks {
+        if let Err(e) = task.await {
+            tracing::error!(error = %e, "Stream subscriber failed");
+        }
+    }
+}
+
+/// One subscription to a `StreamManager`. Dropping it leaves the subscriber
+/// running until the stream stops.
+pub struct SubscriberHandle {
+    id: u64,
+    shared: Arc<Shared>,
+}
+
+impl SubscriberHandle {
+    /// Ends this subscription only, and waits for the subscriber to finish.
+    pub async fn cancel(self) {
+        let task = {
+            let mut subscribers = self
+                .shared
+                .subscribers
+                .lock()
+                .unwrap_or_else(|e| e.into_inner());
+            subscribers.senders.remove(&self.id);
+            subscribers.tasks.remove(&self.id)
+        };
+
+        if let Some(task) = task {
+            join(vec![task]).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::idle::NeverIdle;
+    use crate::window_info::scripted_source::ScriptedWindowSource;
+    use tokio::time::sleep;
+
+    fn window(title: &str) -> ActiveWindow {
+        ActiveWindow {
+            class: "Code".to_string(),
+            title: title.to_string(),
+            url: None,
+            raw_title: None,
+        }
+    }
+
+    fn source(titles: &[&str]) -> ScriptedWindowSource {
+        ScriptedWindowSource::new(
+            titles
+                .iter()
+                .enumerate()
+                .map(|(i, title)| (Duration::from_secs(i as u64 * 2), Some(window(title))))
+                .collect(),
+        )
+    }
+
+    async fn start(
+        manager: &StreamManager,
+        source: ScriptedWindowSource,
+    ) -> Result<(), StreamError> {
+        manager
+            .start(
+                source,
+                IdleDetector::new(NeverIdle, Duration::from_secs(60)),
+                PollSchedule::fixed(Duration::from_millis(300)),
+            )
+            .await
+    }
+
+    /// A subscriber that collects window titles, plus a marker on finish.
+    fn collector() -> (Arc<Mutex<Vec<String>>>, impl Subscriber) {
+        struct Collector(Arc<Mutex<Vec<String>>>);
+
+        impl Subscriber for Collector {
+            fn event(&mut self, event: &StreamEvent) {
+                if let StreamEvent::Window(window_info) = event {
+                    self.0.lock().unwrap().push(window_info.title.clone());
+                }
+            }
+
+            fn finish(&mut self) {
+                self.0.lock().unwrap().push("finished".to_string());
+            }
+        }
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        (seen.clone(), Collector(seen))
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn one_producer_feeds_every_subscriber() {
+        let manager = StreamManager::default();
+        start(&manager, source(&["a", "b", "c", "d"]))
+            .await
+            .unwrap();
+        sleep(Duration::from_millis(100)).await;
+
+        let (first, subscriber) = collector();
+        let first_handle = manager.subscribe(subscriber).await.unwrap();
+        let (second, subscriber) = collector();
+        manager.subscribe(subscriber).await.unwrap();
+
+        assert_eq!(
+            start(&manager, source(&["x"])).await,
+            Err(StreamError::AlreadyRunning)
+        );
+
+        sleep(Duration::from_secs(3)).await;
+        first_handle.cancel().await;
+        assert!(manager.is_running().await);
+
+        sleep(Duration::from_secs(2)).await;
+        manager.stop().await.unwrap();
+
+        assert_eq!(*first.lock().unwrap(), ["a", "b", "finished"]);
+        assert_eq!(*second.lock().unwrap(), ["a", "b", "c", "finished"]);
+        assert!(!manager.is_running().await);
+        assert_eq!(manager.current_window(), None);
+        assert_eq!(manager.stop().await, Err(StreamError::NotRunning));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn subscribers_finish_when_the_source_runs_out() {
+        let manager = StreamManager::default();
+        let (seen, subscriber) = collector();
+        assert!(matches!(
+            manager.subscribe(subscriber).await,
+            Err(StreamError::NotRunning)
+        ));
+
+        start(&manager, source(&["a", "b"])).await.unwrap();
+        let (seen_again, subscriber) = collector();
+        manager.subscribe(subscriber).await.unwrap();
+
+        sleep(Duration::from_secs(10)).await;
+        assert!(!manager.is_running().await);
+        assert_eq!(*seen_again.lock().unwrap(), ["a", "b", "finished"]);
+        assert!(seen.lock().unwrap().is_empty());
+
+        // A finished stream makes way for the next one
+        start(&manager, source(&["c"])).await.unwrap();
+        manager.stop().await.unwrap();
+    }
+}
@@ -0,0 +1,297 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, RwLock};
+
+use crate::model::{ActiveWindow, MacOSError, NormalizeError, StorageError};
+use crate::storage::Storage;
+use crate::window_info::window_source::WindowSource;
+
+/// Settings key the normalization config is saved under.
+pub const NORMALIZATION_SETTING: &str = "titles.normalization";
+
+pub type NormalizerState = Arc<RwLock<TitleNormalizer>>;
+
+/// Unread counters like `(3) Slack` or `Inbox (1,204) - Gmail`: at the start
+/// of the title, or right before a separator.
+const LEADING_COUNTER: &str = r"^[(\[]\d[\d,.]*\+?[)\]]\s+";
+const INNER_COUNTER: &str = r"\s[(\[]\d[\d,.]*\+?[)\]](\s+[-–—|·:]\s)";
+
+/// Unsaved-changes and unread markers: `● main.rs`, `• general`, `main.rs •`,
+/// `Notes — Edited`.
+const DIRTY_MARKER: &str = r"^[●•*]\s+|\s+[●•]$|\s+[-—]\s+Edited$";
+
+/// One step of the pipeline. Steps run in order on the output of the last.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum NormalizeStep {
+    StripCounters,
+    StripDirtyMarkers,
+    /// Drops the first of `suffixes` the title ends with.
+    StripSuffixes {
+        suffixes: Vec<String>,
+    },
+    /// Replaces every match of a regular expression; `$1` and friends refer
+    /// to its groups.
+    Replace {
+        pattern: String,
+        #[serde(default)]
+        replacement: String,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NormalizeConfig {
+    pub steps: Vec<NormalizeStep>,
+}
+
+impl Default for NormalizeConfig {
+    fn default() -> Self {
+        let suffixes = [
+            " - Google Chrome",
+            " - Brave",
+            " - Microsoft Edge",
+            " - Mozilla Firefox",
+            " — Mozilla Firefox",
+            " - Visual Studio Code",
+            " — Visual Studio Code",
+            " - Slack",
+        ];
+
+        NormalizeConfig {
+            steps: vec![
+                NormalizeStep::StripDirtyMarkers,
+                NormalizeStep::StripCounters,
+                NormalizeStep::StripSuffixes {
+                    suffixes: suffixes.iter().map(|suffix| suffix.to_string()).collect(),
+                },
+            ],
+        }
+    }
+}
+
+enum CompiledStep {
+    Regexes(Vec<(Regex, &'static str)>),
+    Suffixes(Vec<String>),
+    Replace(Regex, String),
+}
+
+/// Collapses the volatile parts of window titles, so a new unread count or
+/// unsaved edit doesn't start a new segment.
+pub struct TitleNormalizer {
+    config: NormalizeConfig,
+    steps: Vec<CompiledStep>,
+}
+
+impl TitleNormalizer {
+    pub fn compile(config: NormalizeConfig) -> Result<Self, NormalizeError> {
+        let builtin = |pattern: &str| Regex::new(pattern).expect("built-in pattern");
+
+        let steps = config
+            .steps
+            .iter()
+            .map(|step| {
+                Ok(match step {
+                    NormalizeStep::StripCounters => CompiledStep::Regexes(vec![
+                        (builtin(LEADING_COUNTER), ""),
+                        (builtin(INNER_COUNTER), "$1"),
+                    ]),
+                    NormalizeStep::StripDirtyMarkers => {
+                        CompiledStep::Regexes(vec![(builtin(DIRTY_MARKER), "")])
+                    }
+                    NormalizeStep::StripSuffixes { suffixes } => {
+                        CompiledStep::Suffixes(suffixes.clone())
+                    }
+                    NormalizeStep::Replace {
+                        pattern,
+                        replacement,
+                    } => CompiledStep::Replace(
+                        Regex::new(pattern).map_err(|source| NormalizeError::InvalidPattern {
+                            pattern: pattern.clone(),
+                            source,
+                        })?,
+                        replacement.clone(),
+                    ),
+                })
+            })
+            .collect::<Result<_, NormalizeError>>()?;
+
+        Ok(TitleNormalizer { config, steps })
+    }
+
+    /// The saved config, or the default one if none was saved.
+    pub fn load(storage: &Storage) -> Result<Self, StorageError> {
+        let config = storage.setting(NORMALIZATION_SETTING)?.unwrap_or_default();
+
+        TitleNormalizer::compile(config).map_err(|e| StorageError::InvalidSetting {
+            key: NORMALIZATION_SETTING,
+            message: e.to_string(),
+        })
+    }
+
+    pub fn config(&self) -> &NormalizeConfig {
+        &self.config
+    }
+
+    /// Runs the pipeline over `title` and trims the result. A title the
+    /// pipeline would empty is kept as is.
+    pub fn normalize(&self, title: &str) -> String {
+        let mut normalized = title.trim().to_string();
+
+        for step in &self.steps {
+            normalized = match step {
+                CompiledStep::Regexes(regexes) => {
+                    regexes
+                        .iter()
+                        .fold(normalized, |title, (regex, replacement)| {
+                            regex.replace_all(&title, *replacement).into_owned()
+                        })
+                }
+                CompiledStep::Suffixes(suffixes) => suffixes
+                    .iter()
+                    .find_map(|suffix| normalized.strip_suffix(suffix.as_str()))
+                    .map(str::to_string)
+                    .unwrap_or(normalized),
+                CompiledStep::Replace(regex, replacement) => regex
+                    .replace_all(&normalized, replacement.as_str())
+                    .into_owned(),
+            };
+            normalized = normalized.trim().to_string();
+        }
+
+        if normalized.is_empty() {
+            title.trim().to_string()
+        } else {
+            normalized
+        }
+    }
+
+    /// Normalizes the window's title, keeping the original in `raw_title`.
+    pub fn apply(&self, mut window: ActiveWindow) -> ActiveWindow {
+        if window != ActiveWindow::none() {
+            let normalized = self.normalize(&window.title);
+            window.raw_title = Some(std::mem::replace(&mut window.title, normalized));
+        }
+
+        window
+    }
+}
+
+impl Default for TitleNormalizer {
+    fn default() -> Self {
+        TitleNormalizer::compile(NormalizeConfig::default()).expect("default config compiles")
+    }
+}
+
+/// Wraps a `WindowSource` and normalizes the titles it reports, so change
+/// detection only sees titles that changed in a way that matters.
+pub struct NormalizingSource<S: WindowSource> {
+    inner: S,
+    normalizer: NormalizerState,
+}
+
+impl<S: WindowSource> NormalizingSource<S> {
+    pub fn new(inner: S, normalizer: NormalizerState) -> Self {
+        NormalizingSource { inner, normalizer }
+    }
+
+    fn normalize(&self, window: ActiveWindow) -> ActiveWindow {
+        match self.normalizer.read() {
+            Ok(normalizer) => normalizer.apply(window),
+            Err(_) => window,
+        }
+    }
+}
+
+impl<S: WindowSource> WindowSource for NormalizingSource<S> {
+    fn active_window(&mut self) -> Result<ActiveWindow, MacOSError> {
+        self.inner
+            .active_window()
+            .map(|window| self.normalize(window))
+    }
+
+    fn fallback_window(&mut self) -> Result<ActiveWindow, MacOSError> {
+        self.inner
+            .fallback_window()
+            .map(|window| self.normalize(window))
+    }
+
+    fn is_exhausted(&self) -> bool {
+        self.inner.is_exhausted()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_pipeline_collapses_noisy_titles() {
+        let normalizer = TitleNormalizer::default();
+        let fixtures = [
+            ("(3) Slack | general", "Slack | general"),
+            ("[12] Discord | #rust", "Discord | #rust"),
+            ("● main.rs — locus", "main.rs — locus"),
+            (
+                "Inbox (1,204) - charan@example.com - Gmail",
+                "Inbox - charan@example.com - Gmail",
+            ),
+            ("Pull requests - Google Chrome", "Pull requests"),
+            ("(2) YouTube - Mozilla Firefox", "YouTube"),
+            (
+                "general (Channel) - Acme - Slack",
+                "general (Channel) - Acme",
+            ),
+            ("Notes — Edited", "Notes"),
+            ("Budget (2024).xlsx", "Budget (2024).xlsx"),
+            ("  Finder  ", "Finder"),
+            ("●", "●"),
+        ];
+
+        for (raw, expected) in fixtures {
+            assert_eq!(normalizer.normalize(raw), expected, "{}", raw);
+        }
+    }
+
+    #[test]
+    fn steps_come_from_config_and_run_in_order() {
+        let config: NormalizeConfig = serde_json::from_str(
+            r#"{ "steps": [
+                { "kind": "replace", "pattern": "\\s*\\d{2}:\\d{2}:\\d{2}$" },
+                { "kind": "stripSuffixes", "suffixes": [" - Zoom"] }
+            ] }"#,
+        )
+        .unwrap();
+        let normalizer = TitleNormalizer::compile(config).unwrap();
+
+        assert_eq!(normalizer.normalize("Standup - Zoom 00:12:31"), "Standup");
+        // Counters are only stripped when configured
+        assert_eq!(normalizer.normalize("(3) Slack"), "(3) Slack");
+
+        assert!(matches!(
+            TitleNormalizer::compile(NormalizeConfig {
+                steps: vec![NormalizeStep::Replace {
+                    pattern: "(".to_string(),
+                    replacement: String::new(),
+                }],
+            }),
+            Err(NormalizeError::InvalidPattern { .. })
+        ));
+    }
+
+    #[test]
+    fn windows_keep_their_raw_title() {
+        let window = TitleNormalizer::default().apply(ActiveWindow {
+            class: "Slack".to_string(),
+            title: "(3) Slack | general".to_string(),
+            url: None,
+            raw_title: None,
+        });
+
+        assert_eq!(window.title, "Slack | general");
+        assert_eq!(window.raw_title.as_deref(), Some("(3) Slack | general"));
+        assert_eq!(
+            TitleNormalizer::default().apply(ActiveWindow::none()),
+            ActiveWindow::none()
+        );
+    }
+}
@@ -0,0 +1,214 @@
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+use crate::clock::{Clock, SystemClock};
+
+/// Settings key for the polling intervals.
+pub const POLLING_SETTING: &str = "polling";
+
+/// Shortest interval the schedule hands out, whatever the config says.
+const MIN_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Polling intervals, in milliseconds. Missing fields take their default.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PollingConfig {
+    /// Interval right after the window changed...
+    pub fast_ms: u64,
+    /// ...for this long.
+    pub fast_for_ms: u64,
+    pub normal_ms: u64,
+    /// Interval once the window has been stable for `slow_after_ms`.
+    pub slow_ms: u64,
+    pub slow_after_ms: u64,
+    /// Interval while the user is away or the screen is locked.
+    pub idle_ms: u64,
+}
+
+impl Default for PollingConfig {
+    fn default() -> Self {
+        PollingConfig {
+            fast_ms: 200,
+            fast_for_ms: 5_000,
+            normal_ms: 500,
+            slow_ms: 1_500,
+            slow_after_ms: 2 * 60_000,
+            idle_ms: 5_000,
+        }
+    }
+}
+
+impl PollingConfig {
+    /// The same interval whatever happens.
+    pub fn fixed(interval: Duration) -> Self {
+        let ms = interval.as_millis() as u64;
+
+        PollingConfig {
+            fast_ms: ms,
+            fast_for_ms: 0,
+            normal_ms: ms,
+            slow_ms: ms,
+            slow_after_ms: 0,
+            idle_ms: ms,
+        }
+    }
+}
+
+/// What the last poll saw.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PollOutcome {
+    Changed,
+    Unchanged,
+    Idle,
+    Locked,
+}
+
+/// Decides how long to sleep between polls: fast right after a change,
+/// slower the longer the window stays put, and rarely while nobody is
+/// looking.
+pub struct PollSchedule<C: Clock> {
+    config: PollingConfig,
+    clock: C,
+    last_change: Instant,
+}
+
+impl<C: Clock> PollSchedule<C> {
+    pub fn new(config: PollingConfig, clock: C) -> Self {
+        let last_change = clock.now();
+
+        PollSchedule {
+            config,
+            clock,
+            last_change,
+        }
+    }
+
+    /// The interval to wait after a poll with `outcome`.
+    pub fn next(&mut self, outcome: PollOutcome) -> Duration {
+        let now = self.clock.now();
+        let ms = match outcome {
+            PollOutcome::Idle | PollOutcome::Locked => self.config.idle_ms,
+            PollOutcome::Changed => {
+                self.last_change = now;
+                self.config.fast_ms
+            }
+            PollOutcome::Unchanged => {
+                let stable_for = now.saturating_duration_since(self.last_change);
+                if stable_for < Duration::from_millis(self.config.fast_for_ms) {
+                    self.config.fast_ms
+                } else if stable_for < Duration::from_millis(self.config.slow_after_ms) {
+                    self.config.normal_ms
+                } else {
+                    self.config.slow_ms
+                }
+            }
+        };
+
+        Duration::from_millis(ms).max(MIN_INTERVAL)
+    }
+}
+
+impl PollSchedule<SystemClock> {
+    /// Polls every `interval`, whatever happens.
+    pub fn fixed(interval: Duration) -> Self {
+        PollSchedule::new(PollingConfig::fixed(interval), SystemClock)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    /// Polls `outcomes` in order, sleeping on the clock for each interval,
+    /// and returns the intervals in milliseconds.
+    fn run(
+        schedule: &mut PollSchedule<ManualClock>,
+        clock: &ManualClock,
+        outcomes: &[PollOutcome],
+    ) -> Vec<u64> {
+        outcomes
+            .iter()
+            .map(|outcome| {
+                let interval = schedule.next(*outcome);
+                clock.advance(interval);
+                interval.as_millis() as u64
+            })
+            .collect()
+    }
+
+    fn config() -> PollingConfig {
+        PollingConfig {
+            fast_ms: 200,
+            fast_for_ms: 1_000,
+            normal_ms: 500,
+            slow_ms: 2_000,
+            slow_after_ms: 3_000,
+            idle_ms: 10_000,
+        }
+    }
+
+    #[test]
+    fn intervals_slow_down_as_the_window_stays_put() {
+        use PollOutcome::*;
+        let clock = ManualClock::new();
+        let mut schedule = PollSchedule::new(config(), clock.clone());
+
+        assert_eq!(
+            run(
+                &mut schedule,
+                &clock,
+                &[
+                    Changed, Unchanged, Unchanged, Unchanged, Unchanged, Unchanged, Unchanged,
+                    Unchanged, Unchanged, Unchanged
+                ]
+            ),
+            // Stable for 1s at the sixth poll, and for 3s at the tenth
+            vec![200, 200, 200, 200, 200, 500, 500, 500, 500, 2_000]
+        );
+
+        // A change speeds polling straight back up
+        assert_eq!(
+            run(&mut schedule, &clock, &[Unchanged, Changed, Unchanged]),
+            vec![2_000, 200, 200]
+        );
+    }
+
+    #[test]
+    fn idle_and_locked_screens_are_polled_rarely() {
+        use PollOutcome::*;
+        let clock = ManualClock::new();
+        let mut schedule = PollSchedule::new(config(), clock.clone());
+
+        assert_eq!(
+            run(
+                &mut schedule,
+                &clock,
+                &[Changed, Idle, Idle, Locked, Changed, Unchanged]
+            ),
+            vec![200, 10_000, 10_000, 10_000, 200, 200]
+        );
+    }
+
+    #[test]
+    fn config_fills_in_defaults_and_has_a_floor() {
+        let config: PollingConfig = serde_json::from_str(r#"{ "idleMs": 0 }"#).unwrap();
+        assert_eq!(config.fast_ms, PollingConfig::default().fast_ms);
+
+        let clock = ManualClock::new();
+        let mut schedule = PollSchedule::new(config, clock);
+        assert_eq!(schedule.next(PollOutcome::Locked), MIN_INTERVAL);
+
+        let mut fixed = PollSchedule::new(
+            PollingConfig::fixed(Duration::from_millis(300)),
+            ManualClock::new(),
+        );
+        assert!([
+            PollOutcome::Changed,
+            PollOutcome::Unchanged,
+            PollOutcome::Idle
+        ]
+        .iter()
+        .all(|outcome| fixed.next(*outcome) == Duration::from_millis(300)));
+    }
+}
@@ -1,20 +1,360 @@
-use tauri::State;
-use tokio::time::Duration;
+use serde_json::json;
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+use tauri::{Manager, State};
+use tokio::time::{sleep, Duration};
+use uuid::Uuid;
 
-use crate::model::StreamState;
-use crate::window_info::macos_window_info::stream_macos;
+use crate::activity::ActivityRecorder;
+use crate::clock::SystemClock;
+use crate::diagnostics::{self, Diagnostics, MacOSProbes};
+use crate::events::{self, AppEvent, RuleMatch};
+use crate::idle::{
+    macos_idle::MacOSIdleSource, IdleDetector, IdleSource, NeverIdle, DEFAULT_IDLE_THRESHOLD,
+    IDLE_THRESHOLD_SETTING,
+};
+use crate::model::{ActiveWindow, AppError, Context, StreamState};
+use crate::rpc::{client, server, RpcError};
+use crate::rules::RulesState;
+use crate::storage::Storage;
+use crate::stream::manager::{StreamManager, Subscriber, SubscriberHandle};
+use crate::stream::normalize::{NormalizerState, NormalizingSource};
+use crate::stream::schedule::{PollSchedule, PollingConfig, POLLING_SETTING};
+use crate::window_info::cached_probe::{CachedWindowSource, ProbeStats};
+use crate::window_info::daemon_source::DaemonWindowSource;
+use crate::window_info::macos_window_info::{MacOSProber, StreamEvent};
+use crate::window_info::trace::{self, RecordingSource};
+use crate::window_info::window_source::WindowSource;
 
-pub async fn stream_title<'r>(app: tauri::AppHandle, stream_state: State<'r, StreamState>) {
-    let cancel_flag = stream_state.cancel_flag.clone();
-    *cancel_flag.lock().await = false;
+/// Whether tracking has run the permission diagnostics since launch.
+static DIAGNOSED: AtomicBool = AtomicBool::new(false);
 
-    let sleep_duration = Duration::from_millis(300);
+/// Polling interval for streams that don't adapt: trace replays and the app
+/// mirroring a daemon.
+pub const FIXED_INTERVAL: Duration = Duration::from_millis(300);
 
-    // Start macOS window tracking
-    let _ = stream_macos(cancel_flag, app, sleep_duration).map_err(|e| eprintln!("{}", e));
+/// How many `FIXED_INTERVAL`s to wait for a daemon taking over to listen.
+const HANDOVER_ATTEMPTS: u32 = 20;
+
+/// Recorder and idle detector for tracking the live Mac into `storage`.
+pub fn live_recorder(
+    storage: &Arc<Storage>,
+    rules: &RulesState,
+    session_id: String,
+) -> Result<(ActivityRecorder, IdleDetector<MacOSIdleSource>), AppError> {
+    let threshold = storage
+        .setting::<u64>(IDLE_THRESHOLD_SETTING)
+        .context("Unable to read the idle threshold")?
+        .map_or(DEFAULT_IDLE_THRESHOLD, Duration::from_secs);
+
+    Ok((
+        ActivityRecorder::new(session_id, storage.clone(), rules.clone()),
+        IdleDetector::new(MacOSIdleSource, threshold),
+    ))
+}
+
+/// Polling schedule for the live Mac, from the saved intervals.
+pub fn live_schedule(storage: &Storage) -> Result<PollSchedule<SystemClock>, AppError> {
+    let config = storage
+        .setting::<PollingConfig>(POLLING_SETTING)
+        .context("Unable to read the polling intervals")?
+        .unwrap_or_default();
+
+    Ok(PollSchedule::new(config, SystemClock))
+}
+
+/// The live Mac's windows, probed through the per-app cache by `prober`.
+pub fn live_source(
+    prober: MacOSProber,
+    stats: Arc<ProbeStats>,
+) -> CachedWindowSource<MacOSProber, SystemClock> {
+    CachedWindowSource::new(prober, SystemClock, stats)
+}
+
+/// Starts streaming the live macOS window into storage under `session_id`, a
+/// fresh one if not given, optionally recording a trace to the file named
+/// `record_to` in the traces folder.
+/// Returns the session id.
+///
+/// Tracking that is already running is left alone and its session id
+/// returned, so every caller can ask for it without tripping over the others,
+/// unless another `session_id` is asked for: tracking then moves over to it.
+///
+/// When a headless daemon is tracking, the app only mirrors its current
+/// window to the webview; the daemon does the recording, under the session
+/// id it returns.
+pub async fn start_tracking(
+    app: &tauri::AppHandle,
+    session_id: Option<String>,
+    record_to: Option<String>,
+) -> Result<String, AppError> {
+    let stream_state = app.state::<StreamState>();
+    let stream = &stream_state.stream;
+    let mut tracking = stream_state.tracking.lock().await;
+
+    if let Some(running) = tracking.take() {
+        if stream.is_running().await {
+            if session_id.as_ref().is_none_or(|id| *id == running) {
+                *tracking = Some(running.clone());
+                return Ok(running);
+            }
+            stream.stop().await.context("Unable to stop the stream")?;
+        }
+    }
+
+    let session_id = match stream_state.daemon_socket() {
+        Some(socket) => mirror_daemon(app, &stream_state, socket, session_id).await?,
+        None => {
+            let session_id = session_id.unwrap_or_else(|| Uuid::new_v4().to_string());
+            start_live(app, &stream_state, session_id.clone(), record_to).await?;
+            session_id
+        }
+    };
+    *tracking = Some(session_id.clone());
+
+    Ok(session_id)
+}
+
+/// Has the daemon at `socket` track under `session_id`, or carry on with its
+/// own session if none is given, and mirrors its window to the webview.
+/// Returns the daemon's session id.
+async fn mirror_daemon(
+    app: &tauri::AppHandle,
+    stream_state: &StreamState,
+    socket: PathBuf,
+    session_id: Option<String>,
+) -> Result<String, AppError> {
+    let params = json!({ "sessionId": session_id });
+    let session_id = client::request_async(&socket, "start_stream", params)
+        .await
+        .map_err(AppError::from)
+        .and_then(|answer| answer.map_err(RpcError::into_app_error))
+        .and_then(|value| serde_json::from_value(value).map_err(AppError::Decode))
+        .context("Unable to start the daemon's tracking")?;
+
+    let stream = &stream_state.stream;
+    let idle = IdleDetector::new(NeverIdle, DEFAULT_IDLE_THRESHOLD);
+    let source = DaemonWindowSource::new(socket, FIXED_INTERVAL);
+    start(stream, source, idle, PollSchedule::fixed(FIXED_INTERVAL)).await?;
+    subscribe_webview(app, stream).await?;
+
+    Ok(session_id)
+}
+
+/// Gives the socket, and the live tracking, to a headless daemon that is
+/// starting. Returns the session the app was tracking, for the daemon to
+/// carry on with; the app mirrors the daemon under it once it listens.
+pub async fn hand_over(app: &tauri::AppHandle) -> Result<Option<String>, AppError> {
+    let stream_state = app.state::<StreamState>();
+    let mut tracking = stream_state.tracking.lock().await;
+
+    let running = stream_state.stream.is_running().await;
+    let session_id = tracking.take().filter(|_| running);
+    if session_id.is_some() {
+        stream_state
+            .stream
+            .stop()
+            .await
+            .context("Unable to stop the stream")?;
+    }
+
+    let server = stream_state
+        .server
+        .lock()
+        .ok()
+        .and_then(|mut server| server.take());
+    if let Some(server) = server {
+        server.abort();
+        // Waits for the listener to close, so the daemon can bind
+        let _ = server.await;
+    }
+
+    if let Some(session_id) = session_id.clone() {
+        let app = app.clone();
+        tokio::spawn(async move {
+            let socket = app.state::<StreamState>().socket_path.clone();
+            for _ in 0..HANDOVER_ATTEMPTS {
+                if server::is_listening(&socket) {
+                    break;
+                }
+                sleep(FIXED_INTERVAL).await;
+            }
+            if let Err(e) = start_tracking(&app, Some(session_id), None).await {
+                events::emit_error(&app, &e);
+            }
+        });
+    }
+
+    Ok(session_id)
+}
+
+async fn start_live(
+    app: &tauri::AppHandle,
+    stream_state: &StreamState,
+    session_id: String,
+    record_to: Option<String>,
+) -> Result<(), AppError> {
+    let stream = &stream_state.stream;
+    let storage = app.state::<Arc<Storage>>();
+    let (recorder, idle) = live_recorder(
+        storage.inner(),
+        app.state::<RulesState>().inner(),
+        session_id.clone(),
+    )?;
+    let schedule = live_schedule(&storage)?;
+    let normalizer = app.state::<NormalizerState>().inner().clone();
+    let probe = live_source(MacOSProber::default(), stream_state.probe_stats.clone());
+
+    match record_to {
+        Some(name) => {
+            let path = trace::trace_path(&stream_state.data_dir, &name)?;
+            // Traces keep the raw titles, so replays go through the current
+            // normalization
+            let source =
+                RecordingSource::create(probe, &path).context("Unable to create the trace file")?;
+            let source = NormalizingSource::new(source, normalizer);
+            start(stream, source, idle, schedule).await?;
+        }
+        None => {
+            let source = NormalizingSource::new(probe, normalizer);
+            start(stream, source, idle, schedule).await?;
+        }
+    }
+
+    subscribe(stream, recorder).await?;
+    subscribe_webview(app, stream).await?;
+
+    // Tell the webview up front if a permission is missing, once per launch;
+    // `permission_diagnostics` checks again on demand
+    if DIAGNOSED.swap(true, Ordering::Relaxed) {
+        return Ok(());
+    }
+    let app = app.clone();
+    tokio::spawn(async move {
+        match run_diagnostics().await {
+            Ok(diagnostics) => diagnostics::emit_status(&app, &diagnostics),
+            Err(e) => events::emit_error(&app, &e),
+        }
+    });
+
+    Ok(())
+}
+
+/// Diagnoses the live Mac off the async workers; the probes block on
+/// `osascript`.
+pub async fn run_diagnostics() -> Result<Diagnostics, AppError> {
+    tokio::task::spawn_blocking(|| diagnostics::diagnose(&mut MacOSProbes))
+        .await
+        .map_err(std::io::Error::from)
+        .context("Unable to run diagnostics")
+}
+
+/// Starts `stream` polling `source`, with the error the commands report.
+pub async fn start<S: WindowSource, I: IdleSource>(
+    stream: &StreamManager,
+    source: S,
+    idle: IdleDetector<I>,
+    schedule: PollSchedule<SystemClock>,
+) -> Result<(), AppError> {
+    stream
+        .start(source, idle, schedule)
+        .await
+        .context("Unable to start the stream")
+}
+
+pub async fn subscribe(
+    stream: &StreamManager,
+    subscriber: impl Subscriber,
+) -> Result<SubscriberHandle, AppError> {
+    stream
+        .subscribe(subscriber)
+        .await
+        .context("Unable to subscribe to the stream")
+}
+
+/// Forwards the stream's events to the webview, and tells it which user rule
+/// each new window matches.
+pub async fn subscribe_webview(
+    app: &tauri::AppHandle,
+    stream: &StreamManager,
+) -> Result<(), AppError> {
+    let events_app = app.clone();
+    subscribe(stream, move |event: &StreamEvent| {
+        events::emit(&events_app, AppEvent::from(event))
+    })
+    .await?;
+
+    let rules_app = app.clone();
+    subscribe(stream, move |event: &StreamEvent| {
+        if let StreamEvent::Window(window_info) = event {
+            if let Some(matched) = rule_match(&rules_app, window_info) {
+                events::emit(&rules_app, AppEvent::RuleMatched(matched));
+            }
+        }
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// The user rule `window` matches, if any.
+fn rule_match(app: &tauri::AppHandle, window: &ActiveWindow) -> Option<RuleMatch> {
+    let classification = app
+        .state::<RulesState>()
+        .read()
+        .ok()?
+        .classify_window(window);
+
+    Some(RuleMatch {
+        class: window.class.clone(),
+        title: window.title.clone(),
+        rule: classification.rule?,
+        category: classification.category,
+        productivity: classification.productivity,
+    })
+}
+
+/// Stops tracking if it is under `session_id`, as when that session's timer
+/// pauses or ends. An attached daemon carries on under a fresh session of its
+/// own, so what follows isn't counted towards the timer's.
+pub async fn stop_tracking(app: &tauri::AppHandle, session_id: &str) -> Result<(), AppError> {
+    let stream_state = app.state::<StreamState>();
+    let mut tracking = stream_state.tracking.lock().await;
+    if tracking.as_deref() != Some(session_id) {
+        return Ok(());
+    }
+    *tracking = None;
+
+    if let Some(socket) = stream_state.daemon_socket() {
+        let params = json!({ "sessionId": Uuid::new_v4().to_string() });
+        client::request_async(&socket, "start_stream", params)
+            .await
+            .map_err(AppError::from)
+            .and_then(|answer| answer.map_err(RpcError::into_app_error))
+            .context("Unable to end the daemon's session")?;
+    }
+
+    if stream_state.stream.is_running().await {
+        stream_state
+            .stream
+            .stop()
+            .await
+            .context("Unable to stop the stream")?;
+    }
+    Ok(())
 }
 
-pub async fn stop_stream(stream_state: State<'_, StreamState>) {
-    let mut cancel_flag = stream_state.cancel_flag.lock().await;
-    *cancel_flag = true;
+pub async fn stop_stream(stream_state: State<'_, StreamState>) -> Result<(), AppError> {
+    let mut tracking = stream_state.tracking.lock().await;
+    *tracking = None;
+    stream_state
+        .stream
+        .stop()
+        .await
+        .context("Unable to stop the stream")
 }
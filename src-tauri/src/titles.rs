@@ -0,0 +1,126 @@
+pub mod chat;
+pub mod editors;
+pub mod notes;
+pub mod terminals;
+pub mod xcode;
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// What a window title says beyond the app: the project it belongs to, the
+/// file or document open in it, and so on. Fields a parser can't tell are
+/// `None`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TitleFields {
+    pub project: Option<String>,
+    pub file: Option<String>,
+    pub document: Option<String>,
+    pub url: Option<String>,
+    pub channel: Option<String>,
+}
+
+impl TitleFields {
+    /// Fills the fields this one is missing from `other`.
+    pub fn or(self, other: TitleFields) -> TitleFields {
+        TitleFields {
+            project: self.project.or(other.project),
+            file: self.file.or(other.file),
+            document: self.document.or(other.document),
+            url: self.url.or(other.url),
+            channel: self.channel.or(other.channel),
+        }
+    }
+}
+
+pub type TitleParser = fn(&str) -> TitleFields;
+
+/// Title parsers keyed by app class. Classes without a parser get empty
+/// fields.
+pub struct TitleParsers {
+    parsers: HashMap<String, TitleParser>,
+}
+
+impl TitleParsers {
+    pub fn empty() -> Self {
+        TitleParsers {
+            parsers: HashMap::new(),
+        }
+    }
+
+    /// Parses titles of windows whose class is one of `classes` with `parser`,
+    /// replacing any parser registered before.
+    pub fn register(&mut self, classes: &[&str], parser: TitleParser) {
+        for class in classes {
+            self.parsers.insert(class.to_string(), parser);
+        }
+    }
+
+    pub fn parse(&self, class: &str, title: &str) -> TitleFields {
+        self.parsers
+            .get(class)
+            .map(|parser| parser(title))
+            .unwrap_or_default()
+    }
+}
+
+impl Default for TitleParsers {
+    /// The built-in parsers, under the class names both the native probe and
+    /// the AppleScript fallbacks report.
+    fn default() -> Self {
+        let mut parsers = TitleParsers::empty();
+        parsers.register(editors::CLASSES, editors::parse);
+        parsers.register(terminals::CLASSES, terminals::parse);
+        parsers.register(xcode::CLASSES, xcode::parse);
+        parsers.register(notes::CLASSES, notes::parse);
+        parsers.register(chat::CLASSES, chat::parse);
+        parsers
+    }
+}
+
+/// Splits a title on the em dash macOS apps put between its parts.
+fn em_dash_parts(title: &str) -> Vec<&str> {
+    title
+        .split(" — ")
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .collect()
+}
+
+fn owned(value: &str) -> Option<String> {
+    Some(value.to_string()).filter(|value| !value.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parsers_are_looked_up_by_class() {
+        let parsers = TitleParsers::default();
+
+        assert_eq!(
+            parsers.parse("Code", "main.rs — locus").file.as_deref(),
+            Some("main.rs")
+        );
+        assert_eq!(
+            parsers.parse("VS Code", "main.rs — locus").file.as_deref(),
+            Some("main.rs")
+        );
+        assert_eq!(parsers.parse("Finder", "Downloads"), TitleFields::default());
+    }
+
+    #[test]
+    fn registering_replaces_a_parser() {
+        let mut parsers = TitleParsers::default();
+        parsers.register(&["Finder"], |title| TitleFields {
+            document: owned(title),
+            ..Default::default()
+        });
+
+        assert_eq!(
+            parsers.parse("Finder", "Downloads").document.as_deref(),
+            Some("Downloads")
+        );
+    }
+}
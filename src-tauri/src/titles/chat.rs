@@ -0,0 +1,54 @@
+use super::{owned, TitleFields};
+
+pub const CLASSES: &[&str] = &["Slack"];
+
+/// Slack: `general (Channel) - Acme - Slack`, or `(DM)` for direct messages,
+/// with an `!` or `*` in front when there is unread activity. The workspace is
+/// the project.
+pub fn parse(title: &str) -> TitleFields {
+    let title = title.trim_start_matches(['!', '*']).trim();
+    let title = title.strip_suffix(" - Slack").unwrap_or(title);
+
+    let Some((conversation, workspace)) = title.rsplit_once(" - ") else {
+        return TitleFields::default();
+    };
+    let channel = [" (Channel)", " (DM)", " (Private)"]
+        .iter()
+        .find_map(|kind| conversation.strip_suffix(kind))
+        .unwrap_or(conversation);
+
+    TitleFields {
+        project: owned(workspace.trim()),
+        channel: owned(channel.trim()),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slack_titles_give_the_channel_and_workspace() {
+        let fixtures = [
+            (
+                "general (Channel) - Acme - Slack",
+                Some("Acme"),
+                Some("general"),
+            ),
+            (
+                "! eng-alerts (Channel) - Acme - Slack",
+                Some("Acme"),
+                Some("eng-alerts"),
+            ),
+            ("Priya (DM) - Acme - Slack", Some("Acme"), Some("Priya")),
+            ("Slack", None, None),
+        ];
+
+        for (title, workspace, channel) in fixtures {
+            let fields = parse(title);
+            assert_eq!(fields.project.as_deref(), workspace, "{}", title);
+            assert_eq!(fields.channel.as_deref(), channel, "{}", title);
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use super::{em_dash_parts, owned, TitleFields};
+
+pub const CLASSES: &[&str] = &[
+    "Code",
+    "VS Code",
+    "Visual Studio Code",
+    "Code - Insiders",
+    "Cursor",
+    "Zed",
+];
+
+const APP_NAMES: &[&str] = &[
+    "Visual Studio Code",
+    "Visual Studio Code - Insiders",
+    "Cursor",
+    "Zed",
+];
+
+/// VS Code and its forks, and Zed: `file — workspace`, with a `●` in front
+/// while the file has unsaved changes and the app name at the end on some
+/// setups. A title with only a workspace has no file.
+pub fn parse(title: &str) -> TitleFields {
+    let title = title.trim_start_matches('●').trim();
+    let mut parts = em_dash_parts(title);
+    if parts.len() > 1 && APP_NAMES.contains(parts.last().unwrap_or(&"")) {
+        parts.pop();
+    }
+
+    let (file, workspace) = match parts.as_slice() {
+        [] => return TitleFields::default(),
+        [workspace] => (None, *workspace),
+        [file, .., workspace] => (owned(file), *workspace),
+    };
+
+    TitleFields {
+        project: owned(strip_remote(workspace)),
+        file,
+        ..Default::default()
+    }
+}
+
+/// Drops the `[SSH: host]` or `[Dev Container]` tag from remote workspaces.
+fn strip_remote(workspace: &str) -> &str {
+    match workspace.rfind(" [") {
+        Some(at) if workspace.ends_with(']') => &workspace[..at],
+        _ => workspace,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(project: Option<&str>, file: Option<&str>) -> TitleFields {
+        TitleFields {
+            project: project.map(str::to_string),
+            file: file.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn editor_titles_split_into_file_and_workspace() {
+        let fixtures = [
+            (
+                "main.rs — locus-macos",
+                fields(Some("locus-macos"), Some("main.rs")),
+            ),
+            (
+                "● storage.rs — locus-macos",
+                fields(Some("locus-macos"), Some("storage.rs")),
+            ),
+            (
+                "lib.rs — locus-macos — Visual Studio Code",
+                fields(Some("locus-macos"), Some("lib.rs")),
+            ),
+            (
+                "App.tsx — web [SSH: devbox]",
+                fields(Some("web"), Some("App.tsx")),
+            ),
+            ("locus-macos", fields(Some("locus-macos"), None)),
+            (
+                "README.md — notes — Zed",
+                fields(Some("notes"), Some("README.md")),
+            ),
+            ("", TitleFields::default()),
+        ];
+
+        for (title, expected) in fixtures {
+            assert_eq!(parse(title), expected, "{}", title);
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use super::{owned, TitleFields};
+
+pub const CLASSES: &[&str] = &["Obsidian"];
+
+/// Obsidian: `Note - Vault - Obsidian v1.6.7`, or `Vault - Obsidian v1.6.7`
+/// with no note open. The vault is the project and the note the document.
+pub fn parse(title: &str) -> TitleFields {
+    let title = match title.rfind(" - Obsidian") {
+        Some(at) => &title[..at],
+        None => title.strip_suffix("Obsidian").unwrap_or(title),
+    };
+
+    let (note, vault) = match title.rsplit_once(" - ") {
+        Some((note, vault)) => (owned(note.trim()), vault),
+        None => (None, title),
+    };
+
+    TitleFields {
+        project: owned(vault.trim()),
+        document: note,
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn obsidian_titles_give_the_note_and_vault() {
+        let fixtures = [
+            (
+                "Weekly review - Journal - Obsidian v1.6.7",
+                Some("Journal"),
+                Some("Weekly review"),
+            ),
+            (
+                "2024-10-05 - Standup - Work - Obsidian v1.4.16",
+                Some("Work"),
+                Some("2024-10-05 - Standup"),
+            ),
+            ("Journal - Obsidian v1.6.7", Some("Journal"), None),
+            ("Obsidian", None, None),
+        ];
+
+        for (title, vault, note) in fixtures {
+            let fields = parse(title);
+            assert_eq!(fields.project.as_deref(), vault, "{}", title);
+            assert_eq!(fields.document.as_deref(), note, "{}", title);
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use super::{em_dash_parts, owned, TitleFields};
+
+pub const CLASSES: &[&str] = &[
+    "Terminal",
+    "iTerm2",
+    "Ghostty",
+    "WezTerm",
+    "Alacritty",
+    "kitty",
+    "Warp",
+];
+
+const SHELLS: &[&str] = &["zsh", "bash", "fish", "sh", "login"];
+
+/// Terminals show the working directory, the running command, or both.
+/// Terminal.app writes `directory — command — 80×24`; shell integrations in
+/// the others set the title to the path or the command alone.
+///
+/// The directory's name is the project; a command other than the shell
+/// itself is the document.
+pub fn parse(title: &str) -> TitleFields {
+    let mut parts = em_dash_parts(title);
+    if parts.last().is_some_and(|part| is_size(part)) {
+        parts.pop();
+    }
+
+    let (cwd, command): (Option<&str>, Option<&str>) = match parts.as_slice() {
+        [] => return TitleFields::default(),
+        [only] => {
+            let only = strip_user_host(only);
+            if is_path(only) {
+                (Some(only), None)
+            } else {
+                (None, Some(only))
+            }
+        }
+        [cwd, command, ..] => (Some(strip_user_host(cwd)), Some(command)),
+    };
+
+    TitleFields {
+        project: cwd.and_then(directory_name).and_then(owned),
+        document: command.filter(|command| !is_shell(command)).and_then(owned),
+        ..Default::default()
+    }
+}
+
+/// `80×24`
+fn is_size(part: &str) -> bool {
+    part.split_once('×').is_some_and(|(columns, rows)| {
+        [columns, rows]
+            .iter()
+            .all(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+    })
+}
+
+/// Drops the `user@host:` prompt prefix some shells put before the path.
+fn strip_user_host(part: &str) -> &str {
+    match part.split_once(": ") {
+        Some((prompt, rest)) if prompt.contains('@') && !prompt.contains(' ') => rest,
+        _ => part,
+    }
+}
+
+fn is_path(part: &str) -> bool {
+    part.starts_with('/') || part.starts_with('~')
+}
+
+fn is_shell(command: &str) -> bool {
+    SHELLS.contains(&command.trim_start_matches('-'))
+}
+
+/// The last component of a path, or the name itself. The home directory and
+/// the root aren't projects.
+fn directory_name(cwd: &str) -> Option<&str> {
+    let name = cwd.trim_end_matches('/').rsplit('/').next()?;
+    Some(name).filter(|name| !name.is_empty() && *name != "~")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(project: Option<&str>, document: Option<&str>) -> TitleFields {
+        TitleFields {
+            project: project.map(str::to_string),
+            document: document.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn terminal_titles_give_the_directory_and_command() {
+        let fixtures = [
+            // Terminal.app
+            ("locus — -zsh — 120×40", fields(Some("locus"), None)),
+            (
+                "locus — cargo test — 120×40",
+                fields(Some("locus"), Some("cargo test")),
+            ),
+            (
+                "charan@mbp: ~/code/locus — vim main.rs — 80×24",
+                fields(Some("locus"), Some("vim main.rs")),
+            ),
+            // Shell integrations
+            ("~/code/locus-macos", fields(Some("locus-macos"), None)),
+            ("charan@mbp: /usr/local/bin/", fields(Some("bin"), None)),
+            (
+                "cargo clippy --workspace",
+                fields(None, Some("cargo clippy --workspace")),
+            ),
+            ("~", fields(None, None)),
+            ("-zsh", fields(None, None)),
+            ("", fields(None, None)),
+        ];
+
+        for (title, expected) in fixtures {
+            assert_eq!(parse(title), expected, "{}", title);
+        }
+    }
+}
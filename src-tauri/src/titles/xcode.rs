@@ -0,0 +1,55 @@
+use super::{em_dash_parts, owned, TitleFields};
+
+pub const CLASSES: &[&str] = &["Xcode"];
+
+/// Xcode: `Project — File.swift`, or just the project, with an `Edited` part
+/// while there are unsaved changes.
+pub fn parse(title: &str) -> TitleFields {
+    let parts: Vec<_> = em_dash_parts(title)
+        .into_iter()
+        .filter(|part| *part != "Edited")
+        .collect();
+
+    let Some(project) = parts.first() else {
+        return TitleFields::default();
+    };
+    let project = project
+        .trim_end_matches(".xcodeproj")
+        .trim_end_matches(".xcworkspace");
+
+    TitleFields {
+        project: owned(project),
+        file: parts.get(1).and_then(|file| owned(file)),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn xcode_titles_name_the_project_and_file() {
+        let fixtures = [
+            (
+                "Locus — ContentView.swift",
+                Some("Locus"),
+                Some("ContentView.swift"),
+            ),
+            (
+                "Locus — AppDelegate.swift — Edited",
+                Some("Locus"),
+                Some("AppDelegate.swift"),
+            ),
+            ("Locus.xcodeproj", Some("Locus"), None),
+            ("Locus.xcworkspace — Edited", Some("Locus"), None),
+            ("", None, None),
+        ];
+
+        for (title, project, file) in fixtures {
+            let fields = parse(title);
+            assert_eq!(fields.project.as_deref(), project, "{}", title);
+            assert_eq!(fields.file.as_deref(), file, "{}", title);
+        }
+    }
+}
@@ -1,3 +1,9 @@
 // macOS-only window information module
+pub mod cached_probe;
+pub mod daemon_source;
 pub mod macos_window_info;
+#[cfg(target_os = "macos")]
 pub mod native_macos;
+pub mod scripted_source;
+pub mod trace;
+pub mod window_source;
@@ -0,0 +1,479 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::clock::Clock;
+use crate::model::{ActiveWindow, MacOSError};
+use crate::window_info::macos_window_info::osascript_spawns;
+use crate::window_info::window_source::WindowSource;
+
+/// How long a window title is trusted while the same app stays in front.
+pub const TITLE_REFRESH: Duration = Duration::from_secs(3);
+
+/// First and longest wait before asking an app that failed to answer again.
+pub const BACKOFF_BASE: Duration = Duration::from_secs(10);
+pub const BACKOFF_MAX: Duration = Duration::from_secs(5 * 60);
+
+/// Apps that fail this many probes without ever answering are taken as
+/// unsupported, and only asked again every `UNSUPPORTED_RETRY`, in case
+/// they were relaunched or granted permission since.
+pub const UNSUPPORTED_AFTER: u32 = 3;
+pub const UNSUPPORTED_RETRY: Duration = Duration::from_secs(30 * 60);
+
+/// The two halves of a window probe: which app is in front, which is cheap,
+/// and what its window shows, which usually means spawning `osascript`.
+pub trait WindowProber: Send + 'static {
+    /// Name of the frontmost app. Asked on every tick.
+    fn frontmost_app(&mut self) -> Result<String, MacOSError>;
+
+    /// The frontmost window of `app`, title and all.
+    fn window(&mut self, app: &str) -> Result<ActiveWindow, MacOSError>;
+}
+
+/// What has been learned about probing one app.
+#[derive(Debug, Default)]
+struct AppCapability {
+    answered: bool,
+    failures: u32,
+    retry_at: Option<Instant>,
+}
+
+impl AppCapability {
+    fn unsupported(&self) -> bool {
+        !self.answered && self.failures >= UNSUPPORTED_AFTER
+    }
+
+    fn can_probe(&self, now: Instant) -> bool {
+        self.retry_at.is_none_or(|retry_at| now >= retry_at)
+    }
+
+    fn succeeded(&mut self) {
+        self.answered = true;
+        self.failures = 0;
+        self.retry_at = None;
+    }
+
+    fn failed(&mut self, now: Instant) {
+        self.failures += 1;
+        let backoff = if self.unsupported() {
+            UNSUPPORTED_RETRY
+        } else {
+            BACKOFF_BASE
+                .saturating_mul(1 << (self.failures - 1).min(16))
+                .min(BACKOFF_MAX)
+        };
+        self.retry_at = Some(now + backoff);
+    }
+}
+
+struct Current {
+    app: String,
+    window: ActiveWindow,
+    probed_at: Instant,
+}
+
+/// A `WindowSource` that asks for the frontmost app on every tick but only
+/// probes window titles when that app changes or its title is
+/// `TITLE_REFRESH` old. Apps that don't answer are backed off, and after
+/// `UNSUPPORTED_AFTER` failures only retried every `UNSUPPORTED_RETRY`; their
+/// windows are reported by app name alone.
+pub struct CachedWindowSource<P: WindowProber, C: Clock> {
+    prober: P,
+    clock: C,
+    stats: Arc<ProbeStats>,
+    capabilities: HashMap<String, AppCapability>,
+    current: Option<Current>,
+    started: bool,
+}
+
+impl<P: WindowProber, C: Clock> CachedWindowSource<P, C> {
+    /// Starts a fresh cache. `stats` are reset to count from the first poll,
+    /// so a source that never runs leaves the running counters alone.
+    pub fn new(prober: P, clock: C, stats: Arc<ProbeStats>) -> Self {
+        CachedWindowSource {
+            prober,
+            clock,
+            stats,
+            capabilities: HashMap::new(),
+            current: None,
+            started: false,
+        }
+    }
+
+    fn probe(&mut self, app: String, now: Instant) -> ActiveWindow {
+        let capability = self.capabilities.entry(app.clone()).or_default();
+
+        let window = if capability.can_probe(now) {
+            let _span = tracing::debug_span!("probe", app = %app).entered();
+            let started = self.clock.now();
+            let result = self.prober.window(&app);
+            let latency = self.clock.now().saturating_duration_since(started);
+            let latency_ms = latency.as_millis() as u64;
+
+            match result {
+                Ok(window) => {
+                    tracing::debug!(latency_ms, "Probe answered");
+                    if capability.unsupported() {
+                        tracing::info!("Answering again");
+                        self.stats.mark_supported(&app);
+                    }
+                    capability.succeeded();
+                    self.stats.record_probe(latency, true);
+                    window
+                }
+                Err(e) => {
+                    tracing::debug!(latency_ms, error = %e, "Probe failed");
+                    let was_unsupported = capability.unsupported();
+                    capability.failed(now);
+                    self.stats.record_probe(latency, false);
+                    if capability.unsupported() && !was_unsupported {
+                        tracing::info!("Backing off probing its titles");
+                        self.stats.mark_unsupported(&app);
+                    }
+                    self.last_window_of(&app)
+                }
+            }
+        } else {
+            self.stats.record_cache_hit();
+            self.last_window_of(&app)
+        };
+
+        self.current = Some(Current {
+            app,
+            window: window.clone(),
+            probed_at: now,
+        });
+        window
+    }
+
+    /// The last title seen for `app` if it is still in front, or its name.
+    fn last_window_of(&self, app: &str) -> ActiveWindow {
+        match &self.current {
+            Some(current) if current.app == app => current.window.clone(),
+            _ => ActiveWindow {
+                class: app.to_string(),
+                title: app.to_string(),
+                url: None,
+                raw_title: None,
+            },
+        }
+    }
+}
+
+impl<P: WindowProber, C: Clock> WindowSource for CachedWindowSource<P, C> {
+    fn active_window(&mut self) -> Result<ActiveWindow, MacOSError> {
+        let now = self.clock.now();
+        if !self.started {
+            self.started = true;
+            self.stats.reset(now);
+        }
+        self.stats.record_tick();
+        let app = self.prober.frontmost_app()?;
+
+        if let Some(current) = &self.current {
+            if current.app == app
+                && now.saturating_duration_since(current.probed_at) < TITLE_REFRESH
+            {
+                self.stats.record_cache_hit();
+                return Ok(current.window.clone());
+            }
+        }
+
+        Ok(self.probe(app, now))
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+struct Totals {
+    since: Option<Instant>,
+    ticks: u64,
+    title_probes: u64,
+    failures: u64,
+    cache_hits: u64,
+    latency: Duration,
+    unsupported_apps: Vec<String>,
+}
+
+/// Running totals of the probe's work, shared with whoever wants to look.
+#[derive(Debug, Default)]
+pub struct ProbeStats(Mutex<Totals>);
+
+/// A snapshot of `ProbeStats`, as the `probe_counters` command returns it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ProbeCounters {
+    pub ticks: u64,
+    pub title_probes: u64,
+    pub failures: u64,
+    pub cache_hits: u64,
+    /// Title probes per second since tracking started.
+    pub probes_per_second: f64,
+    /// `osascript` processes spawned since the app started.
+    pub spawn_count: u64,
+    pub average_latency_ms: f64,
+    pub unsupported_apps: Vec<String>,
+}
+
+impl ProbeStats {
+    fn update(&self, update: impl FnOnce(&mut Totals)) {
+        if let Ok(mut totals) = self.0.lock() {
+            update(&mut totals);
+        }
+    }
+
+    fn reset(&self, now: Instant) {
+        self.update(|totals| {
+            *totals = Totals {
+                since: Some(now),
+                ..Default::default()
+            }
+        });
+    }
+
+    fn record_tick(&self) {
+        self.update(|totals| totals.ticks += 1);
+    }
+
+    fn record_cache_hit(&self) {
+        self.update(|totals| totals.cache_hits += 1);
+    }
+
+    fn record_probe(&self, latency: Duration, answered: bool) {
+        self.update(|totals| {
+            totals.title_probes += 1;
+            totals.latency += latency;
+            if !answered {
+                totals.failures += 1;
+            }
+        });
+    }
+
+    fn mark_unsupported(&self, app: &str) {
+        self.update(|totals| totals.unsupported_apps.push(app.to_string()));
+    }
+
+    fn mark_supported(&self, app: &str) {
+        self.update(|totals| totals.unsupported_apps.retain(|name| name != app));
+    }
+
+    pub fn counters(&self, now: Instant) -> ProbeCounters {
+        let totals = self
+            .0
+            .lock()
+            .map(|totals| totals.clone())
+            .unwrap_or_default();
+        let elapsed = totals
+            .since
+            .map_or(Duration::ZERO, |since| now.saturating_duration_since(since));
+
+        ProbeCounters {
+            ticks: totals.ticks,
+            title_probes: totals.title_probes,
+            failures: totals.failures,
+            cache_hits: totals.cache_hits,
+            probes_per_second: if elapsed.is_zero() {
+                0.0
+            } else {
+                totals.title_probes as f64 / elapsed.as_secs_f64()
+            },
+            spawn_count: osascript_spawns(),
+            average_latency_ms: if totals.title_probes == 0 {
+                0.0
+            } else {
+                totals.latency.as_secs_f64() * 1000.0 / totals.title_probes as f64
+            },
+            unsupported_apps: totals.unsupported_apps,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    /// Serves a fixed frontmost app and counts title probes. Apps named in
+    /// `silent` never answer.
+    struct FakeProber {
+        app: Arc<Mutex<String>>,
+        silent: Arc<Mutex<Vec<&'static str>>>,
+        probes: Arc<Mutex<Vec<String>>>,
+        clock: ManualClock,
+    }
+
+    impl WindowProber for FakeProber {
+        fn frontmost_app(&mut self) -> Result<String, MacOSError> {
+            Ok(self.app.lock().unwrap().clone())
+        }
+
+        fn window(&mut self, app: &str) -> Result<ActiveWindow, MacOSError> {
+            self.probes.lock().unwrap().push(app.to_string());
+            self.clock.advance(Duration::from_millis(40));
+
+            if self.silent.lock().unwrap().contains(&app) {
+                return Err(MacOSError::NoActiveWindow);
+            }
+            Ok(ActiveWindow {
+                class: app.to_string(),
+                title: format!("{} window", app),
+                url: None,
+                raw_title: None,
+            })
+        }
+    }
+
+    struct Harness {
+        source: CachedWindowSource<FakeProber, ManualClock>,
+        app: Arc<Mutex<String>>,
+        silent: Arc<Mutex<Vec<&'static str>>>,
+        probes: Arc<Mutex<Vec<String>>>,
+        clock: ManualClock,
+        stats: Arc<ProbeStats>,
+    }
+
+    fn harness(silent: Vec<&'static str>) -> Harness {
+        let clock = ManualClock::new();
+        let app = Arc::new(Mutex::new("Code".to_string()));
+        let probes = Arc::new(Mutex::new(Vec::new()));
+        let silent = Arc::new(Mutex::new(silent));
+        let stats = Arc::new(ProbeStats::default());
+        let prober = FakeProber {
+            app: app.clone(),
+            silent: silent.clone(),
+            probes: probes.clone(),
+            clock: clock.clone(),
+        };
+
+        Harness {
+            source: CachedWindowSource::new(prober, clock.clone(), stats.clone()),
+            app,
+            silent,
+            probes,
+            clock,
+            stats,
+        }
+    }
+
+    impl Harness {
+        fn tick(&mut self) -> String {
+            let window = self.source.active_window().unwrap();
+            self.clock.advance(Duration::from_millis(300));
+            window.title
+        }
+
+        fn switch_to(&self, app: &str) {
+            *self.app.lock().unwrap() = app.to_string();
+        }
+
+        fn probe_count(&self) -> usize {
+            self.probes.lock().unwrap().len()
+        }
+    }
+
+    #[test]
+    fn titles_are_probed_on_app_changes_and_refreshes_only() {
+        let mut h = harness(vec![]);
+
+        assert_eq!(h.tick(), "Code window");
+        for _ in 0..5 {
+            assert_eq!(h.tick(), "Code window");
+        }
+        assert_eq!(h.probe_count(), 1);
+
+        h.switch_to("Slack");
+        assert_eq!(h.tick(), "Slack window");
+        assert_eq!(h.probe_count(), 2);
+
+        // Still Slack after the refresh interval: one more probe
+        h.clock.advance(TITLE_REFRESH);
+        h.tick();
+        assert_eq!(h.probe_count(), 3);
+    }
+
+    #[test]
+    fn apps_that_never_answer_are_backed_off_then_rarely_retried() {
+        let mut h = harness(vec!["Legacy"]);
+        h.switch_to("Legacy");
+
+        assert_eq!(h.tick(), "Legacy");
+        assert_eq!(h.probe_count(), 1);
+
+        // Backed off: the refresh interval alone doesn't retry
+        h.clock.advance(TITLE_REFRESH);
+        h.tick();
+        assert_eq!(h.probe_count(), 1);
+
+        // Retried after the backoff, which then doubles
+        h.clock.advance(BACKOFF_BASE);
+        h.tick();
+        assert_eq!(h.probe_count(), 2);
+        h.clock.advance(BACKOFF_BASE);
+        h.tick();
+        assert_eq!(h.probe_count(), 2);
+        h.clock.advance(BACKOFF_BASE);
+        h.tick();
+        assert_eq!(h.probe_count(), 3);
+
+        for _ in 0..3 {
+            h.clock.advance(BACKOFF_MAX);
+            assert_eq!(h.tick(), "Legacy");
+        }
+        assert_eq!(h.probe_count(), UNSUPPORTED_AFTER as usize);
+        assert_eq!(
+            h.stats.counters(h.clock.now()).unsupported_apps,
+            vec!["Legacy".to_string()]
+        );
+
+        // Other apps are unaffected
+        h.switch_to("Code");
+        assert_eq!(h.tick(), "Code window");
+
+        // Asked again once the longer wait is over, and listed only once
+        h.switch_to("Legacy");
+        h.clock.advance(UNSUPPORTED_RETRY);
+        assert_eq!(h.tick(), "Legacy");
+        assert_eq!(h.probe_count(), UNSUPPORTED_AFTER as usize + 2);
+        assert_eq!(h.stats.counters(h.clock.now()).unsupported_apps.len(), 1);
+
+        // Relaunched with permission granted
+        h.silent.lock().unwrap().clear();
+        h.clock.advance(UNSUPPORTED_RETRY);
+        assert_eq!(h.tick(), "Legacy window");
+        assert!(h.stats.counters(h.clock.now()).unsupported_apps.is_empty());
+    }
+
+    #[test]
+    fn counters_report_rates_and_latency() {
+        let mut h = harness(vec![]);
+        for _ in 0..10 {
+            h.tick();
+        }
+        h.switch_to("Slack");
+        h.tick();
+
+        let counters = h.stats.counters(h.clock.now());
+        assert_eq!(counters.ticks, 11);
+        assert_eq!(counters.title_probes, 2);
+        assert_eq!(counters.cache_hits, 9);
+        assert_eq!(counters.failures, 0);
+        assert!((counters.average_latency_ms - 40.0).abs() < 1e-6);
+        assert!(counters.probes_per_second > 0.5 && counters.probes_per_second < 1.0);
+
+        // A second source that never polls, like a start the stream rejected,
+        // leaves the running counters alone
+        let _rejected = CachedWindowSource::new(
+            FakeProber {
+                app: h.app.clone(),
+                silent: h.silent.clone(),
+                probes: h.probes.clone(),
+                clock: h.clock.clone(),
+            },
+            h.clock.clone(),
+            h.stats.clone(),
+        );
+        assert_eq!(h.stats.counters(h.clock.now()), counters);
+    }
+}
@@ -0,0 +1,102 @@
+use serde_json::Value;
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+use tokio::{
+    task::JoinHandle,
+    time::{sleep, Duration},
+};
+
+use crate::model::{ActiveWindow, MacOSError};
+use crate::rpc::client;
+use crate::window_info::window_source::WindowSource;
+
+/// A `WindowSource` that mirrors the window a running headless daemon is
+/// tracking, so the app can show live activity without probing twice.
+///
+/// The daemon is asked on a task of its own, so polls only read the latest
+/// answer and never wait on the socket.
+pub struct DaemonWindowSource {
+    latest: Arc<Mutex<Result<ActiveWindow, String>>>,
+    poller: JoinHandle<()>,
+}
+
+impl DaemonWindowSource {
+    /// Starts asking the daemon at `socket` every `interval`. Must be called
+    /// from within the async runtime.
+    pub fn new(socket: PathBuf, interval: Duration) -> Self {
+        let latest = Arc::new(Mutex::new(Ok(ActiveWindow::none())));
+        let poller = tokio::spawn({
+            let latest = latest.clone();
+            async move {
+                loop {
+                    let answer = current_window(&socket).await;
+                    if let Ok(mut latest) = latest.lock() {
+                        *latest = answer;
+                    }
+                    sleep(interval).await;
+                }
+            }
+        });
+
+        DaemonWindowSource { latest, poller }
+    }
+}
+
+async fn current_window(socket: &Path) -> Result<ActiveWindow, String> {
+    let answer = client::request_async(socket, "current_window", Value::Null)
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.message)?;
+
+    // The daemon reports no window while stopped or idle
+    serde_json::from_value::<Option<ActiveWindow>>(answer)
+        .map(|window| window.unwrap_or_else(ActiveWindow::none))
+        .map_err(|e| e.to_string())
+}
+
+impl WindowSource for DaemonWindowSource {
+    fn active_window(&mut self) -> Result<ActiveWindow, MacOSError> {
+        self.latest
+            .lock()
+            .map_err(|_| MacOSError::DaemonUnavailable("The last answer was lost".to_string()))?
+            .clone()
+            .map_err(MacOSError::DaemonUnavailable)
+    }
+}
+
+impl Drop for DaemonWindowSource {
+    fn drop(&mut self) {
+        self.poller.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc::server;
+    use crate::stream::manager::StreamManager;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn polls_read_the_daemons_last_answer() {
+        let data_dir = std::env::temp_dir().join(format!("locus-{}", uuid::Uuid::new_v4()));
+        let path = server::socket_path(&data_dir).unwrap();
+        let listener = server::bind(&path).unwrap();
+        let daemon = tokio::spawn(server::serve(
+            listener,
+            Arc::new(StreamManager::default()),
+            |_| async { Ok(json!({ "class": "Code", "title": "main.rs" })) },
+        ));
+
+        let mut source = DaemonWindowSource::new(path, Duration::from_millis(10));
+        // Nothing to mirror until the daemon first answers
+        assert_eq!(source.active_window().unwrap(), ActiveWindow::none());
+        sleep(Duration::from_millis(100)).await;
+        assert_eq!(source.active_window().unwrap().title, "main.rs");
+
+        daemon.abort();
+        std::fs::remove_dir_all(&data_dir).unwrap();
+    }
+}
@@ -1,100 +1,319 @@
-use std::sync::Arc;
-use tauri::{Emitter, EventTarget};
+use std::{
+    io::{self, Read},
+    process::{Child, Command, Output, Stdio},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    thread,
+    time::Instant,
+};
 use tokio::{
     sync::Mutex,
     time::{sleep, Duration},
 };
 
-use crate::model::{ActiveWindow, MacOSError};
+use crate::browser;
+use crate::clock::Clock;
+use crate::diagnostics::{classify_osascript_error, ScriptFailure};
+use crate::idle::{IdleDetector, IdleSource, IdleTransition};
+use crate::model::{ActiveWindow, AppError, ErrorInfo, MacOSError};
+use crate::stream::schedule::{PollOutcome, PollSchedule};
+use crate::window_info::cached_probe::WindowProber;
+use crate::window_info::window_source::WindowSource;
+
+/// Asks the system for the frontmost app, which costs no process, and
+/// AppleScript for its window: the active tab for browsers, the window title
+/// for everything else.
+///
+/// NSWorkspace only stays current in a process running an NSApplication
+/// loop, so the daemon reads the frontmost app off the window server's
+/// window list instead.
+#[derive(Default)]
+pub struct MacOSProber {
+    #[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+    headless: bool,
+}
 
-// For now, let's use a simple approach with system commands
-// In a production app, we'd use proper CoreGraphics bindings
-use std::process::Command;
+impl MacOSProber {
+    /// A prober for processes without an NSApplication run loop.
+    pub fn headless() -> Self {
+        MacOSProber { headless: true }
+    }
+}
 
-pub fn stream_macos(
-    cancel_flag: Arc<Mutex<bool>>,
-    app: tauri::AppHandle,
-    sleep_duration: Duration,
-) -> Result<(), MacOSError> {
-    tokio::spawn(async move {
-        let mut old_window_info = ActiveWindow::none();
-        let mut consecutive_failures = 0;
-        const MAX_FAILURES: u32 = 5;
-
-        loop {
-            if *cancel_flag.lock().await {
-                break;
-            }
+impl WindowProber for MacOSProber {
+    fn frontmost_app(&mut self) -> Result<String, MacOSError> {
+        #[cfg(target_os = "macos")]
+        return if self.headless {
+            crate::window_info::native_macos::frontmost_app_from_window_list()
+        } else {
+            Ok(crate::window_info::native_macos::get_native_window_info()?.class)
+        };
+
+        #[cfg(not(target_os = "macos"))]
+        Ok(get_general_window_info()?.class)
+    }
 
-            let window_info = get_macos_active_window_info().unwrap_or_else(|error| {
-                consecutive_failures += 1;
-                if consecutive_failures <= MAX_FAILURES {
-                    eprintln!(
-                        "Error getting window info (attempt {}): {:?}",
-                        consecutive_failures, error
-                    );
-                }
+    fn window(&mut self, app: &str) -> Result<ActiveWindow, MacOSError> {
+        let window = if browser::is_browser(app) {
+            get_browser_tab_info()?
+        } else {
+            get_enhanced_window_info()?
+        };
 
-                // If we keep failing, try to get basic app info as fallback
-                get_basic_app_info().unwrap_or_else(|_| ActiveWindow::none())
-            });
+        if window.title.is_empty() || window.title == "No Window" {
+            return Err(MacOSError::NoActiveWindow);
+        }
 
-            // Reset failure counter on success
-            if window_info.title != "none" {
-                consecutive_failures = 0;
-            }
+        // Keep the name the system reports, whatever the scripts call the app
+        Ok(ActiveWindow {
+            class: app.to_string(),
+            ..window
+        })
+    }
+}
 
-            // Only emit if the window info has actually changed
-            if window_info != old_window_info {
-                old_window_info = window_info.clone();
+static OSASCRIPT_SPAWNS: AtomicU64 = AtomicU64::new(0);
 
-                // Stream the window info to the frontend
-                if let Err(e) = app.emit_to(EventTarget::app(), "active-window-title", window_info)
-                {
-                    eprintln!("Error emitting window info: {:?}", e);
-                }
-            }
+/// `osascript` processes spawned since the app started.
+pub fn osascript_spawns() -> u64 {
+    OSASCRIPT_SPAWNS.load(Ordering::Relaxed)
+}
 
-            sleep(sleep_duration).await;
-        }
-    });
+/// How long a script may run before it counts as hung.
+pub const SCRIPT_TIMEOUT: Duration = Duration::from_secs(5);
 
-    Ok(())
+/// Starts `osascript` on `script` with its output piped. Every script the
+/// app runs goes through here, so `osascript_spawns` counts them all.
+pub fn spawn_osascript(script: &str) -> io::Result<Child> {
+    OSASCRIPT_SPAWNS.fetch_add(1, Ordering::Relaxed);
+    Command::new("osascript")
+        .arg("-e")
+        .arg(script)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
 }
 
-fn get_macos_active_window_info() -> Result<ActiveWindow, MacOSError> {
-    // Try multiple approaches in order of reliability
+/// Runs `script` to the end, or kills it once it has run `SCRIPT_TIMEOUT` and
+/// fails with `io::ErrorKind::TimedOut`: a script waiting on a permission
+/// prompt would otherwise hold up its caller for good.
+pub fn osascript(script: &str) -> io::Result<Output> {
+    run_to_end(spawn_osascript(script)?, SCRIPT_TIMEOUT)
+}
 
-    // 1. Try native CoreGraphics approach first (most reliable)
-    if let Ok(native_info) = crate::window_info::native_macos::get_native_window_info() {
-        if !native_info.title.is_empty() && native_info.title != "Unknown Application" {
-            return Ok(native_info);
+/// Waits up to `timeout` for `child` to exit, reading its output as it comes
+/// so a chatty child never blocks on a full pipe.
+fn run_to_end(mut child: Child, timeout: Duration) -> io::Result<Output> {
+    let stdout = child.stdout.take().map(drain);
+    let stderr = child.stderr.take().map(drain);
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        match child.try_wait()? {
+            Some(status) => break status,
+            None if Instant::now() < deadline => thread::sleep(Duration::from_millis(20)),
+            None => {
+                let _ = child.kill();
+                let _ = child.wait();
+                // The readers end by themselves once whatever the child
+                // started lets go of the pipes
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "The script timed out",
+                ));
+            }
         }
+    };
+
+    let collect = |reader: Option<thread::JoinHandle<io::Result<Vec<u8>>>>| match reader {
+        Some(reader) => reader
+            .join()
+            .unwrap_or_else(|_| Err(io::Error::other("The pipe reader panicked"))),
+        None => Ok(Vec::new()),
+    };
+    let stdout = collect(stdout);
+    let stderr = collect(stderr);
+
+    Ok(Output {
+        status,
+        stdout: stdout?,
+        stderr: stderr?,
+    })
+}
+
+fn drain(mut pipe: impl Read + Send + 'static) -> thread::JoinHandle<io::Result<Vec<u8>>> {
+    thread::spawn(move || {
+        let mut out = Vec::new();
+        pipe.read_to_end(&mut out).map(|_| out)
+    })
+}
+
+/// The error a probe reports when `script` couldn't run to the end. Timeouts
+/// count as script failures, so the cache backs off the app like any other.
+pub fn launch_error(script: &'static str) -> impl FnOnce(io::Error) -> MacOSError {
+    move |source| match source.kind() {
+        io::ErrorKind::TimedOut => MacOSError::Script(ScriptFailure::Timeout),
+        _ => MacOSError::Launch { script, source },
     }
+}
+
+/// Why a script that exited with an error failed.
+fn script_error(output: &Output) -> MacOSError {
+    MacOSError::Script(classify_osascript_error(&String::from_utf8_lossy(
+        &output.stderr,
+    )))
+}
+
+/// Change detection and failure counting for the polling loop.
+#[derive(Debug)]
+pub struct WindowTracker {
+    old_window_info: ActiveWindow,
+    consecutive_failures: u32,
+    /// The error that made the probe count as failing, until it is taken.
+    failure: Option<MacOSError>,
+}
 
-    // 2. Try the enhanced AppleScript approach
-    if let Ok(enhanced_info) = get_enhanced_window_info() {
-        if !enhanced_info.title.is_empty()
-            && enhanced_info.title != "No Window"
-            && !enhanced_info.title.contains("Error|")
-            && enhanced_info.title != "Active"
-        {
-            return Ok(enhanced_info);
+impl Default for WindowTracker {
+    fn default() -> Self {
+        WindowTracker {
+            old_window_info: ActiveWindow::none(),
+            consecutive_failures: 0,
+            failure: None,
         }
     }
+}
+
+impl WindowTracker {
+    const MAX_FAILURES: u32 = 5;
+
+    /// Polls `source` once and returns the window if it changed since the last poll.
+    pub fn poll<S: WindowSource>(&mut self, source: &mut S) -> Option<ActiveWindow> {
+        let window_info = source.active_window().unwrap_or_else(|error| {
+            self.consecutive_failures += 1;
+            if self.consecutive_failures <= Self::MAX_FAILURES {
+                tracing::warn!(
+                    attempt = self.consecutive_failures,
+                    error = %error,
+                    "Unable to read the active window"
+                );
+            }
+            if self.consecutive_failures == Self::MAX_FAILURES {
+                self.failure = Some(error);
+            }
 
-    // 3. Try browser-specific detection for browsers
-    if let Ok(browser_info) = get_browser_tab_info() {
-        if !browser_info.title.is_empty()
-            && browser_info.title != "No Window"
-            && !browser_info.title.contains("Error|")
-        {
-            return Ok(browser_info);
+            // If we keep failing, try to get basic app info as fallback
+            source
+                .fallback_window()
+                .unwrap_or_else(|_| ActiveWindow::none())
+        });
+
+        // Reset failure counter on success
+        if window_info.title != "none" {
+            self.consecutive_failures = 0;
+        }
+
+        // Only report if the window info has actually changed
+        if window_info != self.old_window_info {
+            self.old_window_info = window_info.clone();
+            return Some(window_info);
         }
+
+        None
     }
 
-    // 4. Fallback to general window detection
-    get_general_window_info()
+    /// The error behind a run of failed polls, once per run.
+    pub fn take_failure(&mut self) -> Option<MacOSError> {
+        self.failure.take()
+    }
+}
+
+/// What the polling loop reports: window changes, idle transitions and a
+/// probe that keeps failing.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StreamEvent {
+    Window(ActiveWindow),
+    IdleStart { idle_for: Duration },
+    IdleEnd,
+    Error(ErrorInfo),
+}
+
+/// Polls `source` until `cancel_flag` is set, handing every window change and
+/// idle transition to `emit`.
+///
+/// Windows are not polled while the user is idle or the screen is locked.
+/// When they come back the current window is reported again, even if it did
+/// not change, so the next segment starts there. `schedule` picks the wait
+/// between polls.
+pub async fn poll_windows<S, I, C, F>(
+    mut source: S,
+    mut idle: IdleDetector<I>,
+    cancel_flag: Arc<Mutex<bool>>,
+    mut schedule: PollSchedule<C>,
+    mut emit: F,
+) where
+    S: WindowSource,
+    I: IdleSource,
+    C: Clock,
+    F: FnMut(StreamEvent) + Send,
+{
+    let mut tracker = WindowTracker::default();
+
+    loop {
+        if *cancel_flag.lock().await {
+            break;
+        }
+
+        match idle.poll() {
+            Some(IdleTransition::Started { idle_for }) => {
+                emit(StreamEvent::IdleStart { idle_for });
+            }
+            Some(IdleTransition::Ended) => {
+                tracker = WindowTracker::default();
+                emit(StreamEvent::IdleEnd);
+            }
+            None => {}
+        }
+
+        let outcome = if idle.is_idle() {
+            PollOutcome::Idle
+        } else if idle.screen_locked() {
+            PollOutcome::Locked
+        } else {
+            // Probes block on `osascript`, so they run off the async workers
+            let probed = tokio::task::spawn_blocking(move || {
+                let changed = tracker.poll(&mut source);
+                (source, tracker, changed)
+            })
+            .await;
+            let Ok((probed_source, probed_tracker, changed)) = probed else {
+                tracing::error!("The window probe panicked");
+                break;
+            };
+            source = probed_source;
+            tracker = probed_tracker;
+
+            if let Some(error) = tracker.take_failure() {
+                let error = AppError::from(error).context("Unable to read the active window");
+                emit(StreamEvent::Error(ErrorInfo::from(&error)));
+            }
+            match changed {
+                Some(window_info) => {
+                    emit(StreamEvent::Window(window_info));
+                    PollOutcome::Changed
+                }
+                None => PollOutcome::Unchanged,
+            }
+        };
+
+        if source.is_exhausted() {
+            break;
+        }
+
+        sleep(schedule.next(outcome)).await;
+    }
 }
 
 fn get_browser_tab_info() -> Result<ActiveWindow, MacOSError> {
@@ -112,7 +331,7 @@ try
             if (count of windows) > 0 then
                 set currentTab to current tab of front window
                 set tabTitle to name of currentTab
-                return "Safari|" & tabTitle
+                return "Safari|" & tabTitle & linefeed & ((URL of currentTab) as text)
             end if
         end tell
     else if (appName contains "Chrome") or (appName contains "Google Chrome") then
@@ -121,7 +340,7 @@ try
                 tell front window
                     set activeTab to active tab
                     set tabTitle to title of activeTab
-                    return "Chrome|" & tabTitle
+                    return "Chrome|" & tabTitle & linefeed & ((URL of activeTab) as text)
                 end tell
             end if
         end tell
@@ -131,10 +350,6 @@ try
             tell process "Firefox"
                 if (count of windows) > 0 then
                     set windowTitle to name of front window
-                    -- Clean up Firefox title format
-                    if windowTitle contains " - Mozilla Firefox" then
-                        set windowTitle to text 1 thru ((offset of " - Mozilla Firefox" in windowTitle) - 1) of windowTitle
-                    end if
                     return "Firefox|" & windowTitle
                 end if
             end tell
@@ -147,7 +362,7 @@ try
                     tell front window
                         set activeTab to active tab
                         set tabTitle to title of activeTab
-                        return "Brave|" & tabTitle
+                        return "Brave|" & tabTitle & linefeed & ((URL of activeTab) as text)
                     end tell
                 end if
             end tell
@@ -157,9 +372,6 @@ try
                 tell process "Brave Browser"
                     if (count of windows) > 0 then
                         set windowTitle to name of front window
-                        if windowTitle contains " - Brave" then
-                            set windowTitle to text 1 thru ((offset of " - Brave" in windowTitle) - 1) of windowTitle
-                        end if
                         return "Brave|" & windowTitle
                     end if
                 end tell
@@ -171,7 +383,7 @@ try
                 tell front window
                     set activeTab to active tab
                     set tabTitle to title of activeTab
-                    return "Arc|" & tabTitle
+                    return "Arc|" & tabTitle & linefeed & ((URL of activeTab) as text)
                 end tell
             end if
         end tell
@@ -181,7 +393,7 @@ try
                 tell front window
                     set activeTab to active tab
                     set tabTitle to title of activeTab
-                    return "Edge|" & tabTitle
+                    return "Edge|" & tabTitle & linefeed & ((URL of activeTab) as text)
                 end tell
             end if
         end tell
@@ -205,24 +417,14 @@ end try
 return "Unknown|No Browser Tab"
 "#;
 
-    let output = Command::new("osascript")
-        .arg("-e")
-        .arg(script)
-        .output()
-        .map_err(|e| {
-            MacOSError::CoreGraphicsError(format!("Failed to run browser AppleScript: {}", e))
-        })?;
-
-    if output.status.success() {
-        let result = String::from_utf8_lossy(&output.stdout);
-        let parts: Vec<&str> = result.trim().split('|').collect();
-
-        if parts.len() >= 2 && parts[0] != "Unknown" {
-            return Ok(ActiveWindow {
-                class: parts[0].to_string(),
-                title: parts[1].to_string(),
-            });
-        }
+    let output = osascript(script).map_err(launch_error("browser"))?;
+
+    if !output.status.success() {
+        return Err(script_error(&output));
+    }
+
+    if let Some(window) = browser::parse_tab_output(&String::from_utf8_lossy(&output.stdout)) {
+        return Ok(window);
     }
 
     Err(MacOSError::NoActiveWindow)
@@ -276,25 +478,15 @@ tell application "System Events"
         end try
     end try
     
-    -- Clean up window title if we got something
+    -- App suffixes and other noise are stripped by the title normalizer
     if windowTitle is not "" then
-        -- Clean up and enhance the window title based on the application
         if appName contains "Chrome" or appName contains "Google Chrome" then
-            if windowTitle contains " - Google Chrome" then
-                set windowTitle to text 1 thru ((offset of " - Google Chrome" in windowTitle) - 1) of windowTitle
-            end if
             return "Chrome|" & windowTitle
             
         else if appName contains "Safari" then
-            if windowTitle contains " — " then
-                set windowTitle to text 1 thru ((offset of " — " in windowTitle) - 1) of windowTitle
-            end if
             return "Safari|" & windowTitle
             
         else if appName contains "Firefox" then
-            if windowTitle contains " - Mozilla Firefox" then
-                set windowTitle to text 1 thru ((offset of " - Mozilla Firefox" in windowTitle) - 1) of windowTitle
-            end if
             return "Firefox|" & windowTitle
             
         else if appName contains "Code" or appName contains "VS Code" or appName contains "Visual Studio Code" then
@@ -316,126 +508,254 @@ tell application "System Events"
 end tell
 "#;
 
-    let output = Command::new("osascript")
-        .arg("-e")
-        .arg(script)
-        .output()
-        .map_err(|e| {
-            MacOSError::CoreGraphicsError(format!("Failed to run enhanced AppleScript: {}", e))
-        })?;
-
-    if output.status.success() {
-        let result = String::from_utf8_lossy(&output.stdout);
-        let parts: Vec<&str> = result.trim().split('|').collect();
-
-        if parts.len() >= 2 {
-            return Ok(ActiveWindow {
-                class: parts[0].to_string(),
-                title: parts[1].to_string(),
-            });
-        }
+    let output = osascript(script).map_err(launch_error("enhanced"))?;
+
+    if !output.status.success() {
+        return Err(script_error(&output));
+    }
+
+    let result = String::from_utf8_lossy(&output.stdout);
+    let parts: Vec<&str> = result.trim().split('|').collect();
+
+    if parts.len() >= 2 {
+        return Ok(ActiveWindow {
+            class: parts[0].to_string(),
+            title: parts[1].to_string(),
+            url: None,
+            raw_title: None,
+        });
     }
 
     Err(MacOSError::NoActiveWindow)
 }
 
+/// The frontmost app and its window title, from System Events alone, for
+/// where there is no window server to ask. The title is left empty when the
+/// app has no window to name.
+#[cfg(not(target_os = "macos"))]
 fn get_general_window_info() -> Result<ActiveWindow, MacOSError> {
-    // Enhanced general window detection for non-browser apps
     let script = r#"
 tell application "System Events"
     set frontApp to first application process whose frontmost is true
     set appName to name of frontApp
-    
-    -- Try to get detailed window information
     try
-        set frontWindow to front window of frontApp
-        set windowTitle to name of frontWindow
-        
-        -- For some apps, try to get more specific information
-        if appName contains "Code" or appName contains "VS Code" or appName contains "Visual Studio Code" then
-            -- VS Code window titles usually contain the file name and path
-            return "VS Code|" & windowTitle
-        else if appName contains "Terminal" then
-            return "Terminal|" & windowTitle
-        else if appName contains "Finder" then
-            return "Finder|" & windowTitle
-        else if appName contains "Xcode" then
-            return "Xcode|" & windowTitle
-        else if appName contains "Slack" then
-            return "Slack|" & windowTitle
-        else if appName contains "Discord" then
-            return "Discord|" & windowTitle
-        else if appName contains "Zoom" then
-            return "Zoom|" & windowTitle
-        else if appName contains "Teams" or appName contains "Microsoft Teams" then
-            return "Teams|" & windowTitle
-        else if appName contains "Notion" then
-            return "Notion|" & windowTitle
-        else if appName contains "Obsidian" then
-            return "Obsidian|" & windowTitle
-        else
-            return appName & "|" & windowTitle
-        end if
+        return appName & "|" & (name of front window of frontApp)
     on error
-        -- Fallback if window name cannot be retrieved
-        return appName & "|Active"
+        return appName & "|"
     end try
 end tell
 "#;
 
-    let output = Command::new("osascript")
-        .arg("-e")
-        .arg(script)
-        .output()
-        .map_err(|e| {
-            MacOSError::CoreGraphicsError(format!("Failed to run general AppleScript: {}", e))
-        })?;
+    let output = osascript(script).map_err(launch_error("general"))?;
 
     if !output.status.success() {
-        return Err(MacOSError::CoreGraphicsError(
-            "AppleScript execution failed".to_string(),
-        ));
+        return Err(script_error(&output));
     }
 
     let result = String::from_utf8_lossy(&output.stdout);
-    let parts: Vec<&str> = result.trim().split('|').collect();
-
-    if parts.len() >= 2 {
-        Ok(ActiveWindow {
-            class: parts[0].to_string(),
-            title: parts[1].to_string(),
-        })
-    } else {
-        Ok(ActiveWindow::none())
+    match result.trim().split_once('|') {
+        Some((app_name, title)) if !app_name.is_empty() => Ok(ActiveWindow {
+            class: app_name.to_string(),
+            title: title.to_string(),
+            url: None,
+            raw_title: None,
+        }),
+        _ => Err(MacOSError::NoActiveWindow),
     }
 }
 
-fn get_basic_app_info() -> Result<ActiveWindow, MacOSError> {
-    // Simple fallback that just gets the frontmost application name
-    let script = r#"
-tell application "System Events"
-    set frontApp to first application process whose frontmost is true
-    return name of frontApp
-end tell
-"#;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::idle::{scripted_idle::ScriptedIdleSource, NeverIdle};
+    use crate::window_info::scripted_source::ScriptedWindowSource;
+
+    fn window(class: &str, title: &str) -> ActiveWindow {
+        ActiveWindow {
+            class: class.to_string(),
+            title: title.to_string(),
+            url: None,
+            raw_title: None,
+        }
+    }
 
-    let output = Command::new("osascript")
-        .arg("-e")
-        .arg(script)
-        .output()
-        .map_err(|e| {
-            MacOSError::CoreGraphicsError(format!("Failed to run basic AppleScript: {}", e))
-        })?;
-
-    if output.status.success() {
-        let app_name = String::from_utf8_lossy(&output.stdout).trim().to_string();
-        if !app_name.is_empty() {
-            return Ok(ActiveWindow {
-                class: app_name.clone(),
-                title: format!("{} - Active", app_name),
-            });
+    #[test]
+    fn tracker_reports_only_changes() {
+        let mut source =
+            ScriptedWindowSource::new(vec![(Duration::ZERO, Some(window("Finder", "Downloads")))]);
+        let mut tracker = WindowTracker::default();
+
+        assert_eq!(
+            tracker.poll(&mut source),
+            Some(window("Finder", "Downloads"))
+        );
+        assert_eq!(tracker.poll(&mut source), None);
+    }
+
+    #[test]
+    fn tracker_counts_failures_until_a_window_is_seen() {
+        let mut source = ScriptedWindowSource::new(vec![(Duration::ZERO, None)]);
+        let mut tracker = WindowTracker::default();
+
+        for _ in 0..3 {
+            assert_eq!(tracker.poll(&mut source), None);
         }
+        assert_eq!(tracker.consecutive_failures, 3);
     }
 
-    Err(MacOSError::NoActiveWindow)
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn headless_ticks_spawn_nothing_while_the_app_stays_in_front() {
+        use crate::clock::SystemClock;
+        use crate::window_info::cached_probe::{CachedWindowSource, ProbeStats};
+
+        let mut source = CachedWindowSource::new(
+            MacOSProber::headless(),
+            SystemClock,
+            Arc::new(ProbeStats::default()),
+        );
+        // The first tick probes the title, whether or not the script may run
+        let _ = source.active_window();
+        let spawns = osascript_spawns();
+
+        for _ in 0..5 {
+            let _ = source.active_window();
+        }
+        assert_eq!(osascript_spawns(), spawns);
+    }
+
+    fn sh(command: &str) -> Child {
+        Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap()
+    }
+
+    #[test]
+    fn children_writing_more_than_a_pipe_holds_still_finish() {
+        let output = run_to_end(
+            sh("head -c 1000000 /dev/zero; echo failed >&2"),
+            Duration::from_secs(5),
+        )
+        .unwrap();
+
+        assert!(output.status.success());
+        assert_eq!(output.stdout.len(), 1_000_000);
+        assert_eq!(output.stderr, b"failed\n");
+    }
+
+    #[test]
+    fn hung_children_are_killed_at_the_timeout() {
+        let error = run_to_end(sh("sleep 5"), Duration::from_millis(100)).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn timed_out_scripts_fail_the_probe() {
+        let timed_out = io::Error::new(io::ErrorKind::TimedOut, "The script timed out");
+        assert!(matches!(
+            launch_error("enhanced")(timed_out),
+            MacOSError::Script(ScriptFailure::Timeout)
+        ));
+        let missing = io::Error::from(io::ErrorKind::NotFound);
+        assert!(matches!(
+            launch_error("enhanced")(missing),
+            MacOSError::Launch {
+                script: "enhanced",
+                ..
+            }
+        ));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn poll_windows_emits_each_change_once() {
+        let source = ScriptedWindowSource::new(vec![
+            (Duration::ZERO, Some(window("Code", "main.rs"))),
+            (Duration::from_secs(2), Some(window("Safari", "Docs"))),
+            (Duration::from_secs(4), None),
+            (Duration::from_secs(6), Some(window("Safari", "Docs"))),
+        ]);
+        let cancel_flag = Arc::new(Mutex::new(false));
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+
+        let handle = tokio::spawn(poll_windows(
+            source,
+            IdleDetector::new(NeverIdle, Duration::from_secs(60)),
+            cancel_flag.clone(),
+            PollSchedule::fixed(Duration::from_millis(300)),
+            move |event| {
+                let _ = tx.send(event);
+            },
+        ));
+
+        sleep(Duration::from_secs(8)).await;
+        *cancel_flag.lock().await = true;
+        handle.await.unwrap();
+
+        let mut emitted = Vec::new();
+        while let Ok(event) = rx.try_recv() {
+            emitted.push(event);
+        }
+
+        assert_eq!(
+            emitted,
+            vec![
+                StreamEvent::Window(window("Code", "main.rs")),
+                StreamEvent::Window(window("Safari", "Docs")),
+                StreamEvent::Window(ActiveWindow::none()),
+                StreamEvent::Error(ErrorInfo {
+                    code: "probe.noActiveWindow".to_string(),
+                    message: "Unable to read the active window: No active window found".to_string(),
+                    retryable: true,
+                }),
+                StreamEvent::Window(window("Safari", "Docs")),
+            ]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn poll_windows_pauses_while_idle_and_reports_the_window_on_return() {
+        let source = ScriptedWindowSource::new(vec![
+            (Duration::ZERO, Some(window("Slack", "general"))),
+            (Duration::from_secs(200), Some(window("Slack", "general"))),
+        ]);
+        let idle = IdleDetector::new(
+            ScriptedIdleSource::new(vec![Duration::ZERO, Duration::from_secs(150)]),
+            Duration::from_secs(60),
+        );
+        let cancel_flag = Arc::new(Mutex::new(false));
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+
+        tokio::spawn(poll_windows(
+            source,
+            idle,
+            cancel_flag,
+            PollSchedule::fixed(Duration::from_secs(1)),
+            move |event| {
+                let _ = tx.send(event);
+            },
+        ))
+        .await
+        .unwrap();
+
+        let mut emitted = Vec::new();
+        while let Ok(event) = rx.try_recv() {
+            emitted.push(event);
+        }
+
+        assert_eq!(
+            emitted,
+            vec![
+                StreamEvent::Window(window("Slack", "general")),
+                StreamEvent::IdleStart {
+                    idle_for: Duration::from_secs(60)
+                },
+                StreamEvent::IdleEnd,
+                StreamEvent::Window(window("Slack", "general")),
+            ]
+        );
+    }
 }
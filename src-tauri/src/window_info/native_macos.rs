@@ -1,4 +1,6 @@
+use crate::browser::is_browser;
 use crate::model::{ActiveWindow, MacOSError};
+use crate::window_info::macos_window_info::{launch_error, osascript};
 use cocoa::base::{id, nil};
 use cocoa::foundation::NSAutoreleasePool;
 use objc::{class, msg_send, sel, sel_impl};
@@ -38,10 +40,60 @@ pub fn get_native_window_info() -> Result<ActiveWindow, MacOSError> {
         Ok(ActiveWindow {
             title: app_name.clone(),
             class: app_name,
+            url: None,
+            raw_title: None,
         })
     }
 }
 
+/// Name of the app owning the frontmost normal window, from the window
+/// server. Unlike NSWorkspace this stays current without a run loop, so the
+/// daemon can ask on every tick without spawning anything.
+pub fn frontmost_app_from_window_list() -> Result<String, MacOSError> {
+    use core_foundation::base::{CFType, TCFType};
+    use core_foundation::dictionary::{CFDictionary, CFDictionaryRef};
+    use core_foundation::number::CFNumber;
+    use core_foundation::string::CFString;
+    use core_graphics::window::{
+        copy_window_info, kCGNullWindowID, kCGWindowLayer, kCGWindowListExcludeDesktopElements,
+        kCGWindowListOptionOnScreenOnly, kCGWindowOwnerName,
+    };
+
+    let windows = copy_window_info(
+        kCGWindowListOptionOnScreenOnly | kCGWindowListExcludeDesktopElements,
+        kCGNullWindowID,
+    )
+    .ok_or(MacOSError::NoActiveWindow)?;
+    let (layer_key, owner_key) = unsafe {
+        (
+            CFString::wrap_under_get_rule(kCGWindowLayer),
+            CFString::wrap_under_get_rule(kCGWindowOwnerName),
+        )
+    };
+
+    // Windows come front to back. Layer 0 holds app windows; the menu bar,
+    // Dock and overlays sit above it
+    for window in windows.iter() {
+        let window: CFDictionary<CFString, CFType> =
+            unsafe { CFDictionary::wrap_under_get_rule(*window as CFDictionaryRef) };
+        let layer = window
+            .find(&layer_key)
+            .and_then(|value| value.downcast::<CFNumber>())
+            .and_then(|number| number.to_i64());
+        if layer != Some(0) {
+            continue;
+        }
+        if let Some(owner) = window
+            .find(&owner_key)
+            .and_then(|value| value.downcast::<CFString>())
+        {
+            return Ok(owner.to_string());
+        }
+    }
+
+    Err(MacOSError::NoActiveWindow)
+}
+
 // Enhanced function to get detailed browser tab information
 pub fn get_enhanced_browser_info() -> Result<ActiveWindow, MacOSError> {
     // First get the basic window info
@@ -53,6 +105,8 @@ pub fn get_enhanced_browser_info() -> Result<ActiveWindow, MacOSError> {
             return Ok(ActiveWindow {
                 class: basic_info.class,
                 title: enhanced_info,
+                url: None,
+                raw_title: None,
             });
         }
     }
@@ -60,22 +114,6 @@ pub fn get_enhanced_browser_info() -> Result<ActiveWindow, MacOSError> {
     Ok(basic_info)
 }
 
-fn is_browser(app_name: &str) -> bool {
-    let browsers = [
-        "Safari",
-        "Chrome",
-        "Google Chrome",
-        "Firefox",
-        "Mozilla Firefox",
-        "Brave",
-        "Brave Browser",
-        "Arc",
-        "Edge",
-        "Microsoft Edge",
-    ];
-    browsers.iter().any(|&browser| app_name.contains(browser))
-}
-
 fn get_browser_tab_via_applescript(app_name: &str) -> Result<String, MacOSError> {
     let script = if app_name.contains("Chrome") || app_name.contains("Google Chrome") {
         r#"tell application "Google Chrome"
@@ -104,18 +142,12 @@ fn get_browser_tab_via_applescript(app_name: &str) -> Result<String, MacOSError>
         end tell"#
     } else if app_name.contains("Firefox") {
         // Firefox doesn't support AppleScript the same way, fall back to window title
-        return Err(MacOSError::CoreGraphicsError(
-            "Firefox doesn't support tab AppleScript".to_string(),
-        ));
+        return Err(MacOSError::UnsupportedApp(app_name.to_string()));
     } else {
-        return Err(MacOSError::CoreGraphicsError("Unknown browser".to_string()));
+        return Err(MacOSError::UnsupportedApp(app_name.to_string()));
     };
 
-    let output = std::process::Command::new("osascript")
-        .arg("-e")
-        .arg(script)
-        .output()
-        .map_err(|e| MacOSError::CoreGraphicsError(format!("AppleScript failed: {}", e)))?;
+    let output = osascript(script).map_err(launch_error("browser tab"))?;
 
     if output.status.success() {
         let result = String::from_utf8_lossy(&output.stdout).trim().to_string();
@@ -124,7 +156,5 @@ fn get_browser_tab_via_applescript(app_name: &str) -> Result<String, MacOSError>
         }
     }
 
-    Err(MacOSError::CoreGraphicsError(
-        "No tab title found".to_string(),
-    ))
+    Err(MacOSError::NoActiveWindow)
 }
@@ -0,0 +1,57 @@
+use tokio::time::{Duration, Instant};
+
+use crate::model::{ActiveWindow, MacOSError};
+use crate::window_info::window_source::WindowSource;
+
+/// A `WindowSource` that replays a fixed script of windows.
+///
+/// Each step is an offset from the first poll and the window that is frontmost
+/// from that point on; `None` makes the probe fail. Offsets are measured with
+/// tokio's clock, so tests can drive it with `start_paused`.
+pub struct ScriptedWindowSource {
+    steps: Vec<(Duration, Option<ActiveWindow>)>,
+    started_at: Option<Instant>,
+}
+
+impl ScriptedWindowSource {
+    pub fn new(mut steps: Vec<(Duration, Option<ActiveWindow>)>) -> Self {
+        steps.sort_by_key(|(at, _)| *at);
+
+        ScriptedWindowSource {
+            steps,
+            started_at: None,
+        }
+    }
+
+    /// Whether the last step of the script has been reached.
+    pub fn is_finished(&self) -> bool {
+        match (self.started_at, self.steps.last()) {
+            (_, None) => true,
+            (Some(started_at), Some((at, _))) => started_at.elapsed() >= *at,
+            (None, Some(_)) => false,
+        }
+    }
+
+    fn current_step(&mut self) -> Option<&Option<ActiveWindow>> {
+        let elapsed = self.started_at.get_or_insert_with(Instant::now).elapsed();
+
+        self.steps
+            .iter()
+            .take_while(|(at, _)| *at <= elapsed)
+            .last()
+            .map(|(_, window)| window)
+    }
+}
+
+impl WindowSource for ScriptedWindowSource {
+    fn active_window(&mut self) -> Result<ActiveWindow, MacOSError> {
+        match self.current_step() {
+            Some(Some(window)) => Ok(window.clone()),
+            _ => Err(MacOSError::NoActiveWindow),
+        }
+    }
+
+    fn is_exhausted(&self) -> bool {
+        self.is_finished()
+    }
+}
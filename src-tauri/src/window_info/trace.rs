@@ -0,0 +1,260 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use crate::model::{ActiveWindow, AppError, MacOSError};
+use crate::window_info::{scripted_source::ScriptedWindowSource, window_source::WindowSource};
+
+/// Folder inside the app data folder that traces are recorded to and
+/// replayed from.
+pub const TRACES_DIR_NAME: &str = "traces";
+
+/// Where the trace `name` lives under `data_dir`, creating the folder. Only a
+/// bare file name is accepted, so callers can't reach files elsewhere.
+pub fn trace_path(data_dir: &Path, name: &str) -> Result<PathBuf, AppError> {
+    let bare = Path::new(name)
+        .file_name()
+        .is_some_and(|file_name| file_name == name);
+    if !bare {
+        return Err(AppError::Validation(format!(
+            "{:?} is not a trace file name",
+            name
+        )));
+    }
+
+    let dir = data_dir.join(TRACES_DIR_NAME);
+    std::fs::create_dir_all(&dir)?;
+
+    Ok(dir.join(name))
+}
+
+/// One line of a JSON-lines activity trace.
+///
+/// `window` is `None` when every probe failed at that point, so a replay
+/// reproduces the same gaps the recording saw.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TraceEntry {
+    pub elapsed_ms: u64,
+    pub window: Option<ActiveWindow>,
+}
+
+/// Wraps a `WindowSource` and appends everything it observes to a trace file.
+pub struct RecordingSource<S: WindowSource> {
+    inner: S,
+    file: File,
+    started_at: Instant,
+}
+
+impl<S: WindowSource> RecordingSource<S> {
+    pub fn create(inner: S, path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(path)?;
+
+        Ok(RecordingSource {
+            inner,
+            file,
+            started_at: Instant::now(),
+        })
+    }
+
+    fn record(&mut self, window: Option<&ActiveWindow>) {
+        let entry = TraceEntry {
+            elapsed_ms: self.started_at.elapsed().as_millis() as u64,
+            window: window.cloned(),
+        };
+
+        let written = serde_json::to_string(&entry)
+            .map_err(io::Error::from)
+            .and_then(|line| writeln!(self.file, "{}", line));
+
+        if let Err(e) = written {
+            tracing::error!(error = %e, "Unable to write the activity trace");
+        }
+    }
+}
+
+impl<S: WindowSource> WindowSource for RecordingSource<S> {
+    fn active_window(&mut self) -> Result<ActiveWindow, MacOSError> {
+        // Failures are only recorded once the fallback has had its chance too
+        let result = self.inner.active_window();
+        if let Ok(window) = &result {
+            self.record(Some(window));
+        }
+
+        result
+    }
+
+    fn fallback_window(&mut self) -> Result<ActiveWindow, MacOSError> {
+        let result = self.inner.fallback_window();
+        self.record(result.as_ref().ok());
+
+        result
+    }
+
+    fn is_exhausted(&self) -> bool {
+        self.inner.is_exhausted()
+    }
+}
+
+/// Reads a JSON-lines trace, skipping blank lines.
+pub fn read_trace(reader: impl BufRead) -> io::Result<Vec<TraceEntry>> {
+    reader
+        .lines()
+        .filter(|line| !matches!(line, Ok(line) if line.trim().is_empty()))
+        .map(|line| serde_json::from_str(&line?).map_err(io::Error::from))
+        .collect()
+}
+
+/// Builds a source that plays a recorded trace back with its original timing.
+pub fn replay_source(path: impl AsRef<Path>) -> io::Result<ScriptedWindowSource> {
+    let entries = read_trace(BufReader::new(File::open(path)?))?;
+
+    Ok(ScriptedWindowSource::new(
+        entries
+            .into_iter()
+            .map(|entry| (Duration::from_millis(entry.elapsed_ms), entry.window))
+            .collect(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(class: &str, title: &str) -> ActiveWindow {
+        ActiveWindow {
+            class: class.to_string(),
+            title: title.to_string(),
+            url: None,
+            raw_title: None,
+        }
+    }
+
+    fn temp_trace_path() -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("locus-trace-{}.jsonl", uuid::Uuid::new_v4()))
+    }
+
+    #[test]
+    fn recording_round_trips_through_read_trace() {
+        let path = temp_trace_path();
+        let script = ScriptedWindowSource::new(vec![
+            (Duration::ZERO, Some(window("Finder", "Downloads"))),
+            (Duration::from_secs(3600), None),
+        ]);
+
+        let mut source = RecordingSource::create(script, &path).unwrap();
+        source.active_window().unwrap();
+        source.active_window().unwrap();
+        drop(source);
+
+        let entries = read_trace(BufReader::new(File::open(&path).unwrap())).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(entries.len(), 2);
+        assert!(entries
+            .iter()
+            .all(|entry| entry.window == Some(window("Finder", "Downloads"))));
+    }
+
+    #[test]
+    fn traces_stay_inside_the_traces_folder() {
+        let data_dir = std::env::temp_dir().join(format!("locus-{}", uuid::Uuid::new_v4()));
+
+        let path = trace_path(&data_dir, "morning.jsonl").unwrap();
+        assert_eq!(path, data_dir.join(TRACES_DIR_NAME).join("morning.jsonl"));
+        for name in ["", ".", "..", "../locus.db", "/etc/hosts", "a/b.jsonl"] {
+            assert!(trace_path(&data_dir, name).is_err(), "{}", name);
+        }
+        std::fs::remove_dir_all(&data_dir).unwrap();
+    }
+
+    #[test]
+    fn read_trace_keeps_failed_probes() {
+        let trace = r#"
+{"elapsed_ms":0,"window":{"class":"Finder","title":"Finder"}}
+{"elapsed_ms":300,"window":null}
+
+{"elapsed_ms":600,"window":{"class":"Slack","title":"general"}}
+"#;
+
+        let entries = read_trace(trace.as_bytes()).unwrap();
+
+        assert_eq!(
+            entries,
+            vec![
+                TraceEntry {
+                    elapsed_ms: 0,
+                    window: Some(window("Finder", "Finder")),
+                },
+                TraceEntry {
+                    elapsed_ms: 300,
+                    window: None,
+                },
+                TraceEntry {
+                    elapsed_ms: 600,
+                    window: Some(window("Slack", "general")),
+                },
+            ]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn replay_emits_recorded_changes_and_stops() {
+        use crate::idle::{IdleDetector, NeverIdle};
+        use crate::stream::schedule::PollSchedule;
+        use crate::window_info::macos_window_info::{poll_windows, StreamEvent};
+        use std::sync::Arc;
+        use tokio::sync::Mutex;
+
+        let path = temp_trace_path();
+        std::fs::write(
+            &path,
+            concat!(
+                r#"{"elapsed_ms":0,"window":{"class":"Finder","title":"Finder"}}"#,
+                "\n",
+                r#"{"elapsed_ms":900,"window":{"class":"Finder","title":"Finder"}}"#,
+                "\n",
+                r#"{"elapsed_ms":1800,"window":{"class":"Code","title":"main.rs"}}"#,
+                "\n",
+            ),
+        )
+        .unwrap();
+
+        let source = replay_source(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        poll_windows(
+            source,
+            IdleDetector::new(NeverIdle, Duration::from_secs(60)),
+            Arc::new(Mutex::new(false)),
+            PollSchedule::fixed(Duration::from_millis(300)),
+            move |event| {
+                let _ = tx.send(event);
+            },
+        )
+        .await;
+
+        assert_eq!(
+            rx.recv().await,
+            Some(StreamEvent::Window(window("Finder", "Finder")))
+        );
+        assert_eq!(
+            rx.recv().await,
+            Some(StreamEvent::Window(window("Code", "main.rs")))
+        );
+        assert_eq!(rx.recv().await, None);
+    }
+
+    #[test]
+    fn read_trace_rejects_malformed_lines() {
+        assert!(read_trace("not json\n".as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,21 @@
+use crate::model::{ActiveWindow, MacOSError};
+
+/// Something the stream loop can poll for the frontmost window.
+///
+/// The macOS implementation lives in `macos_window_info`; tests use
+/// `ScriptedWindowSource` so the loop can run without a Mac.
+pub trait WindowSource: Send + 'static {
+    /// Probe the currently active window.
+    fn active_window(&mut self) -> Result<ActiveWindow, MacOSError>;
+
+    /// Cheaper probe used after `active_window` fails. No fallback by default.
+    fn fallback_window(&mut self) -> Result<ActiveWindow, MacOSError> {
+        Err(MacOSError::NoActiveWindow)
+    }
+
+    /// Whether the source has nothing more to report, e.g. a replayed trace
+    /// that reached its end. Live sources never run out.
+    fn is_exhausted(&self) -> bool {
+        false
+    }
+}
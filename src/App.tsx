@@ -4,6 +4,8 @@ import { memo, useCallback, useEffect, useState } from "react";
 import { useShallow } from "zustand/react/shallow";
 
 import useAlertStore from "./stores/alertStore.tsx";
+import { useBackendErrors } from "./hooks/useBackendErrors.tsx";
+import { useTrackingStream } from "./hooks/useTrackingStream.tsx";
 import {
     hydrateSettings,
     useChartStore,
@@ -32,6 +34,8 @@ function MainScreen() {
     useEffect(() => {
         hydrateSettings();
     }, []);
+    useBackendErrors();
+    useTrackingStream();
 
     const { sessionLengthInSeconds, numberOfSessions, breakLengthInSeconds } = useTimerStore(
         useShallow(state => ({
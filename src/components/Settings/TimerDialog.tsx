@@ -19,6 +19,7 @@ import { convertFileSrc, invoke } from "@tauri-apps/api/core";
 import { appConfigDir } from "@tauri-apps/api/path";
 import { useTimerStore } from "../../stores/settingStore";
 import useAlertStore from "../../stores/alertStore";
+import { errorMessage } from "../../model/BackendError";
 
 // @ts-ignore
 import ColorThief from "colorthief";
@@ -318,7 +319,7 @@ export default function TimerDialog({ activeDialog, handleDialogChange }: TimerD
             showAlert({
                 type: "error",
                 title: "Unable to change background",
-                message: (error as string) || "An unknown error occurred.",
+                message: errorMessage(error),
             });
         }
     };
@@ -1,5 +1,6 @@
 import NumberFlow from "@number-flow/react";
-import { convertFileSrc } from "@tauri-apps/api/core";
+import { convertFileSrc, invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
 import { useChartStore, useTimerStore } from "../stores/settingStore";
 import { Play, Pause, TimerReset, Coffee, BookText, Shell } from "lucide-react";
 import { defaults } from "../constants";
@@ -9,11 +10,22 @@ import { useWindowTitleStream } from "../hooks/useWindowTitleStream";
 import { useShallow } from "zustand/react/shallow";
 import { TitleRanges } from "src/model/SessionHistory";
 import { Tooltip, TooltipContent, TooltipProvider, TooltipTrigger } from "@/components/ui/tooltip";
+import { errorMessage } from "../model/BackendError";
+import { EVENT_NAME, type LocusEvent, type Phase, type PomodoroSnapshot } from "../model/LocusEvents";
+import useAlertStore from "../stores/alertStore";
 
 type TimerStatus = "idle" | "running" | "paused" | "break" | "ended" | "completed";
 
 const MemoizedTimer = memo(Timer);
 
+const STATUS_OF_PHASE: Record<Phase, TimerStatus> = {
+    idle: "idle",
+    focus: "running",
+    shortBreak: "break",
+    paused: "paused",
+    finished: "completed",
+};
+
 function Timer({
     updateChart,
     adjustChart,
@@ -26,8 +38,6 @@ function Timer({
     const backgroundImagePath = useTimerStore(state => state.backgroundImagePath);
     const accentColor = useTimerStore(state => state.accentColor);
 
-    const { changeStreamStatus } = useWindowTitleStream();
-
     const { sessionLengthInSeconds, breakLengthInSeconds, numberOfSessions } = useTimerStore(
         useShallow(state => ({
             sessionLengthInSeconds: state.sessionLengthInSeconds,
@@ -37,6 +47,7 @@ function Timer({
     );
 
     const { activeWindow } = useWindowTitleStream();
+    const showAlert = useAlertStore(state => state.showAlert);
 
     const [time, setTime] = useState(sessionLengthInSeconds);
     const [timerStatus, setTimerStatus] = useState<TimerStatus>("idle");
@@ -65,36 +76,60 @@ function Timer({
         titleRangesRef.current = [];
     };
 
+    // The backend runs the timer, and the tracking with it
+    const runCommand = useCallback(
+        (command: string, args?: Record<string, unknown>) => {
+            invoke<PomodoroSnapshot>(command, args).catch(error => {
+                showAlert({ type: "error", title: "Timer problem", message: errorMessage(error) });
+            });
+        },
+        [showAlert]
+    );
+
     const handleReset = () => {
-        setTime(sessionLengthInSeconds);
-        setTimerStatus("idle");
-        setCurrentSession(0);
         setCompletedAllSessions(false);
-
         resetChart();
         resetRef();
-        changeStreamStatus("stopped");
+        runCommand("pomodoro_abort");
     };
 
     let iconColor = accentColor || defaults.accentColor;
     let { minutes, seconds } = convertSeconds(time);
 
-    const handleSessionCompletion = useCallback(() => {
-        setTimerStatus("break");
-        setTime(breakLengthInSeconds);
-        setCurrentSession(done => done + 1);
-    }, [breakLengthInSeconds]);
-
-    const handlBreakCompletion = useCallback(() => {
-        if (currentSession === numberOfSessions) {
-            setTimerStatus("completed");
+    const applySnapshot = useCallback((snapshot: PomodoroSnapshot) => {
+        setTimerStatus(STATUS_OF_PHASE[snapshot.phase]);
+        setTime(snapshot.remainingSeconds);
+        setCurrentSession(snapshot.completedSessions);
+        if (snapshot.phase === "finished") {
             setCompletedAllSessions(true);
-            changeStreamStatus("stopped");
-        } else {
-            setTimerStatus("running");
-            setTime(sessionLengthInSeconds);
         }
-    }, [currentSession, numberOfSessions, sessionLengthInSeconds, changeStreamStatus]);
+    }, []);
+
+    // Picks up a timer that was running before the window reloaded
+    useEffect(() => {
+        invoke<PomodoroSnapshot>("pomodoro_state")
+            .then(snapshot => {
+                if (snapshot.phase !== "idle") applySnapshot(snapshot);
+            })
+            .catch(() => {});
+    }, [applySnapshot]);
+
+    useEffect(() => {
+        const unlisten = listen<LocusEvent>(EVENT_NAME, event => {
+            const { payload } = event;
+            if (payload.type === "pomodoroTick") {
+                windowNameChange.current.currentTick += 1;
+                titleChange.current.currentTick += 1;
+                applySnapshot(payload.payload);
+            } else if (payload.type === "pomodoroPhaseChanged") {
+                applySnapshot(payload.payload.snapshot);
+            }
+        });
+
+        return () => {
+            unlisten.then(stop => stop());
+        };
+    }, [applySnapshot]);
 
     // change time when user chagnes setting time, only in "idle" state
     useEffect(() => {
@@ -111,34 +146,6 @@ function Timer({
         adjustChart(totalPomodoro, totalBreak);
     }, [sessionLengthInSeconds, breakLengthInSeconds, numberOfSessions, adjustChart]);
 
-    useEffect(() => {
-        let id = null;
-
-        if (timerStatus === "running" || timerStatus === "break") {
-            id = setInterval(() => {
-                windowNameChange.current.currentTick += 1;
-                titleChange.current.currentTick += 1;
-                setTime(time => {
-                    return time - 1;
-                });
-            }, 1000);
-        }
-
-        return () => {
-            id && clearInterval(id);
-        };
-    }, [timerStatus]);
-
-    useEffect(() => {
-        if (time <= 0) {
-            if (timerStatus === "running") {
-                handleSessionCompletion();
-            } else if (timerStatus === "break") {
-                handlBreakCompletion();
-            }
-        }
-    }, [timerStatus, time, handleSessionCompletion, handlBreakCompletion]);
-
     useEffect(() => {
         const ignoreTitles = ["none"].includes(activeWindow.windowName);
         const isSameWindow = windowNameRef.current.currentWindow === activeWindow.windowName;
@@ -257,8 +264,17 @@ function Timer({
                                 fill={iconColor}
                                 stroke={iconColor}
                                 onClick={() => {
-                                    setTimerStatus("running");
-                                    changeStreamStatus("streaming");
+                                    if (timerStatus === "paused") {
+                                        runCommand("pomodoro_resume");
+                                    } else {
+                                        runCommand("pomodoro_start", {
+                                            settings: {
+                                                sessionLengthInSeconds,
+                                                numberOfSessions,
+                                                breakLengthInSeconds,
+                                            },
+                                        });
+                                    }
                                 }}
                             />
                         ) : (
@@ -266,10 +282,7 @@ function Timer({
                                 className="h-4 w-4 cursor-pointer"
                                 fill={iconColor}
                                 stroke={iconColor}
-                                onClick={() => {
-                                    setTimerStatus("paused");
-                                    changeStreamStatus("stopped");
-                                }}
+                                onClick={() => runCommand("pomodoro_pause")}
                             />
                         )}
                     </div>
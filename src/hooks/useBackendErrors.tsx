@@ -0,0 +1,23 @@
+import { useEffect } from "react";
+import { listen } from "@tauri-apps/api/event";
+
+import { EVENT_NAME, type LocusEvent } from "../../src/model/LocusEvents";
+import useAlertStore from "../stores/alertStore";
+
+// Shows the backend's error events. Mount it once, at the top of the app, so
+// each error is alerted once however many components use the stream.
+export function useBackendErrors() {
+    const showAlert = useAlertStore(state => state.showAlert);
+
+    useEffect(() => {
+        const unlisten = listen<LocusEvent>(EVENT_NAME, event => {
+            if (event.payload.type !== "error") return;
+            const { message, retryable } = event.payload.payload;
+            showAlert({ type: retryable ? "warning" : "error", title: "Tracking problem", message });
+        });
+
+        return () => {
+            unlisten.then(stop => stop());
+        };
+    }, [showAlert]);
+}
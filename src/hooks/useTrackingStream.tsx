@@ -0,0 +1,31 @@
+import { useEffect } from "react";
+import { listen } from "@tauri-apps/api/event";
+
+import { EVENT_NAME, type LocusEvent } from "../../src/model/LocusEvents";
+import { ActiveWindow } from "../../src/model/PomodoroTypes";
+import useStreamStore from "../stores/streamStore";
+
+// Keeps the stream store in step with the backend: the window being tracked,
+// and whether tracking runs, which the pomodoro timer decides. Mount it once,
+// at the top of the app; components read it through `useWindowTitleStream`.
+export function useTrackingStream() {
+    const changeStreamStatus = useStreamStore(state => state.changeStreamStatus);
+    const setActiveWindow = useStreamStore(state => state.setActiveWindow);
+
+    useEffect(() => {
+        const unlisten = listen<LocusEvent>(EVENT_NAME, event => {
+            const { payload } = event;
+            if (payload.type === "windowChanged") {
+                setActiveWindow(ActiveWindow.fromEvent(payload.payload));
+            } else if (payload.type === "pomodoroPhaseChanged") {
+                const tracking = payload.payload.to === "focus" || payload.payload.to === "shortBreak";
+                changeStreamStatus(tracking ? "streaming" : "stopped");
+                if (!tracking) setActiveWindow(ActiveWindow.none());
+            }
+        });
+
+        return () => {
+            unlisten.then(stop => stop());
+        };
+    }, [changeStreamStatus, setActiveWindow]);
+}
@@ -0,0 +1,17 @@
+import type { ErrorInfo } from "./LocusEvents";
+
+// Commands reject with an `ErrorInfo`: { code, message, retryable }
+export function isErrorInfo(error: unknown): error is ErrorInfo {
+    return (
+        typeof error === "object" &&
+        error !== null &&
+        typeof (error as ErrorInfo).code === "string" &&
+        typeof (error as ErrorInfo).message === "string"
+    );
+}
+
+export function errorMessage(error: unknown): string {
+    if (isErrorInfo(error)) return error.message;
+    if (typeof error === "string" && error) return error;
+    return "An unknown error occurred.";
+}
@@ -0,0 +1,44 @@
+// Generated from the Rust event types by src-tauri/src/events/typescript.rs;
+// do not edit. Regenerate with `LOCUS_UPDATE_BINDINGS=1 cargo test bindings`.
+
+export const EVENT_SCHEMA_VERSION = 1;
+export const EVENT_NAME = "locus-event";
+
+export type ActiveWindow = { class: string, title: string, 
+/**
+ * The active tab's address, for browsers whose scripting dictionary
+ * exposes it.
+ */
+url?: string, 
+/**
+ * The title as the app reported it, when `title` has been normalized.
+ */
+rawTitle?: string, };
+
+export type AppEvent = { "type": "windowChanged", "payload": ActiveWindow } | { "type": "idleStarted", "payload": IdleEvent } | { "type": "idleEnded", "payload": IdleEvent } | { "type": "pomodoroPhaseChanged", "payload": PhaseChange } | { "type": "pomodoroTick", "payload": PomodoroSnapshot } | { "type": "ruleMatched", "payload": RuleMatch } | { "type": "error", "payload": ErrorInfo } | { "type": "permissionStatus", "payload": PermissionStatus };
+
+export type ErrorInfo = { code: string, message: string, retryable: boolean, };
+
+export type IdleEvent = { at: number, };
+
+export type Permission = "accessibility" | "automation" | "screenRecording";
+
+export type PermissionStatus = { permission: Permission, granted: boolean, target?: string, };
+
+export type Phase = "idle" | "focus" | "shortBreak" | "paused" | "finished";
+
+export type PhaseChange = { from: Phase, to: Phase, snapshot: PomodoroSnapshot, };
+
+export type PomodoroSettings = { sessionLengthInSeconds: number, numberOfSessions: number, breakLengthInSeconds: number, };
+
+export type PomodoroSnapshot = { phase: Phase, 
+/**
+ * The phase a paused timer resumes into.
+ */
+pausedPhase: Phase | null, remainingSeconds: number, completedSessions: number, settings: PomodoroSettings, sessionId: string | null, };
+
+export type Productivity = "productive" | "neutral" | "distracting";
+
+export type RuleMatch = { class: string, title: string, rule: string, category: string, productivity: Productivity, };
+
+export type LocusEvent = { version: typeof EVENT_SCHEMA_VERSION } & AppEvent;
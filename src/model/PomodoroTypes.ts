@@ -1,3 +1,5 @@
+import type { ActiveWindow as ActiveWindowPayload } from "./LocusEvents";
+
 export class ActiveWindow {
     title: string;
     windowName: string;
@@ -10,5 +12,9 @@ export class ActiveWindow {
     static none(): ActiveWindow {
         return new ActiveWindow("none", "none");
     }
+
+    static fromEvent(window: ActiveWindowPayload): ActiveWindow {
+        return new ActiveWindow(window.title, window.class);
+    }
 }
 
@@ -1,14 +1,19 @@
 import { create } from "zustand";
 
+import { ActiveWindow } from "../model/PomodoroTypes";
+
 type streamStatus = "idle" | "streaming" | "stopped";
 
 interface streamStoreType {
     streamStatus: streamStatus;
+    activeWindow: ActiveWindow;
     changeStreamStatus: (status?: streamStatus) => void;
+    setActiveWindow: (activeWindow: ActiveWindow) => void;
 }
 
 const useStreamStore = create<streamStoreType>()(set => ({
     streamStatus: "idle",
+    activeWindow: ActiveWindow.none(),
     changeStreamStatus: status => {
         if (status) {
             set({ streamStatus: status });
@@ -24,6 +29,13 @@ const useStreamStore = create<streamStoreType>()(set => ({
             }
         });
     },
+    setActiveWindow: next => {
+        set(state => {
+            const prev = state.activeWindow;
+            if (prev.title === next.title && prev.windowName === next.windowName) return state;
+            return { activeWindow: next };
+        });
+    },
 }));
 
 export default useStreamStore;